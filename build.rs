@@ -0,0 +1,9 @@
+use std::io::{Error, Result};
+
+fn main() -> Result<()> {
+    // Vendored `protoc`, so that building does not require a system installation
+    let mut config = tonic_prost_build::Config::new();
+    config.protoc_executable(protoc_bin_vendored::protoc_bin_path().map_err(Error::other)?);
+
+    tonic_prost_build::configure().compile_with_config(config, &["proto/book.proto"], &["proto"])
+}
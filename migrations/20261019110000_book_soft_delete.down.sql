@@ -0,0 +1,5 @@
+DELETE FROM book WHERE deleted_at IS NOT NULL;
+DROP INDEX IF EXISTS book_deleted_at_idx;
+DROP INDEX IF EXISTS book_title_author_key;
+ALTER TABLE book ADD CONSTRAINT book_title_author_key UNIQUE (title, author);
+ALTER TABLE book DROP COLUMN IF EXISTS deleted_at;
@@ -0,0 +1,7 @@
+-- Add up migration script here
+ALTER TABLE book ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ NULL;
+
+-- Deleted books must not prevent a new book with the same title and author
+ALTER TABLE book DROP CONSTRAINT IF EXISTS book_title_author_key;
+CREATE UNIQUE INDEX IF NOT EXISTS book_title_author_key ON book (title, author) WHERE deleted_at IS NULL;
+CREATE INDEX IF NOT EXISTS book_deleted_at_idx ON book (deleted_at) WHERE deleted_at IS NOT NULL;
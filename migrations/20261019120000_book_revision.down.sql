@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS book_revision;
+DROP FUNCTION IF EXISTS book_revision_immutable();
@@ -0,0 +1,29 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS book_revision (
+    book_id varchar(36) NOT NULL,
+    revision BIGINT NOT NULL,
+    operation varchar(16) NOT NULL,
+    title varchar(42) NOT NULL,
+    author varchar(42) NOT NULL,
+    actor varchar(255) NOT NULL,
+    request_id varchar(255) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (book_id, revision)
+);
+
+-- Revisions are immutable
+CREATE OR REPLACE FUNCTION book_revision_immutable() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'book revisions are immutable';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER book_revision_immutable
+    BEFORE UPDATE OR DELETE ON book_revision
+    FOR EACH ROW EXECUTE FUNCTION book_revision_immutable();
+
+-- Existing books start their history with a snapshot of their current state
+INSERT INTO book_revision (book_id, revision, operation, title, author, actor, request_id, created_at)
+SELECT id, version, 'snapshot', title, author, 'system', '', COALESCE(deleted_at, updated_at, created_at)
+FROM book
+ON CONFLICT DO NOTHING;
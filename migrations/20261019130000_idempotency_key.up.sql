@@ -0,0 +1,13 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS idempotency_key (
+    key varchar(255) NOT NULL,
+    fingerprint varchar(64) NOT NULL,
+    status_code SMALLINT NULL,
+    headers JSONB NULL,
+    body BYTEA NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (key)
+);
+
+CREATE INDEX IF NOT EXISTS idempotency_key_expires_at_idx ON idempotency_key (expires_at);
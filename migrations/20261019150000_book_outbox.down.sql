@@ -0,0 +1,3 @@
+DROP TRIGGER IF EXISTS book_outbox_insert ON book_revision;
+DROP FUNCTION IF EXISTS book_outbox_insert();
+DROP TABLE IF EXISTS book_outbox;
@@ -0,0 +1,27 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS book_outbox (
+    id BIGSERIAL NOT NULL,
+    book_id varchar(36) NOT NULL,
+    revision BIGINT NOT NULL,
+    -- Recorded revision, as sent to the consumers
+    payload JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    processed_at TIMESTAMPTZ NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX IF NOT EXISTS book_outbox_pending_idx ON book_outbox (id) WHERE processed_at IS NULL;
+CREATE INDEX IF NOT EXISTS book_outbox_processed_at_idx ON book_outbox (processed_at);
+
+-- Each recorded revision is a change event, written in the transaction of the book write
+CREATE OR REPLACE FUNCTION book_outbox_insert() RETURNS trigger AS $$
+BEGIN
+    INSERT INTO book_outbox (book_id, revision, payload, created_at)
+    VALUES (NEW.book_id, NEW.revision, to_jsonb(NEW), NEW.created_at);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER book_outbox_insert
+    AFTER INSERT ON book_revision
+    FOR EACH ROW EXECUTE FUNCTION book_outbox_insert();
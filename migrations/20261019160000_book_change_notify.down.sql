@@ -0,0 +1,7 @@
+CREATE OR REPLACE FUNCTION book_outbox_insert() RETURNS trigger AS $$
+BEGIN
+    INSERT INTO book_outbox (book_id, revision, payload, created_at)
+    VALUES (NEW.book_id, NEW.revision, to_jsonb(NEW), NEW.created_at);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
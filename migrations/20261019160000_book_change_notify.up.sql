@@ -0,0 +1,20 @@
+-- Add up migration script here
+-- Each change is also notified to the listening instances, when its transaction commits
+CREATE OR REPLACE FUNCTION book_outbox_insert() RETURNS trigger AS $$
+DECLARE
+    outbox_id BIGINT;
+BEGIN
+    INSERT INTO book_outbox (book_id, revision, payload, created_at)
+    VALUES (NEW.book_id, NEW.revision, to_jsonb(NEW), NEW.created_at)
+    RETURNING id INTO outbox_id;
+
+    PERFORM pg_notify('book_changes', json_build_object(
+        'id', outbox_id,
+        'book_id', NEW.book_id,
+        'revision', NEW.revision,
+        'operation', NEW.operation
+    )::text);
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
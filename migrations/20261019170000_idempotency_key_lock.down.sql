@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE idempotency_key
+    DROP COLUMN IF EXISTS locked_until,
+    DROP COLUMN IF EXISTS lock_id;
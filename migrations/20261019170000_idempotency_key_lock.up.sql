@@ -0,0 +1,4 @@
+-- Add up migration script here
+ALTER TABLE idempotency_key
+    ADD COLUMN IF NOT EXISTS lock_id varchar(36) NULL,
+    ADD COLUMN IF NOT EXISTS locked_until TIMESTAMPTZ NULL;
@@ -0,0 +1,407 @@
+//! In-process feed of the book changes
+
+use crate::models::revision::BookRevision;
+use async_stream::stream;
+use futures::Stream;
+use serde::Serialize;
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{Arc, Mutex},
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
+
+/// Capacity of the channel to the subscribers, which catch up from the buffer when they lag behind
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// Number of published revisions remembered to skip them if they are published again
+const PUBLISHED_REVISIONS_CAPACITY: usize = 4096;
+
+/// Change of a book, identified by a monotonically increasing ID.
+///
+/// The IDs are only meaningful within the bus which published the event: they are given to the
+/// clients with the epoch of the bus (see `EventBus::event_id`).
+#[derive(Serialize, Debug, Clone)]
+pub struct BookEvent {
+    #[serde(skip)]
+    pub id: u64,
+    #[serde(flatten)]
+    pub revision: BookRevision,
+}
+
+/// Message sent to a subscriber
+#[derive(Debug, Clone)]
+pub enum BookEventMessage {
+    Event(Arc<BookEvent>),
+    /// Some events are no longer buffered and have been missed, the subscriber must reload the books.
+    ///
+    /// Holds the ID of the last published event, from which the subscriber can resume.
+    Resync(u64),
+}
+
+/// Publishes the book changes to the subscribers.
+///
+/// The last `buffer_size` events are kept to let the subscribers resume after a disconnection.
+/// Each bus has a random epoch, so that the IDs of the events published by another process
+/// (before a restart or by another instance) are not mistaken for its own.
+#[derive(Clone)]
+pub struct EventBus {
+    inner: Arc<EventBusInner>,
+}
+
+struct EventBusInner {
+    epoch: String,
+    sender: broadcast::Sender<BookEventMessage>,
+    buffer: Mutex<EventBuffer>,
+    buffer_size: usize,
+}
+
+struct EventBuffer {
+    events: VecDeque<Arc<BookEvent>>,
+    last_id: u64,
+    /// Book ID and revision number of the last published revisions, oldest first
+    published: VecDeque<(String, i64)>,
+    published_keys: HashSet<(String, i64)>,
+}
+
+impl EventBus {
+    pub fn new(buffer_size: usize) -> Self {
+        let (sender, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+
+        Self {
+            inner: Arc::new(EventBusInner {
+                epoch: Uuid::new_v4().simple().to_string()[..8].to_owned(),
+                sender,
+                buffer: Mutex::new(EventBuffer {
+                    events: VecDeque::with_capacity(buffer_size),
+                    last_id: 0,
+                    published: VecDeque::with_capacity(PUBLISHED_REVISIONS_CAPACITY),
+                    published_keys: HashSet::with_capacity(PUBLISHED_REVISIONS_CAPACITY),
+                }),
+                buffer_size,
+            }),
+        }
+    }
+
+    /// Publish committed changes, in order.
+    ///
+    /// A change is published by the instance which made it, and again by the notifications
+    /// listener: the revisions which have been published recently are skipped.
+    pub fn publish(&self, revisions: impl IntoIterator<Item = BookRevision>) {
+        let mut buffer = self
+            .inner
+            .buffer
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        for revision in revisions {
+            if !buffer.remember(&revision) {
+                continue;
+            }
+
+            buffer.last_id += 1;
+            let event = Arc::new(BookEvent {
+                id: buffer.last_id,
+                revision,
+            });
+
+            if self.inner.buffer_size > 0 {
+                if buffer.events.len() == self.inner.buffer_size {
+                    buffer.events.pop_front();
+                }
+                buffer.events.push_back(event.clone());
+            }
+
+            // Sending only fails without subscriber
+            let _ = self.inner.sender.send(BookEventMessage::Event(event));
+        }
+    }
+
+    /// Ask all the subscribers to resync, when changes may have been missed by the publisher.
+    ///
+    /// The buffered events are dropped, so that the subscribers which resume from an earlier
+    /// event are asked to resync as well.
+    pub fn resync(&self) {
+        let mut buffer = self
+            .inner
+            .buffer
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        buffer.events.clear();
+
+        // Sending only fails without subscriber
+        let _ = self
+            .inner
+            .sender
+            .send(BookEventMessage::Resync(buffer.last_id));
+    }
+
+    /// ID of an event for the clients, made of the epoch of the bus and of the event ID (e.g. `1a2b3c4d-42`)
+    pub fn event_id(&self, id: u64) -> String {
+        format!("{}-{id}", self.inner.epoch)
+    }
+
+    /// Subscribe to the changes following the event ID of a client, `None` if the ID is malformed.
+    ///
+    /// The events of another epoch, or of IDs without epoch, cannot be resumed from: the
+    /// subscriber is asked to resync.
+    pub fn resume(
+        &self,
+        last_event_id: &str,
+    ) -> Option<impl Stream<Item = BookEventMessage> + Send + 'static> {
+        let last_event_id = last_event_id.trim();
+        let (epoch, id) = last_event_id
+            .rsplit_once('-')
+            .unwrap_or(("", last_event_id));
+        let id = id.parse::<u64>().ok()?;
+
+        // No event is ever published with the maximum ID
+        Some(self.subscribe(Some(match epoch == self.inner.epoch {
+            true => id,
+            false => u64::MAX,
+        })))
+    }
+
+    /// Subscribe to the changes published from now on, or since the `last_id` event if set
+    pub fn subscribe(
+        &self,
+        last_id: Option<u64>,
+    ) -> impl Stream<Item = BookEventMessage> + Send + 'static {
+        // The receiver is created with the buffer locked, so that no event is missed in between
+        let (mut receiver, (mut pending, mut last_id)) = {
+            let buffer = self
+                .inner
+                .buffer
+                .lock()
+                .unwrap_or_else(|err| err.into_inner());
+            (
+                self.inner.sender.subscribe(),
+                buffer.replay(last_id.unwrap_or(buffer.last_id)),
+            )
+        };
+        let bus = self.clone();
+
+        stream! {
+            loop {
+                for message in pending.drain(..) {
+                    yield message;
+                }
+
+                match receiver.recv().await {
+                    Ok(BookEventMessage::Event(event)) if event.id <= last_id => {}
+                    Ok(BookEventMessage::Event(event)) => {
+                        last_id = event.id;
+                        yield BookEventMessage::Event(event);
+                    }
+                    Ok(BookEventMessage::Resync(id)) => {
+                        last_id = id;
+                        yield BookEventMessage::Resync(id);
+                    }
+                    Err(RecvError::Lagged(_)) => {
+                        let buffer = bus.inner.buffer.lock().unwrap_or_else(|err| err.into_inner());
+                        (pending, last_id) = buffer.replay(last_id);
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }
+    }
+}
+
+impl EventBuffer {
+    /// Remember a published revision, and return whether it is new
+    fn remember(&mut self, revision: &BookRevision) -> bool {
+        let key = (revision.book_id.clone(), revision.revision);
+        if !self.published_keys.insert(key.clone()) {
+            return false;
+        }
+
+        if self.published.len() == PUBLISHED_REVISIONS_CAPACITY
+            && let Some(oldest) = self.published.pop_front()
+        {
+            self.published_keys.remove(&oldest);
+        }
+        self.published.push_back(key);
+
+        true
+    }
+
+    /// Messages to send to a subscriber which received the events up to `last_id`,
+    /// and the ID of the last published event.
+    ///
+    /// A subscriber which missed events that are no longer buffered is asked to resync.
+    fn replay(&self, last_id: u64) -> (Vec<BookEventMessage>, u64) {
+        let first_id = self
+            .events
+            .front()
+            .map(|event| event.id)
+            .unwrap_or(self.last_id + 1);
+
+        let messages = match (first_id - 1..=self.last_id).contains(&last_id) {
+            true => self
+                .events
+                .iter()
+                .filter(|event| event.id > last_id)
+                .cloned()
+                .map(BookEventMessage::Event)
+                .collect(),
+            false => vec![BookEventMessage::Resync(self.last_id)],
+        };
+
+        (messages, self.last_id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::revision::RevisionOperation;
+    use chrono::Utc;
+    use futures::{StreamExt, pin_mut};
+
+    fn revision(book_id: &str) -> BookRevision {
+        revision_number(book_id, 1)
+    }
+
+    fn revision_number(book_id: &str, revision: i64) -> BookRevision {
+        BookRevision {
+            book_id: book_id.to_owned(),
+            revision,
+            operation: RevisionOperation::Create,
+            title: String::from("foo"),
+            author: String::from("bar"),
+            actor: String::from("anonymous"),
+            request_id: String::from("request"),
+            created_at: Utc::now(),
+        }
+    }
+
+    async fn next_ids(stream: impl Stream<Item = BookEventMessage>, count: usize) -> Vec<u64> {
+        stream
+            .take(count)
+            .map(|message| match message {
+                BookEventMessage::Event(event) => event.id,
+                BookEventMessage::Resync(id) => panic!("unexpected resync at {id}"),
+            })
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_live_events() {
+        let bus = EventBus::new(10);
+        bus.publish([revision("a")]);
+
+        let stream = bus.subscribe(None);
+        bus.publish([revision("b"), revision("c")]);
+
+        assert_eq!(next_ids(stream, 2).await, [2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_resume_from_last_id() {
+        let bus = EventBus::new(10);
+        bus.publish([revision("a"), revision("b"), revision("c")]);
+
+        let stream = bus.subscribe(Some(1));
+        bus.publish([revision("d")]);
+
+        assert_eq!(next_ids(stream, 3).await, [2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_resync_when_not_buffered() {
+        let bus = EventBus::new(2);
+        bus.publish([revision("a"), revision("b"), revision("c")]);
+
+        let stream = bus.subscribe(Some(0));
+        pin_mut!(stream);
+        assert!(matches!(
+            stream.next().await,
+            Some(BookEventMessage::Resync(3))
+        ));
+
+        bus.publish([revision("d")]);
+        assert_eq!(next_ids(stream, 1).await, [4]);
+
+        // An ID which has never been published is not valid either
+        let stream = bus.subscribe(Some(10));
+        pin_mut!(stream);
+        assert!(matches!(
+            stream.next().await,
+            Some(BookEventMessage::Resync(4))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_resync() {
+        let bus = EventBus::new(10);
+        bus.publish([revision("a"), revision("b")]);
+
+        let stream = bus.subscribe(None);
+        pin_mut!(stream);
+        bus.resync();
+        assert!(matches!(
+            stream.next().await,
+            Some(BookEventMessage::Resync(2))
+        ));
+
+        bus.publish([revision("c")]);
+        assert_eq!(next_ids(&mut stream, 1).await, [3]);
+
+        // The events published before the resync cannot be resumed from
+        let stream = bus.subscribe(Some(1));
+        pin_mut!(stream);
+        assert!(matches!(
+            stream.next().await,
+            Some(BookEventMessage::Resync(3))
+        ));
+        assert_eq!(next_ids(bus.subscribe(Some(2)), 1).await, [3]);
+    }
+
+    #[tokio::test]
+    async fn test_resume_from_event_id() {
+        let bus = EventBus::new(10);
+        bus.publish([revision("a"), revision("b")]);
+
+        let event_id = bus.event_id(1);
+        assert!(event_id.ends_with("-1"));
+        assert_eq!(next_ids(bus.resume(&event_id).unwrap(), 1).await, [2]);
+
+        // The IDs of another epoch, or without epoch, are resynced
+        for event_id in ["00000000-1", "1"] {
+            let stream = bus.resume(event_id).unwrap();
+            pin_mut!(stream);
+            assert!(matches!(
+                stream.next().await,
+                Some(BookEventMessage::Resync(2))
+            ));
+        }
+
+        assert!(bus.resume("foo").is_none());
+        assert!(bus.resume(&format!("{}-foo", bus.inner.epoch)).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_lagging_catches_up_from_buffer() {
+        let bus = EventBus::new(EVENT_CHANNEL_CAPACITY * 2);
+        let stream = bus.subscribe(None);
+        bus.publish((0..EVENT_CHANNEL_CAPACITY + 10).map(|i| revision_number("a", i as i64)));
+
+        let ids = next_ids(stream, EVENT_CHANNEL_CAPACITY + 10).await;
+        assert_eq!(
+            ids,
+            (1..=(EVENT_CHANNEL_CAPACITY + 10) as u64).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_publish_skips_published_revisions() {
+        let bus = EventBus::new(10);
+        let stream = bus.subscribe(None);
+        bus.publish([revision("a"), revision_number("a", 2)]);
+        bus.publish([revision("b"), revision_number("a", 2), revision("a")]);
+
+        assert_eq!(next_ids(stream, 3).await, [1, 2, 3]);
+        assert_eq!(bus.inner.buffer.lock().unwrap().last_id, 3);
+    }
+}
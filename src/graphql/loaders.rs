@@ -0,0 +1,28 @@
+use crate::{
+    models::revision::BookRevision, repositories::revision::BookRevisionRepository, types::AppError,
+};
+use async_graphql::dataloader::Loader;
+use sqlx::PgPool;
+use std::{collections::HashMap, sync::Arc};
+
+/// Load the revisions of several books in a single query
+pub struct RevisionLoader {
+    pub pool: PgPool,
+}
+
+impl Loader<String> for RevisionLoader {
+    type Value = Vec<BookRevision>;
+    type Error = Arc<AppError>;
+
+    async fn load(&self, keys: &[String]) -> Result<HashMap<String, Self::Value>, Self::Error> {
+        let mut revisions: HashMap<String, Vec<BookRevision>> = HashMap::new();
+        for revision in BookRevisionRepository::get_all_by_book_ids(&self.pool, keys).await? {
+            revisions
+                .entry(revision.book_id.clone())
+                .or_default()
+                .push(revision);
+        }
+
+        Ok(revisions)
+    }
+}
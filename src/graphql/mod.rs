@@ -0,0 +1,53 @@
+//! GraphQL API on the books
+
+mod loaders;
+mod mutation;
+mod query;
+mod types;
+
+pub use loaders::RevisionLoader;
+
+use crate::{
+    app_error,
+    config::Config,
+    types::{AppError, AppErrorCode, AppResult},
+};
+use async_graphql::{EmptySubscription, ErrorExtensions, Schema};
+use mutation::MutationRoot;
+use query::QueryRoot;
+use uuid::Uuid;
+
+/// GraphQL schema of the API
+pub type BookSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
+
+/// Build the GraphQL schema with the depth and complexity limits of the configuration
+pub fn schema(settings: &Config) -> BookSchema {
+    let mut builder = Schema::build(QueryRoot, MutationRoot, EmptySubscription);
+
+    if settings.graphql_max_depth > 0 {
+        builder = builder.limit_depth(settings.graphql_max_depth);
+    }
+    if settings.graphql_max_complexity > 0 {
+        builder = builder.limit_complexity(settings.graphql_max_complexity);
+    }
+
+    builder.finish()
+}
+
+/// Errors are returned with the matching HTTP status in the `code` extension
+impl ErrorExtensions for AppError {
+    fn extend(&self) -> async_graphql::Error {
+        async_graphql::Error::new(self.to_string())
+            .extend_with(|_, extensions| extensions.set("code", self.status_code().as_u16()))
+    }
+}
+
+/// Parse a book ID argument
+fn parse_id(id: &async_graphql::ID) -> AppResult<Uuid> {
+    Uuid::parse_str(id).map_err(|err| {
+        app_error!(
+            AppErrorCode::BadRequest,
+            format!("invalid book id `{}`: {err}", id.as_str())
+        )
+    })
+}
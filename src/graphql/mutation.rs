@@ -0,0 +1,151 @@
+use super::{
+    parse_id,
+    types::{Book, BookInput},
+};
+use crate::{
+    app_error,
+    config::Config,
+    events::EventBus,
+    handlers::book::{
+        batch_operation_versions, execute_batch_operation, restore_book, revert_book,
+    },
+    models::{
+        book::{BookBatchOperation, PartialBook},
+        revision::ChangeContext,
+    },
+    types::{AppError, AppErrorCode, AppResult},
+};
+use async_graphql::{Context, ID, Object, ResultExt};
+use sqlx::PgPool;
+
+/// Book writes, with the same rules as the REST API.
+///
+/// `version` plays the role of the `If-Match` header.
+pub struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    /// Add a new book
+    async fn create_book(
+        &self,
+        ctx: &Context<'_>,
+        input: BookInput,
+    ) -> async_graphql::Result<Book> {
+        execute(ctx, BookBatchOperation::Create { data: input.into() })
+            .await
+            .extend()
+    }
+
+    /// Replace the title and the author of a book
+    async fn update_book(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        input: BookInput,
+        version: Option<i64>,
+    ) -> async_graphql::Result<Book> {
+        let id = parse_id(&id).extend()?;
+        execute(
+            ctx,
+            BookBatchOperation::Update {
+                id,
+                data: input.into(),
+                version,
+            },
+        )
+        .await
+        .extend()
+    }
+
+    /// Move a book to the trash
+    async fn delete_book(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        version: Option<i64>,
+    ) -> async_graphql::Result<bool> {
+        let id = parse_id(&id).extend()?;
+        let (settings, pool, context) = write_context(ctx);
+        let mut connection = pool.acquire().await.map_err(AppError::from).extend()?;
+
+        let (_, _, revision) = execute_batch_operation(
+            &mut connection,
+            BookBatchOperation::Delete { id, version },
+            settings,
+            context,
+        )
+        .await
+        .extend()?;
+        ctx.data_unchecked::<EventBus>().publish([revision]);
+
+        Ok(true)
+    }
+
+    /// Restore a book from the trash
+    async fn restore_book(&self, ctx: &Context<'_>, id: ID) -> async_graphql::Result<Book> {
+        let id = parse_id(&id).extend()?;
+        let (_, pool, context) = write_context(ctx);
+        let mut connection = pool.acquire().await.map_err(AppError::from).extend()?;
+
+        let (book, revision) = restore_book(&mut connection, id.to_string(), context)
+            .await
+            .extend()?;
+        ctx.data_unchecked::<EventBus>().publish([revision]);
+
+        Ok(Book(PartialBook::from(book)))
+    }
+
+    /// Bring a book back to the state of one of its revisions
+    async fn revert_book(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        revision: i64,
+        version: Option<i64>,
+    ) -> async_graphql::Result<Book> {
+        let id = parse_id(&id).extend()?;
+        let (settings, pool, context) = write_context(ctx);
+        let versions = batch_operation_versions(version, settings).extend()?;
+        let mut connection = pool.acquire().await.map_err(AppError::from).extend()?;
+
+        let (book, revision) = revert_book(
+            &mut connection,
+            id.to_string(),
+            revision,
+            versions.as_deref(),
+            context,
+        )
+        .await
+        .extend()?;
+        ctx.data_unchecked::<EventBus>().publish([revision]);
+
+        Ok(Book(PartialBook::from(book)))
+    }
+}
+
+/// Data shared by the writes of a request
+fn write_context<'a>(ctx: &Context<'a>) -> (&'a Config, &'a PgPool, &'a ChangeContext) {
+    (
+        ctx.data_unchecked::<Config>(),
+        ctx.data_unchecked::<PgPool>(),
+        ctx.data_unchecked::<ChangeContext>(),
+    )
+}
+
+/// Execute a write which returns the book
+async fn execute(ctx: &Context<'_>, operation: BookBatchOperation) -> AppResult<Book> {
+    let (settings, pool, context) = write_context(ctx);
+    let mut connection = pool.acquire().await?;
+
+    let (_, book, revision) =
+        execute_batch_operation(&mut connection, operation, settings, context).await?;
+    ctx.data_unchecked::<EventBus>().publish([revision]);
+
+    match book {
+        Some(book) => Ok(Book(PartialBook::from(book))),
+        None => Err(app_error!(
+            AppErrorCode::NotFound,
+            "book could not be found"
+        )),
+    }
+}
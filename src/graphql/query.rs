@@ -0,0 +1,73 @@
+use super::{
+    parse_id,
+    types::{BOOK_FIELD_COLUMNS, Book, BookFilterInput, BookPage},
+};
+use crate::{
+    models::book::{BookFilter, PartialBook},
+    repositories::book::BookRepository,
+    utils::query::{PAGINATION_MAX_LIMIT, PaginateSort, PaginateSortQuery},
+};
+use async_graphql::{Context, ID, Object, ResultExt};
+use sqlx::PgPool;
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Book by its ID, `null` if it does not exist or has been deleted
+    async fn book(&self, ctx: &Context<'_>, id: ID) -> async_graphql::Result<Option<Book>> {
+        let id = parse_id(&id).extend()?;
+        let book = BookRepository::get_by_id(ctx.data_unchecked::<PgPool>(), id.to_string())
+            .await
+            .extend()?;
+
+        Ok(book.map(|book| Book(PartialBook::from(book))))
+    }
+
+    /// Page of active books.
+    ///
+    /// `sort` uses the syntax of the REST API (e.g. `+title,-created_at`).
+    #[graphql(complexity = "page_complexity(limit, child_complexity)")]
+    async fn books(
+        &self,
+        ctx: &Context<'_>,
+        page: Option<u32>,
+        limit: Option<u32>,
+        sort: Option<String>,
+        filter: Option<BookFilterInput>,
+    ) -> async_graphql::Result<BookPage> {
+        let paginate_sort = PaginateSort::from(PaginateSortQuery { page, limit, sort });
+        let filter = BookFilter::from(filter.unwrap_or_default());
+
+        // Only the columns of the selected fields are read, the ID is needed to load the revisions
+        let selection = ctx.look_ahead().field("data");
+        let fields = BOOK_FIELD_COLUMNS
+            .iter()
+            .filter(|(field, column)| *column == "id" || selection.field(field).exists())
+            .map(|(_, column)| *column)
+            .collect::<Vec<_>>();
+
+        let books = BookRepository::get_all(
+            ctx.data_unchecked::<PgPool>(),
+            &paginate_sort,
+            &fields,
+            &filter,
+        )
+        .await
+        .extend()?;
+
+        Ok(BookPage {
+            data: books.data.into_iter().map(Book).collect(),
+            total: books.total,
+        })
+    }
+}
+
+/// Complexity of a page of books, the complexity of a book times the page size
+fn page_complexity(limit: Option<u32>, child_complexity: usize) -> usize {
+    let limit = limit
+        .filter(|limit| (1..=PAGINATION_MAX_LIMIT).contains(limit))
+        .unwrap_or(PAGINATION_MAX_LIMIT);
+
+    limit as usize * child_complexity
+}
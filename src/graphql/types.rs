@@ -0,0 +1,157 @@
+use super::RevisionLoader;
+use crate::{
+    models::{
+        book::{BookCreation, BookFilter, PartialBook},
+        revision::{self, BookRevision},
+    },
+    types::AppError,
+};
+use async_graphql::{
+    Context, Enum, ErrorExtensions, ID, InputObject, Object, SimpleObject, dataloader::DataLoader,
+};
+use chrono::{DateTime, Utc};
+
+/// Book columns loaded for each GraphQL field of a book
+pub const BOOK_FIELD_COLUMNS: &[(&str, &str)] = &[
+    ("id", "id"),
+    ("title", "title"),
+    ("author", "author"),
+    ("createdAt", "created_at"),
+    ("updatedAt", "updated_at"),
+    ("version", "version"),
+    ("deletedAt", "deleted_at"),
+];
+
+/// Book restricted to the columns selected by the query
+pub struct Book(pub PartialBook);
+
+#[Object]
+impl Book {
+    async fn id(&self) -> ID {
+        ID(self.0.id.clone().unwrap_or_default())
+    }
+
+    async fn title(&self) -> &str {
+        self.0.title.as_deref().unwrap_or_default()
+    }
+
+    async fn author(&self) -> &str {
+        self.0.author.as_deref().unwrap_or_default()
+    }
+
+    async fn created_at(&self) -> DateTime<Utc> {
+        self.0.created_at.unwrap_or_default()
+    }
+
+    async fn updated_at(&self) -> Option<DateTime<Utc>> {
+        self.0.updated_at.flatten()
+    }
+
+    async fn version(&self) -> i64 {
+        self.0.version.unwrap_or_default()
+    }
+
+    async fn deleted_at(&self) -> Option<DateTime<Utc>> {
+        self.0.deleted_at.flatten()
+    }
+
+    /// Revisions of the book, oldest first, loaded in one query for all the books of a response
+    async fn revisions(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Revision>> {
+        let revisions = ctx
+            .data_unchecked::<DataLoader<RevisionLoader>>()
+            .load_one(self.0.id.clone().unwrap_or_default())
+            .await
+            .map_err(|err: std::sync::Arc<AppError>| err.as_ref().extend())?;
+
+        Ok(revisions
+            .unwrap_or_default()
+            .into_iter()
+            .map(Revision)
+            .collect())
+    }
+}
+
+/// Operation which produced a revision
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+#[graphql(remote = "revision::RevisionOperation")]
+pub enum RevisionOperation {
+    Snapshot,
+    Create,
+    Update,
+    Delete,
+    Restore,
+    Revert,
+}
+
+/// Immutable state of a book after a change
+pub struct Revision(pub BookRevision);
+
+#[Object]
+impl Revision {
+    async fn revision(&self) -> i64 {
+        self.0.revision
+    }
+
+    async fn operation(&self) -> RevisionOperation {
+        self.0.operation.into()
+    }
+
+    async fn title(&self) -> &str {
+        &self.0.title
+    }
+
+    async fn author(&self) -> &str {
+        &self.0.author
+    }
+
+    async fn actor(&self) -> &str {
+        &self.0.actor
+    }
+
+    async fn request_id(&self) -> &str {
+        &self.0.request_id
+    }
+
+    async fn created_at(&self) -> DateTime<Utc> {
+        self.0.created_at
+    }
+}
+
+/// Page of books
+#[derive(SimpleObject)]
+pub struct BookPage {
+    pub data: Vec<Book>,
+    pub total: i64,
+}
+
+/// Book criteria, matched case-insensitively on a part of the field
+#[derive(InputObject, Default)]
+pub struct BookFilterInput {
+    pub title: Option<String>,
+    pub author: Option<String>,
+}
+
+impl From<BookFilterInput> for BookFilter {
+    fn from(filter: BookFilterInput) -> Self {
+        Self {
+            title: filter.title,
+            author: filter.author,
+        }
+    }
+}
+
+/// Book creation or replacement
+#[derive(InputObject)]
+pub struct BookInput {
+    pub title: String,
+    pub author: String,
+}
+
+impl From<BookInput> for BookCreation {
+    fn from(input: BookInput) -> Self {
+        Self {
+            title: input.title,
+            author: input.author,
+        }
+    }
+}
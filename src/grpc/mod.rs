@@ -0,0 +1,50 @@
+//! gRPC API on the books
+
+mod service;
+
+/// Types and stubs generated from `proto/book.proto`
+pub mod proto {
+    tonic::include_proto!("book.v1");
+}
+
+pub use service::BookGrpcService;
+
+use crate::{
+    config::Config,
+    events::EventBus,
+    models::book::{Book, PartialBook},
+};
+use proto::book_service_server::BookServiceServer;
+use sqlx::PgPool;
+use std::time::SystemTime;
+
+/// Build the gRPC book service
+pub fn service(
+    pool: PgPool,
+    settings: &Config,
+    events: EventBus,
+) -> BookServiceServer<BookGrpcService> {
+    BookServiceServer::new(BookGrpcService::new(pool, settings.clone(), events))
+}
+
+impl From<PartialBook> for proto::Book {
+    fn from(book: PartialBook) -> Self {
+        Self {
+            id: book.id.unwrap_or_default(),
+            title: book.title.unwrap_or_default(),
+            author: book.author.unwrap_or_default(),
+            created_at: book.created_at.map(|date| SystemTime::from(date).into()),
+            updated_at: book
+                .updated_at
+                .flatten()
+                .map(|date| SystemTime::from(date).into()),
+            version: book.version.unwrap_or_default(),
+        }
+    }
+}
+
+impl From<Book> for proto::Book {
+    fn from(book: Book) -> Self {
+        PartialBook::from(book).into()
+    }
+}
@@ -0,0 +1,194 @@
+use super::proto::{
+    self, CreateBookRequest, DeleteBookRequest, GetBookRequest, ListAllBooksRequest,
+    ListBooksRequest, ListBooksResponse, UpdateBookRequest, book_service_server::BookService,
+};
+use crate::{
+    app_error,
+    config::Config,
+    events::EventBus,
+    handlers::book::execute_batch_operation,
+    models::{
+        book::{BOOK_FIELDS, BookBatchOperation, BookCreation, BookFilter},
+        revision::ChangeContext,
+    },
+    repositories::book::BookRepository,
+    types::{AppError, AppErrorCode, AppResult},
+    utils::query::{PaginateSort, PaginateSortQuery},
+};
+use futures::{StreamExt, TryStreamExt, stream::BoxStream};
+use sqlx::PgPool;
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+
+/// gRPC book service, with the same rules as the REST API
+pub struct BookGrpcService {
+    pool: PgPool,
+    settings: Config,
+    events: EventBus,
+}
+
+impl BookGrpcService {
+    pub fn new(pool: PgPool, settings: Config, events: EventBus) -> Self {
+        Self {
+            pool,
+            settings,
+            events,
+        }
+    }
+
+    /// Execute a write which returns the book
+    async fn execute(
+        &self,
+        operation: BookBatchOperation,
+        context: &ChangeContext,
+    ) -> AppResult<proto::Book> {
+        let mut connection = self.pool.acquire().await?;
+
+        let (_, book, revision) =
+            execute_batch_operation(&mut connection, operation, &self.settings, context).await?;
+        self.events.publish([revision]);
+
+        match book {
+            Some(book) => Ok(book.into()),
+            None => Err(app_error!(
+                AppErrorCode::NotFound,
+                "book could not be found"
+            )),
+        }
+    }
+}
+
+/// Who is making a change, from the `x-actor` and `x-request-id` metadata
+fn change_context<T>(request: &Request<T>) -> AppResult<ChangeContext> {
+    let actor = request
+        .metadata()
+        .get("x-actor")
+        .map(|actor| actor.as_bytes());
+    let request_id = request
+        .metadata()
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+        .map(str::to_owned);
+
+    Ok(ChangeContext {
+        actor: ChangeContext::parse_actor(actor)?,
+        request_id: request_id.unwrap_or_else(|| Uuid::new_v4().to_string()),
+    })
+}
+
+/// Parse a book ID field
+fn parse_id(id: &str) -> AppResult<Uuid> {
+    Uuid::parse_str(id).map_err(|err| {
+        app_error!(
+            AppErrorCode::BadRequest,
+            format!("invalid book id `{id}`: {err}")
+        )
+    })
+}
+
+#[tonic::async_trait]
+impl BookService for BookGrpcService {
+    type ListAllStream = BoxStream<'static, Result<proto::Book, Status>>;
+
+    #[instrument(skip(self))]
+    async fn create(
+        &self,
+        request: Request<CreateBookRequest>,
+    ) -> Result<Response<proto::Book>, Status> {
+        let context = change_context(&request)?;
+        let request = request.into_inner();
+        let operation = BookBatchOperation::Create {
+            data: BookCreation {
+                title: request.title,
+                author: request.author,
+            },
+        };
+
+        Ok(Response::new(self.execute(operation, &context).await?))
+    }
+
+    #[instrument(skip(self))]
+    async fn get(&self, request: Request<GetBookRequest>) -> Result<Response<proto::Book>, Status> {
+        let id = parse_id(&request.into_inner().id)?;
+
+        match BookRepository::get_by_id(&self.pool, id.to_string()).await? {
+            Some(book) => Ok(Response::new(book.into())),
+            _ => Err(app_error!(AppErrorCode::NotFound, "book could not be found").into()),
+        }
+    }
+
+    #[instrument(skip(self))]
+    async fn list(
+        &self,
+        request: Request<ListBooksRequest>,
+    ) -> Result<Response<ListBooksResponse>, Status> {
+        let request = request.into_inner();
+        let paginate_sort = PaginateSort::from(PaginateSortQuery {
+            page: request.page,
+            limit: request.limit,
+            sort: request.sort,
+        });
+        let books = BookRepository::get_all(
+            &self.pool,
+            &paginate_sort,
+            BOOK_FIELDS,
+            &BookFilter::default(),
+        )
+        .await?;
+
+        Ok(Response::new(ListBooksResponse {
+            books: books.data.into_iter().map(proto::Book::from).collect(),
+            total: books.total,
+        }))
+    }
+
+    #[instrument(skip(self))]
+    async fn update(
+        &self,
+        request: Request<UpdateBookRequest>,
+    ) -> Result<Response<proto::Book>, Status> {
+        let context = change_context(&request)?;
+        let request = request.into_inner();
+        let operation = BookBatchOperation::Update {
+            id: parse_id(&request.id)?,
+            data: BookCreation {
+                title: request.title,
+                author: request.author,
+            },
+            version: request.version,
+        };
+
+        Ok(Response::new(self.execute(operation, &context).await?))
+    }
+
+    #[instrument(skip(self))]
+    async fn delete(&self, request: Request<DeleteBookRequest>) -> Result<Response<()>, Status> {
+        let context = change_context(&request)?;
+        let request = request.into_inner();
+        let operation = BookBatchOperation::Delete {
+            id: parse_id(&request.id)?,
+            version: request.version,
+        };
+
+        let mut connection = self.pool.acquire().await.map_err(AppError::from)?;
+        let (_, _, revision) =
+            execute_batch_operation(&mut connection, operation, &self.settings, &context).await?;
+        self.events.publish([revision]);
+
+        Ok(Response::new(()))
+    }
+
+    #[instrument(skip(self))]
+    async fn list_all(
+        &self,
+        _request: Request<ListAllBooksRequest>,
+    ) -> Result<Response<Self::ListAllStream>, Status> {
+        let books = BookRepository::stream_all(self.pool.clone())
+            .map_ok(proto::Book::from)
+            .map_err(Status::from)
+            .boxed();
+
+        Ok(Response::new(books))
+    }
+}
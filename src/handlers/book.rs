@@ -1,59 +1,415 @@
 use crate::{
     app_error,
-    models::book::{Book, BookCreation},
-    repositories::book::BookRepository,
-    types::{AppError, AppErrorCode, AppResult},
+    config::Config,
+    events::{BookEventMessage, EventBus},
+    layers::{StreamingBody, header_value_to_str},
+    models::{
+        book::{
+            BOOK_FIELDS, BOOK_RELATIONS, Book, BookBatch, BookBatchMode, BookBatchOperation,
+            BookBatchResult, BookCreation, BookExportFormat, BookExportQuery, BookFilter,
+            BookImportChunk, BookImportOutcome, BookImportQuery, BookImportReport, BookImportRow,
+            BookIncludes, BookWithIncludes, PartialBook,
+        },
+        revision::{BookRevision, BookRevisionDiff, ChangeContext},
+    },
+    repositories::{
+        book::{BookRepository, TITLE_AUTHOR_KEY},
+        revision::BookRevisionRepository,
+    },
+    types::{AppError, AppErrorCode, AppErrorMessage, AppResult},
     utils::{
-        extractors::{ExtractRequestId, Path, Query},
-        query::{PaginateResponse, PaginateSort, PaginateSortQuery},
+        etag::{content_etag, http_date, if_match_versions, is_not_modified, representation_etag},
+        extractors::{ExtractActor, ExtractRequestId, Json, Path, Query},
+        import::parse_rows,
+        negotiation::{ContentFormat, Negotiated, escape_csv_formula},
+        patch::apply_patch,
+        query::{FieldsQuery, IncludeQuery, PaginateResponse, PaginateSort, PaginateSortQuery},
         validation::validate_request_data,
+        versioning::ApiVersion,
     },
 };
 use axum::{
-    extract::{Extension, Json},
-    http::StatusCode,
+    body::{Body, Bytes},
+    extract::Extension,
+    http::{
+        HeaderMap, HeaderName, StatusCode,
+        header::{ACCEPT, CONTENT_DISPOSITION, CONTENT_TYPE, ETAG, LAST_MODIFIED, LOCATION},
+    },
+    response::{
+        AppendHeaders, IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
 };
-use sqlx::{Pool, Postgres};
+use chrono::{DateTime, Utc};
+use futures::{StreamExt, TryStreamExt, stream};
+use serde_json::Value;
+use sqlx::{Connection, PgConnection, PgPool, Pool, Postgres, Transaction};
+use std::{collections::HashMap, time::Duration};
 use uuid::Uuid;
 
+/// Number of attempts of a `PATCH` without `If-Match` on a book modified concurrently
+const PATCH_MAX_ATTEMPTS: u32 = 3;
+
+/// Book response with its `ETag` header
+type BookWithETag = ([(HeaderName, String); 1], Negotiated<Book>);
+
+fn with_etag(book: Book) -> BookWithETag {
+    let etag = representation_etag(book.version, ContentFormat::current(), &[]);
+    ([(ETAG, etag)], Negotiated(book))
+}
+
+/// Response of a created book, with its location
+fn created(book: Book) -> Response {
+    let location = format!("{}/book/{}", ApiVersion::current().prefix(), book.id);
+
+    (StatusCode::CREATED, [(LOCATION, location)], with_etag(book)).into_response()
+}
+
+/// Validators returned with a readable representation
+fn validator_headers(
+    etag: String,
+    last_modified: Option<DateTime<Utc>>,
+) -> AppendHeaders<Vec<(HeaderName, String)>> {
+    let mut headers = vec![(ETAG, etag)];
+    if let Some(last_modified) = last_modified {
+        headers.push((LAST_MODIFIED, http_date(&last_modified)));
+    }
+
+    AppendHeaders(headers)
+}
+
+/// Return the error of a conditional write which did not affect any book
+pub(crate) async fn conditional_write_error(
+    executor: &mut PgConnection,
+    id: String,
+    not_found: AppError,
+) -> AppError {
+    match BookRepository::get_by_id(executor, id).await {
+        Ok(Some(_)) => app_error!(
+            AppErrorCode::PreconditionFailed,
+            "book has been modified in the meantime"
+        ),
+        Ok(None) => not_found,
+        Err(err) => err,
+    }
+}
+
+/// Add the ID of the existing book to a conflict on its title and author
+pub(crate) async fn title_author_conflict_error(
+    executor: &mut PgConnection,
+    title: &str,
+    author: &str,
+    error: AppError,
+) -> AppError {
+    if error.extension("constraint").and_then(Value::as_str) != Some(TITLE_AUTHOR_KEY) {
+        return error;
+    }
+
+    match BookRepository::get_by_title_and_author(executor, title, author).await {
+        Ok(Some(book)) => error.with_extension("existing_id", book.id),
+        _ => error,
+    }
+}
+
+/// Load the related resources of books in one query per relation.
+///
+/// The result has the same order as `ids`.
+async fn load_includes(
+    pool: &PgPool,
+    ids: &[String],
+    relations: &[&str],
+) -> AppResult<Vec<BookIncludes>> {
+    let mut includes = ids
+        .iter()
+        .map(|_| BookIncludes::default())
+        .collect::<Vec<_>>();
+
+    if relations.contains(&"revisions") {
+        let mut revisions: HashMap<String, Vec<BookRevision>> = HashMap::new();
+        for revision in BookRevisionRepository::get_all_by_book_ids(pool, ids).await? {
+            revisions
+                .entry(revision.book_id.clone())
+                .or_default()
+                .push(revision);
+        }
+
+        for (id, include) in ids.iter().zip(includes.iter_mut()) {
+            include.revisions = Some(revisions.remove(id).unwrap_or_default());
+        }
+    }
+
+    Ok(includes)
+}
+
+/// Fields selected for a list of books, which must contain the ID to load the related resources,
+/// and to identify the JSON:API resource objects
+fn list_fields(fields: &FieldsQuery, relations: &[&str]) -> AppResult<Vec<&'static str>> {
+    let mut fields = fields.get_fields(BOOK_FIELDS)?;
+    let identified = !relations.is_empty() || ContentFormat::current() == ContentFormat::JsonApi;
+    if identified && !fields.contains(&"id") {
+        fields.insert(0, "id");
+    }
+
+    Ok(fields)
+}
+
+/// Embed the related resources into a page of books
+async fn embed_includes(
+    pool: &PgPool,
+    books: PaginateResponse<Vec<PartialBook>>,
+    relations: &[&str],
+) -> AppResult<PaginateResponse<Vec<BookWithIncludes<PartialBook>>>> {
+    let ids = books
+        .data
+        .iter()
+        .map(|book| book.id.clone().unwrap_or_default())
+        .collect::<Vec<_>>();
+    let includes = load_includes(pool, &ids, relations).await?;
+
+    Ok(PaginateResponse {
+        data: books
+            .data
+            .into_iter()
+            .zip(includes)
+            .map(|(book, includes)| BookWithIncludes { book, includes })
+            .collect(),
+        total: books.total,
+    })
+}
+
 // Route: POST /api/v1/book
-#[instrument(skip(pool))]
+#[instrument(skip(pool, events))]
 pub async fn create(
     Extension(pool): Extension<Pool<Postgres>>,
+    Extension(events): Extension<EventBus>,
     ExtractRequestId(request_id): ExtractRequestId,
+    ExtractActor(actor): ExtractActor,
     Json(payload): Json<BookCreation>,
-) -> AppResult<Json<Book>> {
+) -> AppResult<Response> {
+    let context = ChangeContext::new(actor, &request_id);
     validate_request_data(&payload)?;
 
+    let mut connection = pool.acquire().await?;
     let mut book = Book::new(payload);
-    BookRepository::create(&pool, &mut book).await?;
+    let revision = match BookRepository::create(&mut *connection, &mut book, &context).await {
+        Ok(revision) => revision,
+        Err(err) => {
+            return Err(title_author_conflict_error(
+                &mut connection,
+                &book.title,
+                &book.author,
+                err,
+            )
+            .await);
+        }
+    };
+    events.publish([revision]);
 
-    Ok(Json(book))
+    Ok(created(book))
 }
 
 // Route: GET /api/v1/book
 #[instrument(skip(pool))]
 pub async fn get_all(
     Query(pagination): Query<PaginateSortQuery>,
+    Query(fields): Query<FieldsQuery>,
+    Query(include): Query<IncludeQuery>,
     Extension(pool): Extension<Pool<Postgres>>,
     ExtractRequestId(request_id): ExtractRequestId,
-) -> AppResult<Json<PaginateResponse<Vec<Book>>>> {
+    headers: HeaderMap,
+) -> AppResult<Response> {
     let paginate_sort = PaginateSort::from(pagination);
-    let books = BookRepository::get_all(&pool, &paginate_sort).await?;
+    let relations = include.get_relations(BOOK_RELATIONS)?;
+    let fields = list_fields(&fields, &relations)?;
+    let books =
+        BookRepository::get_all(&pool, &paginate_sort, &fields, &BookFilter::default()).await?;
+    let books = embed_includes(&pool, books, &relations).await?;
+
+    let format = ContentFormat::current();
+    let body = format
+        .serialize(&books)
+        .map_err(|err| app_error!(AppErrorCode::InternalError, err))?;
+    // The JSON:API documents are converted from the JSON body, so the format is hashed as well.
+    // There is no `Last-Modified`: the deletions and the total do not change the dates of the page.
+    let etag = content_etag(&[format.name().as_bytes(), b"\n", &body].concat());
 
-    Ok(Json(books))
+    if is_not_modified(&headers, &etag, None) {
+        return Ok((StatusCode::NOT_MODIFIED, validator_headers(etag, None)).into_response());
+    }
+
+    Ok((
+        [(CONTENT_TYPE, format.media_type())],
+        validator_headers(etag, None),
+        body,
+    )
+        .into_response())
+}
+
+// Route: GET /api/v1/book/export
+#[instrument(skip(pool))]
+pub async fn export(
+    Query(query): Query<BookExportQuery>,
+    Extension(pool): Extension<Pool<Postgres>>,
+    ExtractRequestId(request_id): ExtractRequestId,
+    headers: HeaderMap,
+) -> AppResult<Response> {
+    let format = match query.format {
+        Some(format) => format,
+        None => BookExportFormat::from_accept(header_value_to_str(headers.get(ACCEPT)))
+            .ok_or_else(|| {
+                app_error!(
+                    AppErrorCode::NotAcceptable,
+                    "supported media types are: application/x-ndjson, text/csv"
+                )
+            })?,
+    };
+
+    let books = BookRepository::stream_all(pool);
+    let body = match format {
+        BookExportFormat::Ndjson => books
+            .map(|book| {
+                let mut line = serde_json::to_vec(&book?)
+                    .map_err(|err| app_error!(AppErrorCode::InternalError, err.to_string()))?;
+                line.push(b'\n');
+                Ok::<_, AppError>(line)
+            })
+            .boxed(),
+        BookExportFormat::Csv => {
+            stream::once(async { Ok(format!("{}\n", BOOK_FIELDS.join(",")).into_bytes()) })
+                .chain(books.map(|book| {
+                    let mut book = book?;
+                    book.title = escape_csv_formula(book.title);
+                    book.author = escape_csv_formula(book.author);
+
+                    let mut writer = csv::WriterBuilder::new()
+                        .has_headers(false)
+                        .from_writer(vec![]);
+                    writer
+                        .serialize(book)
+                        .map_err(|err| app_error!(AppErrorCode::InternalError, err.to_string()))?;
+                    writer
+                        .into_inner()
+                        .map_err(|err| app_error!(AppErrorCode::InternalError, err.to_string()))
+                }))
+                .boxed()
+        }
+    };
+
+    let filename = format!(
+        "books-{}.{}",
+        Utc::now().format("%Y%m%d%H%M%S"),
+        format.extension()
+    );
+
+    Ok((
+        [
+            (CONTENT_TYPE, format.media_type().to_owned()),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{filename}\""),
+            ),
+        ],
+        Extension(StreamingBody),
+        Body::from_stream(body),
+    )
+        .into_response())
+}
+
+// Route: GET /api/v1/book/events
+#[instrument(skip(events, settings))]
+pub async fn events(
+    Extension(events): Extension<EventBus>,
+    Extension(settings): Extension<Config>,
+    headers: HeaderMap,
+) -> AppResult<Response> {
+    let stream = match headers.get("last-event-id") {
+        Some(value) => value
+            .to_str()
+            .ok()
+            .and_then(|value| events.resume(value))
+            .ok_or_else(|| app_error!(AppErrorCode::BadRequest, "invalid Last-Event-ID header"))?
+            .boxed(),
+        None => events.subscribe(None).boxed(),
+    };
+
+    let stream = stream.map(move |message| match message {
+        BookEventMessage::Event(event) => Event::default()
+            .id(events.event_id(event.id))
+            .event(event.revision.operation.as_str())
+            .json_data(&*event),
+        BookEventMessage::Resync(id) => Ok(Event::default()
+            .id(events.event_id(id))
+            .event("resync")
+            .data("")),
+    });
+
+    let sse = Sse::new(stream);
+    let response = match settings.event_keep_alive_interval {
+        0 => sse.into_response(),
+        interval => sse
+            .keep_alive(
+                KeepAlive::new()
+                    .interval(Duration::from_secs(interval))
+                    .text("keep-alive"),
+            )
+            .into_response(),
+    };
+
+    Ok((Extension(StreamingBody), response).into_response())
+}
+
+// Route: GET /api/v1/book/trash
+#[instrument(skip(pool))]
+pub async fn get_trash(
+    Query(pagination): Query<PaginateSortQuery>,
+    Query(fields): Query<FieldsQuery>,
+    Query(include): Query<IncludeQuery>,
+    Extension(pool): Extension<Pool<Postgres>>,
+    ExtractRequestId(request_id): ExtractRequestId,
+) -> AppResult<Negotiated<PaginateResponse<Vec<BookWithIncludes<PartialBook>>>>> {
+    let paginate_sort = PaginateSort::from(pagination);
+    let relations = include.get_relations(BOOK_RELATIONS)?;
+    let fields = list_fields(&fields, &relations)?;
+    let books = BookRepository::get_trash(&pool, &paginate_sort, &fields).await?;
+
+    Ok(Negotiated(embed_includes(&pool, books, &relations).await?))
 }
 
 // Route: GET "/api/v1/book/:id"
 #[instrument(skip(pool))]
 pub async fn get_by_id(
     Path(id): Path<Uuid>,
+    Query(include): Query<IncludeQuery>,
     Extension(pool): Extension<Pool<Postgres>>,
     ExtractRequestId(request_id): ExtractRequestId,
-) -> AppResult<Json<Book>> {
+    headers: HeaderMap,
+) -> AppResult<Response> {
+    let relations = include.get_relations(BOOK_RELATIONS)?;
     let book = BookRepository::get_by_id(&pool, id.to_string()).await?;
     match book {
-        Some(book) => Ok(Json(book)),
+        Some(book) => {
+            let etag = representation_etag(book.version, ContentFormat::current(), &relations);
+            let last_modified = Some(book.last_modified());
+
+            if is_not_modified(&headers, &etag, last_modified.as_ref()) {
+                return Ok((
+                    StatusCode::NOT_MODIFIED,
+                    validator_headers(etag, last_modified),
+                )
+                    .into_response());
+            }
+
+            let includes = load_includes(&pool, std::slice::from_ref(&book.id), &relations)
+                .await?
+                .pop()
+                .unwrap_or_default();
+
+            Ok((
+                validator_headers(etag, last_modified),
+                Negotiated(BookWithIncludes { book, includes }),
+            )
+                .into_response())
+        }
         _ => Err(app_error!(
             AppErrorCode::NotFound,
             "book could not be found"
@@ -62,20 +418,181 @@ pub async fn get_by_id(
 }
 
 // Route: PUT "/api/v1/book/:id"
-#[instrument(skip(pool))]
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip(pool, events, settings))]
 pub async fn update(
     Path(id): Path<Uuid>,
     Extension(pool): Extension<Pool<Postgres>>,
+    Extension(events): Extension<EventBus>,
+    Extension(settings): Extension<Config>,
     ExtractRequestId(request_id): ExtractRequestId,
+    ExtractActor(actor): ExtractActor,
+    headers: HeaderMap,
     Json(payload): Json<BookCreation>,
-) -> AppResult<Json<Book>> {
+) -> AppResult<Response> {
+    let context = ChangeContext::new(actor, &request_id);
+    let versions = if_match_versions(&headers, settings.precondition_required)?;
     validate_request_data(&payload)?;
 
-    BookRepository::update(&pool, id.to_string(), &payload).await?;
+    let mut connection = pool.acquire().await?;
+    let revision = match BookRepository::update(
+        &mut *connection,
+        id.to_string(),
+        &payload,
+        versions.as_deref(),
+        &context,
+    )
+    .await
+    {
+        Ok(revision) => revision,
+        Err(err) => {
+            return Err(title_author_conflict_error(
+                &mut connection,
+                &payload.title,
+                &payload.author,
+                err,
+            )
+            .await);
+        }
+    };
+    let Some(revision) = revision else {
+        // Without condition, an unknown book is created with the given ID
+        if versions.is_none() && !settings.put_upsert_disabled {
+            if BookRepository::exists(&mut *connection, id.to_string()).await? {
+                return Err(app_error!(
+                    AppErrorCode::Conflict,
+                    format!("book {id} is in the trash")
+                ));
+            }
 
-    let book = BookRepository::get_by_id(&pool, id.to_string()).await?;
+            let mut book = Book::new(payload);
+            book.id = id.to_string();
+            let revision = match BookRepository::create(&mut *connection, &mut book, &context).await
+            {
+                Ok(revision) => revision,
+                Err(err) => {
+                    return Err(title_author_conflict_error(
+                        &mut connection,
+                        &book.title,
+                        &book.author,
+                        err,
+                    )
+                    .await);
+                }
+            };
+            events.publish([revision]);
+
+            return Ok(created(book));
+        }
+
+        return Err(conditional_write_error(
+            &mut connection,
+            id.to_string(),
+            app_error!(AppErrorCode::NotFound, "book could not be found"),
+        )
+        .await);
+    };
+    events.publish([revision]);
+
+    let book = BookRepository::get_by_id(&mut *connection, id.to_string()).await?;
+    match book {
+        Some(book) => Ok(with_etag(book).into_response()),
+        _ => Err(app_error!(
+            AppErrorCode::NotFound,
+            "book could not be found"
+        )),
+    }
+}
+
+// Route: PATCH "/api/v1/book/:id"
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip(pool, events, settings, body))]
+pub async fn patch(
+    Path(id): Path<Uuid>,
+    Extension(pool): Extension<Pool<Postgres>>,
+    Extension(events): Extension<EventBus>,
+    Extension(settings): Extension<Config>,
+    ExtractRequestId(request_id): ExtractRequestId,
+    ExtractActor(actor): ExtractActor,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AppResult<BookWithETag> {
+    let context = ChangeContext::new(actor, &request_id);
+    let versions = if_match_versions(&headers, settings.precondition_required)?;
+
+    let mut connection = pool.acquire().await?;
+    let mut attempts = 0;
+    let revision = loop {
+        attempts += 1;
+        let book = match BookRepository::get_by_id(&mut *connection, id.to_string()).await? {
+            Some(book) => book,
+            _ => {
+                return Err(app_error!(
+                    AppErrorCode::NotFound,
+                    "book could not be found"
+                ));
+            }
+        };
+        if let Some(versions) = &versions
+            && !versions.contains(&book.version)
+        {
+            return Err(app_error!(
+                AppErrorCode::PreconditionFailed,
+                "book has been modified in the meantime"
+            ));
+        }
+        let version = book.version;
+
+        let mut document = serde_json::to_value(BookCreation::from(book))
+            .map_err(|err| app_error!(AppErrorCode::InternalError, err.to_string()))?;
+        apply_patch(
+            &mut document,
+            header_value_to_str(headers.get(CONTENT_TYPE)),
+            &body,
+            &["title", "author"],
+        )?;
+
+        let payload: BookCreation = serde_json::from_value(document)
+            .map_err(|err| app_error!(AppErrorCode::UnprocessableEntity, err.to_string()))?;
+        validate_request_data(&payload)?;
+
+        // The patch only applies to the version it has been computed from
+        match BookRepository::update(
+            &mut *connection,
+            id.to_string(),
+            &payload,
+            Some(&[version]),
+            &context,
+        )
+        .await
+        {
+            Ok(Some(revision)) => break revision,
+            // Without `If-Match`, the patch is applied again to the book modified in the meantime
+            Ok(None) if versions.is_none() && attempts < PATCH_MAX_ATTEMPTS => continue,
+            Ok(None) => {
+                return Err(conditional_write_error(
+                    &mut connection,
+                    id.to_string(),
+                    app_error!(AppErrorCode::NotFound, "book could not be found"),
+                )
+                .await);
+            }
+            Err(err) => {
+                return Err(title_author_conflict_error(
+                    &mut connection,
+                    &payload.title,
+                    &payload.author,
+                    err,
+                )
+                .await);
+            }
+        }
+    };
+    events.publish([revision]);
+
+    let book = BookRepository::get_by_id(&mut *connection, id.to_string()).await?;
     match book {
-        Some(book) => Ok(Json(book)),
+        Some(book) => Ok(with_etag(book)),
         _ => Err(app_error!(
             AppErrorCode::NotFound,
             "book could not be found"
@@ -84,18 +601,518 @@ pub async fn update(
 }
 
 // Route: DELETE "/api/v1/book/:id"
-#[instrument(skip(pool))]
+#[instrument(skip(pool, events, settings))]
 pub async fn delete(
     Path(id): Path<Uuid>,
     Extension(pool): Extension<Pool<Postgres>>,
+    Extension(events): Extension<EventBus>,
+    Extension(settings): Extension<Config>,
     ExtractRequestId(request_id): ExtractRequestId,
+    ExtractActor(actor): ExtractActor,
+    headers: HeaderMap,
 ) -> AppResult<StatusCode> {
-    let result = BookRepository::delete(&pool, id.to_string()).await?;
+    let context = ChangeContext::new(actor, &request_id);
+    let versions = if_match_versions(&headers, settings.precondition_required)?;
+
+    let mut connection = pool.acquire().await?;
+    let result = BookRepository::delete(
+        &mut *connection,
+        id.to_string(),
+        versions.as_deref(),
+        &context,
+    )
+    .await?;
     match result {
-        1 => Ok(StatusCode::NO_CONTENT),
+        Some(revision) => {
+            events.publish([revision]);
+
+            Ok(StatusCode::NO_CONTENT)
+        }
+        None => match conditional_write_error(
+            &mut connection,
+            id.to_string(),
+            app_error!(AppErrorCode::NotFound, "book could not be found"),
+        )
+        .await
+        {
+            AppError::NotFound { .. } if settings.idempotent_delete => Ok(StatusCode::NO_CONTENT),
+            err => Err(err),
+        },
+    }
+}
+
+// Route: POST "/api/v1/book/:id/restore"
+#[instrument(skip(pool, events))]
+pub async fn restore(
+    Path(id): Path<Uuid>,
+    Extension(pool): Extension<Pool<Postgres>>,
+    Extension(events): Extension<EventBus>,
+    ExtractRequestId(request_id): ExtractRequestId,
+    ExtractActor(actor): ExtractActor,
+) -> AppResult<BookWithETag> {
+    let context = ChangeContext::new(actor, &request_id);
+    let mut connection = pool.acquire().await?;
+    let (book, revision) = restore_book(&mut connection, id.to_string(), &context).await?;
+    events.publish([revision]);
+
+    Ok(with_etag(book))
+}
+
+// Route: GET "/api/v1/book/:id/history"
+#[instrument(skip(pool))]
+pub async fn get_history(
+    Path(id): Path<Uuid>,
+    Extension(pool): Extension<Pool<Postgres>>,
+    ExtractRequestId(request_id): ExtractRequestId,
+) -> AppResult<Negotiated<Vec<BookRevisionDiff>>> {
+    let revisions = BookRevisionRepository::get_all(&pool, id.to_string()).await?;
+    if revisions.is_empty() {
+        return Err(app_error!(
+            AppErrorCode::NotFound,
+            "book could not be found"
+        ));
+    }
+
+    let mut previous: Option<&BookRevision> = None;
+    let mut history = Vec::with_capacity(revisions.len());
+    for revision in &revisions {
+        history.push(revision.diff(previous));
+        previous = Some(revision);
+    }
+
+    Ok(Negotiated(history))
+}
+
+// Route: POST "/api/v1/book/:id/revert/:revision"
+#[instrument(skip(pool, events, settings))]
+pub async fn revert(
+    Path((id, revision)): Path<(Uuid, i64)>,
+    Extension(pool): Extension<Pool<Postgres>>,
+    Extension(events): Extension<EventBus>,
+    Extension(settings): Extension<Config>,
+    ExtractRequestId(request_id): ExtractRequestId,
+    ExtractActor(actor): ExtractActor,
+    headers: HeaderMap,
+) -> AppResult<BookWithETag> {
+    let context = ChangeContext::new(actor, &request_id);
+    let versions = if_match_versions(&headers, settings.precondition_required)?;
+
+    let mut connection = pool.acquire().await?;
+    let (book, revision) = revert_book(
+        &mut connection,
+        id.to_string(),
+        revision,
+        versions.as_deref(),
+        &context,
+    )
+    .await?;
+    events.publish([revision]);
+
+    Ok(with_etag(book))
+}
+
+// Route: POST "/api/v1/book/batch"
+#[instrument(skip(pool, events, settings, payload))]
+pub async fn batch(
+    Extension(pool): Extension<Pool<Postgres>>,
+    Extension(events): Extension<EventBus>,
+    Extension(settings): Extension<Config>,
+    ExtractRequestId(request_id): ExtractRequestId,
+    ExtractActor(actor): ExtractActor,
+    Json(payload): Json<BookBatch>,
+) -> AppResult<Response> {
+    let context = ChangeContext::new(actor, &request_id);
+    validate_request_data(&payload)?;
+
+    let mut results = Vec::with_capacity(payload.operations.len());
+    match payload.mode {
+        BookBatchMode::Atomic => {
+            let mut transaction = pool.begin().await?;
+            let mut revisions = Vec::with_capacity(payload.operations.len());
+            for (index, operation) in payload.operations.into_iter().enumerate() {
+                match execute_batch_operation(&mut transaction, operation, &settings, &context)
+                    .await
+                {
+                    Ok((status, data, revision)) => {
+                        results.push(BookBatchResult {
+                            index,
+                            status: status.as_u16(),
+                            data,
+                            error: None,
+                        });
+                        revisions.push(revision);
+                    }
+                    Err(err) => {
+                        // Dropping the transaction rolls back the previous operations
+                        return Err(err
+                            .with_context(&format!("operation {index} failed"))
+                            .with_extension("index", index));
+                    }
+                }
+            }
+            transaction.commit().await?;
+            events.publish(revisions);
+        }
+        BookBatchMode::BestEffort => {
+            let mut connection = pool.acquire().await?;
+            for (index, operation) in payload.operations.into_iter().enumerate() {
+                results.push(
+                    match execute_batch_operation(&mut connection, operation, &settings, &context)
+                        .await
+                    {
+                        Ok((status, data, revision)) => {
+                            events.publish([revision]);
+
+                            BookBatchResult {
+                                index,
+                                status: status.as_u16(),
+                                data,
+                                error: None,
+                            }
+                        }
+                        Err(err) => BookBatchResult {
+                            index,
+                            status: err.status_code().as_u16(),
+                            data: None,
+                            error: Some(AppErrorMessage::from(&err)),
+                        },
+                    },
+                );
+            }
+        }
+    }
+
+    Ok(Negotiated(results).into_response())
+}
+
+// Route: POST "/api/v1/book/import"
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip(pool, events, settings, body))]
+pub async fn import(
+    Query(query): Query<BookImportQuery>,
+    Extension(pool): Extension<Pool<Postgres>>,
+    Extension(events): Extension<EventBus>,
+    Extension(settings): Extension<Config>,
+    ExtractRequestId(request_id): ExtractRequestId,
+    ExtractActor(actor): ExtractActor,
+    headers: HeaderMap,
+    body: Body,
+) -> AppResult<Negotiated<BookImportReport>> {
+    let context = ChangeContext::new(actor, &request_id);
+    let mut rows =
+        parse_rows::<BookImportRow>(header_value_to_str(headers.get(CONTENT_TYPE)), body)?;
+
+    let mut report = BookImportReport {
+        dry_run: query.dry_run,
+        ..Default::default()
+    };
+
+    // The rows are committed by chunks, so that a large import does not hold one long transaction.
+    // Each row is run in its own savepoint, so that a failing row does not abort its chunk.
+    // A dry run is rolled back at once at the end, so that each chunk sees the rows of the previous ones.
+    let mut dry_run = match query.dry_run {
+        true => Some(pool.begin().await?),
+        false => None,
+    };
+    let mut chunk = ImportChunk::begin(&pool, dry_run.as_mut(), 1).await?;
+    let mut index = 0;
+    while let Some(row) = rows
+        .try_next()
+        .await
+        .map_err(|err| report.interrupted(err))?
+    {
+        index += 1;
+
+        let result = match row {
+            Ok(row) => {
+                let mut savepoint = chunk.transaction.begin().await?;
+                let result = import_row(&mut savepoint, row, &context).await;
+                if result.is_ok() {
+                    savepoint.commit().await?;
+                }
+                result.map(|(outcome, revision)| {
+                    chunk.revisions.extend(revision);
+                    outcome
+                })
+            }
+            Err(err) => Err(err),
+        };
+        report.add(index, result);
+
+        if index - chunk.first_row + 1 == settings.import_chunk_size {
+            chunk
+                .end(index, &mut report, &events)
+                .await
+                .map_err(|err| report.interrupted(err))?;
+            chunk = ImportChunk::begin(&pool, dry_run.as_mut(), index + 1).await?;
+        }
+    }
+
+    match index >= chunk.first_row {
+        true => chunk
+            .end(index, &mut report, &events)
+            .await
+            .map_err(|err| report.interrupted(err))?,
+        // The last chunk is empty
+        false => drop(chunk),
+    }
+    if let Some(transaction) = dry_run {
+        transaction.rollback().await?;
+    }
+
+    Ok(Negotiated(report))
+}
+
+/// Rows of an import committed together
+struct ImportChunk<'c> {
+    transaction: Transaction<'c, Postgres>,
+    first_row: u64,
+    revisions: Vec<BookRevision>,
+}
+
+impl<'c> ImportChunk<'c> {
+    /// Begin a chunk in its own transaction, or in a savepoint of the transaction of a dry run
+    async fn begin(
+        pool: &PgPool,
+        dry_run: Option<&'c mut Transaction<'static, Postgres>>,
+        first_row: u64,
+    ) -> AppResult<Self> {
+        Ok(Self {
+            transaction: match dry_run {
+                Some(transaction) => transaction.begin().await?,
+                None => pool.begin().await?,
+            },
+            first_row,
+            revisions: vec![],
+        })
+    }
+
+    /// Commit the chunk, publish its revisions unless it is a dry run, and report it
+    async fn end(
+        self,
+        last_row: u64,
+        report: &mut BookImportReport,
+        events: &EventBus,
+    ) -> AppResult<()> {
+        self.transaction.commit().await?;
+        if !report.dry_run {
+            events.publish(self.revisions);
+        }
+
+        report.chunks.push(BookImportChunk {
+            first_row: self.first_row,
+            last_row,
+        });
+        Ok(())
+    }
+}
+
+/// Create or update the book of an import row, and return the recorded revision if any
+async fn import_row(
+    connection: &mut PgConnection,
+    row: BookImportRow,
+    context: &ChangeContext,
+) -> AppResult<(BookImportOutcome, Option<BookRevision>)> {
+    let id = row.id;
+    let data = BookCreation::from(row);
+    validate_request_data(&data)?;
+
+    let duplicate =
+        BookRepository::get_by_title_and_author(&mut *connection, &data.title, &data.author)
+            .await?;
+
+    let Some(id) = id else {
+        return match duplicate {
+            Some(_) => Ok((BookImportOutcome::Skipped, None)),
+            None => {
+                let mut book = Book::new(data);
+                let revision = BookRepository::create(&mut *connection, &mut book, context).await?;
+
+                Ok((BookImportOutcome::Created, Some(revision)))
+            }
+        };
+    };
+
+    match (
+        BookRepository::get_by_id(&mut *connection, id.to_string()).await?,
+        duplicate,
+    ) {
+        (Some(book), _) if book.title == data.title && book.author == data.author => {
+            Ok((BookImportOutcome::Skipped, None))
+        }
+        (_, Some(duplicate)) => Err(app_error!(
+            AppErrorCode::Conflict,
+            format!(
+                "book {} already has the same title and author",
+                duplicate.id
+            )
+        )),
+        (Some(_), None) => {
+            let revision =
+                BookRepository::update(&mut *connection, id.to_string(), &data, None, context)
+                    .await?;
+
+            Ok((BookImportOutcome::Updated, revision))
+        }
+        (None, None) => {
+            if BookRepository::exists(&mut *connection, id.to_string()).await? {
+                return Err(app_error!(
+                    AppErrorCode::Conflict,
+                    format!("book {id} is in the trash")
+                ));
+            }
+
+            let mut book = Book::new(data);
+            book.id = id.to_string();
+            let revision = BookRepository::create(&mut *connection, &mut book, context).await?;
+
+            Ok((BookImportOutcome::Created, Some(revision)))
+        }
+    }
+}
+
+/// Restore a book from the trash, and return it with the recorded revision
+pub(crate) async fn restore_book(
+    connection: &mut PgConnection,
+    id: String,
+    context: &ChangeContext,
+) -> AppResult<(Book, BookRevision)> {
+    let Some(revision) = BookRepository::restore(&mut *connection, id.clone(), context).await?
+    else {
+        return Err(app_error!(
+            AppErrorCode::NotFound,
+            "book could not be found in the trash"
+        ));
+    };
+
+    match BookRepository::get_by_id(&mut *connection, id).await? {
+        Some(book) => Ok((book, revision)),
         _ => Err(app_error!(
-            AppErrorCode::InternalError,
-            "no book or book already deleted"
+            AppErrorCode::NotFound,
+            "book could not be found"
+        )),
+    }
+}
+
+/// Bring a book back to the state of one of its revisions, and return it with the recorded revision
+pub(crate) async fn revert_book(
+    connection: &mut PgConnection,
+    id: String,
+    revision: i64,
+    versions: Option<&[i64]>,
+    context: &ChangeContext,
+) -> AppResult<(Book, BookRevision)> {
+    let payload = match BookRevisionRepository::get(&mut *connection, id.clone(), revision).await? {
+        Some(revision) => BookCreation {
+            title: revision.title,
+            author: revision.author,
+        },
+        _ => {
+            return Err(app_error!(
+                AppErrorCode::NotFound,
+                "revision could not be found"
+            ));
+        }
+    };
+
+    let Some(revision) =
+        BookRepository::revert(&mut *connection, id.clone(), &payload, versions, context).await?
+    else {
+        return Err(conditional_write_error(
+            connection,
+            id,
+            app_error!(AppErrorCode::NotFound, "book could not be found"),
+        )
+        .await);
+    };
+
+    match BookRepository::get_by_id(&mut *connection, id).await? {
+        Some(book) => Ok((book, revision)),
+        _ => Err(app_error!(
+            AppErrorCode::NotFound,
+            "book could not be found"
+        )),
+    }
+}
+
+/// Execute a single book write on the given connection, as done by the batch operations.
+///
+/// Returns the status of the operation, the book unless it has been deleted, and the recorded revision.
+pub(crate) async fn execute_batch_operation(
+    connection: &mut PgConnection,
+    operation: BookBatchOperation,
+    settings: &Config,
+    context: &ChangeContext,
+) -> AppResult<(StatusCode, Option<Book>, BookRevision)> {
+    match operation {
+        BookBatchOperation::Create { data } => {
+            validate_request_data(&data)?;
+
+            let mut book = Book::new(data);
+            let revision = BookRepository::create(&mut *connection, &mut book, context).await?;
+
+            Ok((StatusCode::CREATED, Some(book), revision))
+        }
+        BookBatchOperation::Update { id, data, version } => {
+            let versions = batch_operation_versions(version, settings)?;
+            validate_request_data(&data)?;
+
+            match BookRepository::update(
+                &mut *connection,
+                id.to_string(),
+                &data,
+                versions.as_deref(),
+                context,
+            )
+            .await?
+            {
+                None => Err(conditional_write_error(
+                    connection,
+                    id.to_string(),
+                    app_error!(AppErrorCode::NotFound, "book could not be found"),
+                )
+                .await),
+                Some(revision) => Ok((
+                    StatusCode::OK,
+                    BookRepository::get_by_id(&mut *connection, id.to_string()).await?,
+                    revision,
+                )),
+            }
+        }
+        BookBatchOperation::Delete { id, version } => {
+            let versions = batch_operation_versions(version, settings)?;
+
+            match BookRepository::delete(
+                &mut *connection,
+                id.to_string(),
+                versions.as_deref(),
+                context,
+            )
+            .await?
+            {
+                None => Err(conditional_write_error(
+                    connection,
+                    id.to_string(),
+                    app_error!(AppErrorCode::NotFound, "book could not be found"),
+                )
+                .await),
+                Some(revision) => Ok((StatusCode::NO_CONTENT, None, revision)),
+            }
+        }
+    }
+}
+
+/// Batch counterpart of `If-Match`: the expected version of the book, if any
+pub(crate) fn batch_operation_versions(
+    version: Option<i64>,
+    settings: &Config,
+) -> AppResult<Option<Vec<i64>>> {
+    match version {
+        Some(version) => Ok(Some(vec![version])),
+        None if settings.precondition_required => Err(app_error!(
+            AppErrorCode::PreconditionRequired,
+            "`version` is required"
         )),
+        None => Ok(None),
     }
 }
@@ -0,0 +1,39 @@
+use crate::{
+    config::Config,
+    events::EventBus,
+    graphql::{BookSchema, RevisionLoader},
+    models::revision::ChangeContext,
+    utils::extractors::{ExtractActor, ExtractRequestId},
+};
+use async_graphql::dataloader::DataLoader;
+use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
+use axum::extract::Extension;
+use sqlx::{Pool, Postgres};
+
+// Route: POST "/graphql"
+#[instrument(skip(schema, pool, events, settings, request))]
+pub async fn execute(
+    Extension(schema): Extension<BookSchema>,
+    Extension(pool): Extension<Pool<Postgres>>,
+    Extension(events): Extension<EventBus>,
+    Extension(settings): Extension<Config>,
+    ExtractRequestId(request_id): ExtractRequestId,
+    ExtractActor(actor): ExtractActor,
+    request: GraphQLRequest,
+) -> GraphQLResponse {
+    let context = ChangeContext::new(actor, &request_id);
+    let loader = DataLoader::new(RevisionLoader { pool: pool.clone() }, tokio::spawn);
+
+    schema
+        .execute(
+            request
+                .into_inner()
+                .data(pool)
+                .data(events)
+                .data(settings)
+                .data(context)
+                .data(loader),
+        )
+        .await
+        .into()
+}
@@ -1,2 +1,5 @@
 pub mod book;
+pub mod graphql;
 pub mod web;
+pub mod webhook;
+pub mod ws;
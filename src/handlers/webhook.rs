@@ -0,0 +1,190 @@
+use crate::{
+    app_error,
+    config::Config,
+    models::webhook::{
+        Webhook, WebhookCreation, WebhookDelivery, WebhookDeliveryQuery,
+        WebhookDeliveryWithAttempts,
+    },
+    repositories::webhook::WebhookRepository,
+    types::{AppError, AppErrorCode, AppResult},
+    utils::{
+        extractors::{Json, Path, Query},
+        negotiation::Negotiated,
+        query::{PaginateResponse, PaginateSort, PaginateSortQuery},
+        validation::{FieldError, invalid_request_data, validate_request_data},
+        versioning::ApiVersion,
+        webhook::{check_url, parse_allowed_hosts},
+    },
+};
+use axum::{
+    extract::Extension,
+    http::{StatusCode, header::LOCATION},
+    response::{IntoResponse, Response},
+};
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+// Route: POST /api/v1/webhook
+#[instrument(skip(pool, settings, payload))]
+pub async fn create(
+    Extension(pool): Extension<Pool<Postgres>>,
+    Extension(settings): Extension<Config>,
+    Json(payload): Json<WebhookCreation>,
+) -> AppResult<Response> {
+    validate_request_data(&payload)?;
+    validate_target(&payload.url, &settings)?;
+
+    let webhook = Webhook::new(payload);
+    WebhookRepository::create(&pool, &webhook).await?;
+    let location = format!("{}/webhook/{}", ApiVersion::current().prefix(), webhook.id);
+
+    Ok((
+        StatusCode::CREATED,
+        [(LOCATION, location)],
+        Negotiated(webhook),
+    )
+        .into_response())
+}
+
+// Route: GET /api/v1/webhook
+#[instrument(skip(pool))]
+pub async fn get_all(
+    Extension(pool): Extension<Pool<Postgres>>,
+) -> AppResult<Negotiated<Vec<Webhook>>> {
+    Ok(Negotiated(WebhookRepository::get_all(&pool).await?))
+}
+
+// Route: GET "/api/v1/webhook/:id"
+#[instrument(skip(pool))]
+pub async fn get_by_id(
+    Path(id): Path<Uuid>,
+    Extension(pool): Extension<Pool<Postgres>>,
+) -> AppResult<Negotiated<Webhook>> {
+    match WebhookRepository::get_by_id(&pool, id.to_string()).await? {
+        Some(webhook) => Ok(Negotiated(webhook)),
+        None => Err(app_error!(
+            AppErrorCode::NotFound,
+            "webhook could not be found"
+        )),
+    }
+}
+
+// Route: PUT "/api/v1/webhook/:id"
+#[instrument(skip(pool, settings, payload))]
+pub async fn update(
+    Path(id): Path<Uuid>,
+    Extension(pool): Extension<Pool<Postgres>>,
+    Extension(settings): Extension<Config>,
+    Json(payload): Json<WebhookCreation>,
+) -> AppResult<Negotiated<Webhook>> {
+    validate_request_data(&payload)?;
+    validate_target(&payload.url, &settings)?;
+
+    match WebhookRepository::update(&pool, id.to_string(), &payload).await? {
+        Some(webhook) => Ok(Negotiated(webhook)),
+        None => Err(app_error!(
+            AppErrorCode::NotFound,
+            "webhook could not be found"
+        )),
+    }
+}
+
+// Route: DELETE "/api/v1/webhook/:id"
+#[instrument(skip(pool))]
+pub async fn delete(
+    Path(id): Path<Uuid>,
+    Extension(pool): Extension<Pool<Postgres>>,
+) -> AppResult<StatusCode> {
+    match WebhookRepository::delete(&pool, id.to_string()).await? {
+        0 => Err(app_error!(
+            AppErrorCode::NotFound,
+            "webhook could not be found"
+        )),
+        _ => Ok(StatusCode::NO_CONTENT),
+    }
+}
+
+// Route: GET "/api/v1/webhook/:id/deliveries"
+#[instrument(skip(pool))]
+pub async fn get_deliveries(
+    Path(id): Path<Uuid>,
+    Query(pagination): Query<PaginateSortQuery>,
+    Query(query): Query<WebhookDeliveryQuery>,
+    Extension(pool): Extension<Pool<Postgres>>,
+) -> AppResult<Negotiated<PaginateResponse<Vec<WebhookDeliveryWithAttempts>>>> {
+    if WebhookRepository::get_by_id(&pool, id.to_string())
+        .await?
+        .is_none()
+    {
+        return Err(app_error!(
+            AppErrorCode::NotFound,
+            "webhook could not be found"
+        ));
+    }
+
+    let paginate_sort = PaginateSort::from(pagination);
+    let deliveries = WebhookRepository::get_deliveries(
+        &pool,
+        id.to_string(),
+        query.status,
+        paginate_sort.limit.into(),
+        paginate_sort.offset.into(),
+    )
+    .await?;
+    let total =
+        WebhookRepository::get_deliveries_total(&pool, id.to_string(), query.status).await?;
+
+    let ids = deliveries
+        .iter()
+        .map(|delivery| delivery.id)
+        .collect::<Vec<_>>();
+    let mut attempts: HashMap<i64, Vec<_>> = HashMap::new();
+    for attempt in WebhookRepository::get_attempts(&pool, &ids).await? {
+        attempts
+            .entry(attempt.delivery_id)
+            .or_default()
+            .push(attempt);
+    }
+
+    Ok(Negotiated(PaginateResponse {
+        data: deliveries
+            .into_iter()
+            .map(|delivery| WebhookDeliveryWithAttempts {
+                log: attempts.remove(&delivery.id).unwrap_or_default(),
+                delivery,
+            })
+            .collect(),
+        total,
+    }))
+}
+
+// Route: POST "/api/v1/webhook/:id/deliveries/:delivery_id/retry"
+#[instrument(skip(pool))]
+pub async fn retry_delivery(
+    Path((id, delivery_id)): Path<(Uuid, i64)>,
+    Extension(pool): Extension<Pool<Postgres>>,
+) -> AppResult<Negotiated<WebhookDelivery>> {
+    match WebhookRepository::retry_delivery(&pool, id.to_string(), delivery_id).await? {
+        Some(delivery) => Ok(Negotiated(delivery)),
+        None => Err(app_error!(
+            AppErrorCode::NotFound,
+            "dead delivery could not be found"
+        )),
+    }
+}
+
+/// Refuse a webhook URL which does not target a public HTTP endpoint, unless its host is allowed
+fn validate_target(url: &str, settings: &Config) -> AppResult<()> {
+    check_url(url, &parse_allowed_hosts(&settings.webhook_allowed_hosts)).map_err(|message| {
+        invalid_request_data(
+            AppErrorCode::BadRequest,
+            vec![FieldError {
+                field: String::from("url"),
+                code: String::from("target"),
+                message,
+                params: Default::default(),
+            }],
+        )
+    })
+}
@@ -0,0 +1,169 @@
+//! WebSocket subscriptions to the book changes.
+//!
+//! The changes come from the event stream, filtered by the subscriptions of the connection.
+//! A message is only read from the stream once the previous one has been written to the socket:
+//! a slow client catches up from the event buffer, or receives a `resync` message once the
+//! changes it missed are no longer buffered. A client which does not read its messages
+//! at all is disconnected after `WS_SEND_TIMEOUT`.
+
+use crate::{
+    config::Config,
+    events::{BookEventMessage, EventBus},
+    layers::prometheus::WebSocketConnectionMetric,
+    models::subscription::{
+        BookSubscriptionFilter, ClientMessage, SUBSCRIPTION_MAX_PER_CONNECTION, ServerMessage,
+    },
+};
+use axum::{
+    extract::{
+        Extension,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    response::Response,
+};
+use futures::{SinkExt, StreamExt, pin_mut};
+use std::{collections::HashMap, time::Duration};
+use tokio::time::{Instant, interval_at, timeout};
+
+/// Maximum size of a client message
+const WS_MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// Time given to a client to receive a message
+const WS_SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Route: GET "/ws"
+#[instrument(skip(upgrade, events, settings))]
+pub async fn connect(
+    upgrade: WebSocketUpgrade,
+    Extension(events): Extension<EventBus>,
+    Extension(settings): Extension<Config>,
+) -> Response {
+    let heartbeat = match settings.ws_heartbeat_interval {
+        0 => None,
+        interval => Some(Duration::from_secs(interval)),
+    };
+
+    upgrade
+        .max_message_size(WS_MAX_MESSAGE_SIZE)
+        .on_upgrade(move |socket| serve(socket, events, heartbeat))
+}
+
+/// Serve a connection until it is closed by the client, or the client becomes unresponsive
+async fn serve(socket: WebSocket, events: EventBus, heartbeat: Option<Duration>) {
+    let _metric = WebSocketConnectionMetric::open();
+    let (mut sender, mut receiver) = socket.split();
+    let events = events.subscribe(None);
+    pin_mut!(events);
+
+    let mut subscriptions: HashMap<String, BookSubscriptionFilter> = HashMap::new();
+    let mut heartbeat = heartbeat.map(|period| interval_at(Instant::now() + period, period));
+    let mut awaiting_pong = false;
+
+    loop {
+        let message = tokio::select! {
+            message = receiver.next() => match message {
+                Some(Ok(Message::Text(text))) => handle_client_message(&text, &mut subscriptions),
+                Some(Ok(Message::Binary(_))) => {
+                    ServerMessage::error(None, "binary messages are not supported")
+                }
+                Some(Ok(Message::Pong(_))) => {
+                    awaiting_pong = false;
+                    continue;
+                }
+                // Pings are answered by the socket itself
+                Some(Ok(Message::Ping(_))) => continue,
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+            },
+            Some(message) = events.next() => match message {
+                BookEventMessage::Event(event) => {
+                    let matching: Vec<String> = subscriptions
+                        .iter()
+                        .filter(|(_, filter)| filter.matches(&event.revision))
+                        .map(|(id, _)| id.clone())
+                        .collect();
+                    if matching.is_empty() {
+                        continue;
+                    }
+
+                    ServerMessage::Event {
+                        event_id: event.id,
+                        subscriptions: matching,
+                        data: event.revision.clone(),
+                    }
+                }
+                BookEventMessage::Resync(_) if subscriptions.is_empty() => continue,
+                BookEventMessage::Resync(event_id) => ServerMessage::Resync { event_id },
+            },
+            _ = async { heartbeat.as_mut().unwrap().tick().await }, if heartbeat.is_some() => {
+                if awaiting_pong {
+                    debug!("WebSocket client did not answer the ping, closing the connection");
+                    break;
+                }
+                awaiting_pong = true;
+
+                let ping = sender.send(Message::Ping(Default::default()));
+                match timeout(WS_SEND_TIMEOUT, ping).await {
+                    Ok(Ok(())) => continue,
+                    _ => break,
+                }
+            }
+        };
+
+        let message = match serde_json::to_string(&message) {
+            Ok(message) => message,
+            Err(err) => {
+                error!("failed to serialize a WebSocket message: {err}");
+                continue;
+            }
+        };
+        match timeout(WS_SEND_TIMEOUT, sender.send(Message::Text(message.into()))).await {
+            Ok(Ok(())) => {}
+            Ok(Err(_)) => break,
+            Err(_) => {
+                debug!("WebSocket client is too slow, closing the connection");
+                break;
+            }
+        }
+    }
+}
+
+/// Apply a client message to the subscriptions, and return the answer
+fn handle_client_message(
+    text: &str,
+    subscriptions: &mut HashMap<String, BookSubscriptionFilter>,
+) -> ServerMessage {
+    let message = match serde_json::from_str::<ClientMessage>(text) {
+        Ok(message) => message,
+        Err(err) => return ServerMessage::error(None, format!("invalid message: {err}")),
+    };
+
+    match message {
+        ClientMessage::Subscribe { id, filter } => {
+            if let Err(err) = filter.validate() {
+                return ServerMessage::error(Some(id), err);
+            }
+            if subscriptions.contains_key(&id) {
+                return ServerMessage::error(
+                    Some(id.clone()),
+                    format!("subscription `{id}` already exists"),
+                );
+            }
+            if subscriptions.len() >= SUBSCRIPTION_MAX_PER_CONNECTION {
+                return ServerMessage::error(
+                    Some(id),
+                    format!("at most {SUBSCRIPTION_MAX_PER_CONNECTION} subscriptions are allowed"),
+                );
+            }
+
+            subscriptions.insert(id.clone(), filter);
+            ServerMessage::Ack { id }
+        }
+        ClientMessage::Unsubscribe { id } => match subscriptions.remove(&id) {
+            Some(_) => ServerMessage::Ack { id },
+            None => ServerMessage::error(
+                Some(id.clone()),
+                format!("subscription `{id}` does not exist"),
+            ),
+        },
+    }
+}
@@ -0,0 +1,325 @@
+use crate::{
+    app_error,
+    layers::header_value_to_str,
+    repositories::idempotency::IdempotencyKeyRepository,
+    types::{AppError, AppErrorCode, AppResult},
+    utils::hash::sha256_hex,
+};
+use axum::{
+    body::{Body, to_bytes},
+    http::{HeaderName, HeaderValue, Method, Request, StatusCode, header::CONTENT_LENGTH},
+    response::{IntoResponse, Response},
+};
+use chrono::{TimeDelta, Utc};
+use futures::future::BoxFuture;
+use sqlx::PgPool;
+use std::{
+    convert::Infallible,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower::{Layer, Service};
+use uuid::Uuid;
+
+/// Request header carrying the idempotency key
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Response header set when a stored response is replayed
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// Maximum size of a request body sent with an idempotency key
+const IDEMPOTENCY_MAX_BODY_SIZE: usize = 10 * 1024 * 1024;
+
+/// Maximum length of an idempotency key
+const IDEMPOTENCY_KEY_MAX_LENGTH: usize = 255;
+
+/// Path suffixes of the routes whose request bodies are streamed, of any size: they cannot be
+/// fingerprinted, and refuse the idempotency keys
+const STREAMED_PATHS: [&str; 1] = ["/book/import"];
+
+/// Layer which stores the first response of `POST` requests sent with an `Idempotency-Key`
+/// header, and replays it when the request is retried with the same key.
+///
+/// The routes with a streamed request body (`STREAMED_PATHS`) refuse the header.
+#[derive(Clone)]
+pub struct IdempotencyLayer {
+    pool: PgPool,
+    ttl: Duration,
+    lock_timeout: Duration,
+}
+
+impl IdempotencyLayer {
+    /// Keys expire after `ttl`, and a request which has not stored its response after
+    /// `lock_timeout` (e.g. after a crash) can be taken over by a retry
+    pub fn new(pool: PgPool, ttl: Duration, lock_timeout: Duration) -> Self {
+        Self {
+            pool,
+            ttl,
+            lock_timeout,
+        }
+    }
+}
+
+impl<S> Layer<S> for IdempotencyLayer {
+    type Service = IdempotencyMiddleware<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        IdempotencyMiddleware {
+            inner,
+            pool: self.pool.clone(),
+            ttl: self.ttl,
+            lock_timeout: self.lock_timeout,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct IdempotencyMiddleware<S> {
+    inner: S,
+    pool: PgPool,
+    ttl: Duration,
+    lock_timeout: Duration,
+}
+
+impl<S> Service<Request<Body>> for IdempotencyMiddleware<S>
+where
+    S: Service<Request<Body>, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        if request.method() != Method::POST
+            || !request.headers().contains_key(IDEMPOTENCY_KEY_HEADER)
+        {
+            return Box::pin(self.inner.call(request));
+        }
+
+        // The inner service which has been polled ready is the one to call
+        let clone = self.inner.clone();
+        let inner = std::mem::replace(&mut self.inner, clone);
+        let pool = self.pool.clone();
+        let (ttl, lock_timeout) = (self.ttl, self.lock_timeout);
+
+        Box::pin(async move {
+            Ok(idempotent_call(inner, request, pool, ttl, lock_timeout)
+                .await
+                .unwrap_or_else(IntoResponse::into_response))
+        })
+    }
+}
+
+/// Reservation of a key by a request.
+///
+/// The key is released if the request is interrupted before its response is stored (e.g. if the
+/// client disconnects), so that it can be retried at once.
+struct Reservation {
+    pool: PgPool,
+    key: String,
+    lock_id: String,
+    done: bool,
+}
+
+impl Reservation {
+    /// Release the key without storing a response
+    async fn release(mut self) -> AppResult<()> {
+        self.done = true;
+        IdempotencyKeyRepository::release(&self.pool, &self.key, &self.lock_id).await
+    }
+
+    /// Store the response of the request
+    async fn complete(
+        mut self,
+        status_code: i16,
+        headers: Vec<(String, String)>,
+        body: &[u8],
+    ) -> AppResult<()> {
+        let completed = IdempotencyKeyRepository::complete(
+            &self.pool,
+            &self.key,
+            &self.lock_id,
+            status_code,
+            headers,
+            body,
+        )
+        .await?;
+        self.done = true;
+
+        if !completed {
+            warn!(
+                "Idempotency key {} has been taken over before the response was stored",
+                self.key
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+
+        // The request has been dropped, the key is released in the background
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let pool = self.pool.clone();
+            let key = std::mem::take(&mut self.key);
+            let lock_id = std::mem::take(&mut self.lock_id);
+            runtime.spawn(async move {
+                if let Err(err) = IdempotencyKeyRepository::release(&pool, &key, &lock_id).await {
+                    error!("Failed to release idempotency key {key}: {err}");
+                }
+            });
+        }
+    }
+}
+
+async fn idempotent_call<S>(
+    mut inner: S,
+    request: Request<Body>,
+    pool: PgPool,
+    ttl: Duration,
+    lock_timeout: Duration,
+) -> AppResult<Response>
+where
+    S: Service<Request<Body>, Response = Response, Error = Infallible>,
+{
+    let path = request.uri().path();
+    if STREAMED_PATHS.iter().any(|suffix| path.ends_with(suffix)) {
+        return Err(app_error!(
+            AppErrorCode::BadRequest,
+            "`Idempotency-Key` is not supported by requests with a streamed body"
+        )
+        .with_extension("header", "Idempotency-Key"));
+    }
+
+    let key = header_value_to_str(request.headers().get(IDEMPOTENCY_KEY_HEADER)).to_owned();
+    if key.is_empty() || key.len() > IDEMPOTENCY_KEY_MAX_LENGTH {
+        return Err(app_error!(
+            AppErrorCode::BadRequest,
+            format!("`Idempotency-Key` must contain 1 to {IDEMPOTENCY_KEY_MAX_LENGTH} characters")
+        ));
+    }
+
+    let (parts, body) = request.into_parts();
+    let body = to_bytes(body, IDEMPOTENCY_MAX_BODY_SIZE)
+        .await
+        .map_err(|err| app_error!(AppErrorCode::PayloadTooLarge, err.to_string()))?;
+
+    let mut fingerprint = format!("{} {}\n", parts.method, parts.uri).into_bytes();
+    fingerprint.extend_from_slice(&body);
+    let fingerprint = sha256_hex(&fingerprint);
+
+    let delta = |duration: Duration| {
+        TimeDelta::from_std(duration)
+            .map_err(|err| app_error!(AppErrorCode::InternalError, err.to_string()))
+    };
+    let (ttl, lock_timeout) = (delta(ttl)?, delta(lock_timeout)?);
+    let lock_id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    if !IdempotencyKeyRepository::reserve(
+        &pool,
+        &key,
+        &fingerprint,
+        &lock_id,
+        now + lock_timeout,
+        now + ttl,
+    )
+    .await?
+    {
+        return replay(&pool, &key, &fingerprint).await;
+    }
+    let reservation = Reservation {
+        pool,
+        key,
+        lock_id,
+        done: false,
+    };
+
+    let Ok(response) = inner
+        .call(Request::from_parts(parts, Body::from(body)))
+        .await;
+
+    // Server errors are not stored, so that the request can be retried
+    if response.status().is_server_error() {
+        reservation.release().await?;
+        return Ok(response);
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = match to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(err) => {
+            reservation.release().await?;
+            return Err(app_error!(AppErrorCode::InternalError, err.to_string()));
+        }
+    };
+
+    let headers = parts
+        .headers
+        .iter()
+        .filter(|(name, _)| *name != CONTENT_LENGTH)
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                header_value_to_str(Some(value)).to_owned(),
+            )
+        })
+        .collect();
+    reservation
+        .complete(i16::try_from(parts.status.as_u16())?, headers, &body)
+        .await?;
+
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+/// Replay the response stored for a key
+async fn replay(pool: &PgPool, key: &str, fingerprint: &str) -> AppResult<Response> {
+    let stored = match IdempotencyKeyRepository::get(pool, key).await? {
+        Some(stored) => stored,
+        None => {
+            return Err(app_error!(
+                AppErrorCode::Conflict,
+                "a request with the same `Idempotency-Key` is being processed"
+            ));
+        }
+    };
+
+    if stored.fingerprint != fingerprint {
+        return Err(app_error!(
+            AppErrorCode::UnprocessableEntity,
+            "`Idempotency-Key` has already been used with a different request"
+        ));
+    }
+
+    let (Some(status_code), Some(headers), Some(body)) =
+        (stored.status_code, stored.headers, stored.body)
+    else {
+        return Err(app_error!(
+            AppErrorCode::Conflict,
+            "a request with the same `Idempotency-Key` is being processed"
+        ));
+    };
+
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = StatusCode::from_u16(u16::try_from(status_code)?)
+        .map_err(|err| app_error!(AppErrorCode::InternalError, err.to_string()))?;
+    for (name, value) in headers.0 {
+        if let (Ok(name), Ok(value)) = (HeaderName::try_from(name), HeaderValue::try_from(value)) {
+            response.headers_mut().append(name, value);
+        }
+    }
+    response
+        .headers_mut()
+        .insert(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+
+    Ok(response)
+}
@@ -0,0 +1,86 @@
+use crate::{
+    app_error,
+    layers::header_value_to_str,
+    types::{AppError, AppErrorCode},
+    utils::{
+        jsonapi::book_document,
+        negotiation::{CONTENT_FORMAT, ContentFormat},
+    },
+};
+use axum::{
+    body::{Body, to_bytes},
+    extract::OriginalUri,
+    http::{
+        Request, StatusCode,
+        header::{ACCEPT, CONTENT_LENGTH},
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+/// Route layer which renders the books as JSON:API documents when they are negotiated.
+///
+/// The bodies of the successful responses are converted, the errors being already rendered as
+/// JSON:API error objects by `AppError`.
+pub async fn book_documents(req: Request<Body>, next: Next) -> Response {
+    if ContentFormat::current() != ContentFormat::JsonApi {
+        return next.run(req).await;
+    }
+
+    // The path of the collection is the original path without the one seen by the nested router
+    let uri = req
+        .extensions()
+        .get::<OriginalUri>()
+        .map(|uri| uri.0.clone())
+        .unwrap_or_else(|| req.uri().clone());
+    let collection = uri
+        .path()
+        .trim_end_matches('/')
+        .strip_suffix(req.uri().path().trim_end_matches('/'))
+        .unwrap_or(uri.path())
+        .to_owned();
+
+    let response = next.run(req).await;
+    if !response.status().is_success() || response.status() == StatusCode::NO_CONTENT {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let body = match to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(err) => {
+            return app_error!(AppErrorCode::InternalError, err.to_string()).into_response();
+        }
+    };
+
+    match serde_json::from_slice(&body)
+        .ok()
+        .and_then(|body| book_document(body, &collection, uri.path(), uri.query()))
+    {
+        Some(document) => {
+            parts.headers.remove(CONTENT_LENGTH);
+            Response::from_parts(parts, Body::from(document.to_string()))
+        }
+        None => Response::from_parts(parts, Body::from(body)),
+    }
+}
+
+/// Route layer of the endpoints which do not offer JSON:API documents.
+///
+/// The format is negotiated again without JSON:API, and the requests are rejected with
+/// `406 Not Acceptable` if no other format is acceptable.
+pub async fn without_json_api(req: Request<Body>, next: Next) -> Response {
+    if ContentFormat::current() != ContentFormat::JsonApi {
+        return next.run(req).await;
+    }
+
+    let accept = header_value_to_str(req.headers().get(ACCEPT));
+    match ContentFormat::from_accept_except(Some(accept), ContentFormat::JsonApi) {
+        Some(format) => CONTENT_FORMAT.scope(Some(format), next.run(req)).await,
+        None => app_error!(
+            AppErrorCode::NotAcceptable,
+            "JSON:API documents are not offered by this endpoint"
+        )
+        .into_response(),
+    }
+}
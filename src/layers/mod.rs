@@ -1,11 +1,20 @@
+pub mod idempotency;
+pub mod jsonapi;
 pub mod logger;
+pub mod negotiation;
+pub mod problem;
 pub mod prometheus;
+pub mod versioning;
 
 use crate::app_error;
 use crate::types::{AppError, AppErrorCode};
+use crate::utils::problem::ErrorContext;
 use axum::{
     body::{Body, to_bytes},
-    http::{HeaderValue, Request, StatusCode},
+    http::{
+        HeaderMap, HeaderName, HeaderValue, Request, StatusCode,
+        header::{CONTENT_LENGTH, CONTENT_TYPE, VARY},
+    },
     middleware::Next,
     response::{IntoResponse, Response},
 };
@@ -28,39 +37,64 @@ impl MakeRequestId for MakeRequestUuid {
 
 // =============== Override some HTTP errors ================
 
+/// Response extension marking a streamed body, which must not be buffered
+#[derive(Debug, Clone, Copy)]
+pub struct StreamingBody;
+
 /// Layer which override some HTTP errors by using `AppError`
 pub async fn override_http_errors(req: Request<Body>, next: Next) -> impl IntoResponse {
     let response = next.run(req).await;
 
+    // Streamed responses are returned as is, without reading their body
+    if response.extensions().get::<StreamingBody>().is_some() {
+        return response;
+    }
+
+    // Responses without content, and protocol upgrades, are returned as is
+    if matches!(
+        response.status(),
+        StatusCode::SWITCHING_PROTOCOLS | StatusCode::NOT_MODIFIED | StatusCode::NO_CONTENT
+    ) {
+        return response;
+    }
+
     // If it is an image, audio or video, we return response
     let headers = response.headers();
-    if let Some(content_type) = headers.get("content-type") {
-        let content_type = content_type.to_str().unwrap_or_default();
-        if content_type.starts_with("image/")
-            || content_type.starts_with("audio/")
-            || content_type.starts_with("video/")
-        {
-            return response;
-        }
+    let content_type = header_value_to_str(headers.get(CONTENT_TYPE));
+    if content_type.starts_with("image/")
+        || content_type.starts_with("audio/")
+        || content_type.starts_with("video/")
+    {
+        return response;
     }
 
+    // Plain text errors are rejections of axum, which are rendered as the other errors
+    let plain_text_error = content_type.starts_with("text/plain")
+        && (response.status().is_client_error() || response.status().is_server_error())
+        && !ErrorContext::current().legacy;
+
     let (parts, body) = response.into_parts();
     match to_bytes(body, usize::MAX).await {
-        Ok(body_bytes) => match String::from_utf8(body_bytes.to_vec()) {
-            Ok(body) => match parts.status {
-                StatusCode::METHOD_NOT_ALLOWED => {
-                    app_error!(AppErrorCode::MethodNotAllowed).into_response()
-                }
-                StatusCode::UNPROCESSABLE_ENTITY => {
-                    if body.contains("Failed to deserialize the JSON body") {
-                        app_error!(AppErrorCode::UnprocessableEntity, body).into_response()
-                    } else {
-                        Response::from_parts(parts, Body::from(body))
+        Ok(body) => match parts.status {
+            StatusCode::METHOD_NOT_ALLOWED => {
+                app_error!(AppErrorCode::MethodNotAllowed).into_response()
+            }
+            status if plain_text_error => {
+                match AppError::from_status(status, String::from_utf8_lossy(&body).into_owned()) {
+                    Some(error) => {
+                        let mut response = error.into_response();
+                        for (name, value) in parts.headers.iter() {
+                            if name != CONTENT_TYPE && name != CONTENT_LENGTH {
+                                response.headers_mut().append(name, value.clone());
+                            }
+                        }
+                        response
                     }
+                    None => Response::from_parts(parts, Body::from(body)),
                 }
-                _ => Response::from_parts(parts, Body::from(body)),
-            },
-            Err(err) => app_error!(AppErrorCode::InternalError, err.to_string()).into_response(),
+            }
+            // Bodies are not necessarily UTF-8 (e.g. MessagePack)
+            _ => Response::from_parts(parts, Body::from(body)),
         },
         Err(err) => app_error!(AppErrorCode::InternalError, err.to_string()).into_response(),
     }
@@ -68,6 +102,18 @@ pub async fn override_http_errors(req: Request<Body>, next: Next) -> impl IntoRe
 
 // =============== Utils ================
 
+/// Add a request header to the `Vary` response header, unless it is already listed
+pub fn add_vary(headers: &mut HeaderMap, name: HeaderName) {
+    let listed = headers.get_all(VARY).iter().any(|value| {
+        header_value_to_str(Some(value))
+            .split(',')
+            .any(|listed| listed.trim() == "*" || listed.trim().eq_ignore_ascii_case(name.as_str()))
+    });
+    if !listed {
+        headers.append(VARY, HeaderValue::from_name(name));
+    }
+}
+
 /// Convert `HeaderValue` to `&str`
 pub fn header_value_to_str(value: Option<&HeaderValue>) -> &str {
     match value {
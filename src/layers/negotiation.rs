@@ -0,0 +1,34 @@
+use crate::{
+    layers::{add_vary, header_value_to_str},
+    utils::negotiation::{CONTENT_FORMAT, ContentFormat, not_acceptable},
+};
+use axum::{
+    body::Body,
+    http::{Request, header::ACCEPT},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+/// Layer which selects the response format from the `Accept` request header.
+///
+/// The format is available to the handlers and to `AppError` with `ContentFormat::current()`.
+/// As every response depends on it, `Accept` is added to the `Vary` response header.
+pub async fn content_negotiation(req: Request<Body>, next: Next) -> Response {
+    let accept = req
+        .headers()
+        .contains_key(ACCEPT)
+        .then(|| header_value_to_str(req.headers().get(ACCEPT)));
+    let format = ContentFormat::from_accept(accept);
+
+    let mut response = CONTENT_FORMAT.scope(format, next.run(req)).await;
+    add_vary(response.headers_mut(), ACCEPT);
+    response
+}
+
+/// Route layer which rejects requests with `406 Not Acceptable` if no supported format is acceptable
+pub async fn require_acceptable(req: Request<Body>, next: Next) -> Response {
+    match CONTENT_FORMAT.try_with(|format| format.is_some()) {
+        Ok(false) => not_acceptable().into_response(),
+        _ => next.run(req).await,
+    }
+}
@@ -0,0 +1,18 @@
+use crate::{
+    layers::header_value_to_str,
+    utils::problem::{ERROR_CONTEXT, ErrorContext},
+};
+use axum::{body::Body, extract::State, http::Request, middleware::Next, response::Response};
+
+/// Layer which sets how the errors of a request are rendered, `legacy` being the state of the layer.
+///
+/// The context is available to `AppError` with `ErrorContext::current()`.
+pub async fn error_context(State(legacy): State<bool>, req: Request<Body>, next: Next) -> Response {
+    let request_id = header_value_to_str(req.headers().get("x-request-id"));
+    let context = ErrorContext {
+        legacy,
+        request_id: (!request_id.is_empty()).then(|| request_id.to_owned()),
+    };
+
+    ERROR_CONTEXT.scope(context, next.run(req)).await
+}
@@ -5,7 +5,7 @@ use crate::{
 use axum::{
     body::Body, extract::MatchedPath, http::Request, middleware::Next, response::IntoResponse,
 };
-use metrics::{counter, histogram};
+use metrics::{counter, gauge, histogram};
 use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
 use std::time::Instant;
 
@@ -15,6 +15,25 @@ pub const SECONDS_DURATION_BUCKETS: &[f64; 11] = &[
 
 pub struct PrometheusMetric {}
 
+/// Open WebSocket connection, counted by the `websocket_connections` gauge until dropped
+pub struct WebSocketConnectionMetric;
+
+impl WebSocketConnectionMetric {
+    pub fn open() -> Self {
+        let labels = [("service", APP_NAME)];
+        counter!("websocket_connections_total", &labels).increment(1);
+        gauge!("websocket_connections", &labels).increment(1.0);
+
+        Self
+    }
+}
+
+impl Drop for WebSocketConnectionMetric {
+    fn drop(&mut self) {
+        gauge!("websocket_connections", "service" => APP_NAME).decrement(1.0);
+    }
+}
+
 impl PrometheusMetric {
     pub fn get_handle() -> AppResult<PrometheusHandle> {
         PrometheusBuilder::new()
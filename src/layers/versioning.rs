@@ -0,0 +1,144 @@
+use crate::{
+    APP_NAME, app_error,
+    config::Config,
+    layers::{add_vary, header_value_to_str},
+    types::{AppError, AppErrorCode, CliError},
+    utils::{
+        etag::http_date,
+        problem::{ERROR_CONTEXT, ErrorContext},
+        versioning::{API_BASE_PATH, API_VERSION, ApiDeprecation, ApiVersion},
+    },
+};
+use axum::{
+    body::Body,
+    extract::State,
+    http::{
+        HeaderName, HeaderValue, Request,
+        header::{ACCEPT, LINK},
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use metrics::counter;
+use std::sync::Arc;
+
+const DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+const SUNSET: HeaderName = HeaderName::from_static("sunset");
+
+/// Deprecations of the REST API versions, state of the `api_version` layer
+#[derive(Debug, Clone, Default)]
+pub struct ApiVersioning {
+    deprecations: Arc<Vec<ApiDeprecation>>,
+}
+
+impl ApiVersioning {
+    pub fn new(settings: &Config) -> Result<Self, CliError> {
+        let deprecations = ApiDeprecation::from_config(
+            ApiVersion::V1,
+            &settings.api_v1_deprecation,
+            &settings.api_v1_sunset,
+        )
+        .map_err(CliError::ConfigError)?;
+
+        Ok(Self {
+            deprecations: Arc::new(deprecations.into_iter().collect()),
+        })
+    }
+
+    fn deprecation(&self, version: ApiVersion) -> Option<&ApiDeprecation> {
+        self.deprecations
+            .iter()
+            .find(|deprecation| deprecation.version == version)
+    }
+}
+
+/// Layer which selects the version of the REST API requests, from their path prefix or else
+/// from the `Accept` header.
+///
+/// The version is available to the handlers with `ApiVersion::current()`. The responses of the
+/// unversioned paths vary on `Accept`, those of the deprecated versions carry the `Deprecation`
+/// and `Sunset` headers, and the requests are counted by version.
+pub async fn api_version(
+    State(versioning): State<ApiVersioning>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let path = req.uri().path().to_owned();
+    let versioned_path = ApiVersion::from_path(&path);
+    let version = match versioned_path {
+        Some(version) => version,
+        None if path.starts_with(&format!("{API_BASE_PATH}/")) => {
+            let accept = req
+                .headers()
+                .contains_key(ACCEPT)
+                .then(|| header_value_to_str(req.headers().get(ACCEPT)));
+            match ApiVersion::from_accept(accept) {
+                Ok(version) => version,
+                Err(requested) => {
+                    let mut response = unsupported_version(&requested).into_response();
+                    add_vary(response.headers_mut(), ACCEPT);
+                    return response;
+                }
+            }
+        }
+        None => return next.run(req).await,
+    };
+
+    // The legacy error format is only kept for the first version
+    let mut context = ErrorContext::current();
+    context.legacy &= version == ApiVersion::V1;
+
+    let mut response = API_VERSION
+        .scope(version, ERROR_CONTEXT.scope(context, next.run(req)))
+        .await;
+    if versioned_path.is_none() {
+        add_vary(response.headers_mut(), ACCEPT);
+    }
+
+    let deprecation = versioning.deprecation(version);
+    if let Some(deprecation) = deprecation {
+        let headers = response.headers_mut();
+        if let Ok(value) = format!("@{}", deprecation.deprecated_at.timestamp()).parse() {
+            headers.insert(DEPRECATION, value);
+        }
+        if let Some(sunset_at) = &deprecation.sunset_at
+            && let Ok(value) = HeaderValue::from_str(&http_date(sunset_at))
+        {
+            headers.insert(SUNSET, value);
+        }
+        if versioned_path.is_some()
+            && let Some(rest) = path.strip_prefix(&version.prefix())
+            && let Ok(value) = format!(
+                "<{}{rest}>; rel=\"successor-version\"",
+                ApiVersion::LATEST.prefix()
+            )
+            .parse()
+        {
+            headers.append(LINK, value);
+        }
+    }
+
+    let labels = [
+        ("service", APP_NAME.to_owned()),
+        ("version", version.label().to_owned()),
+        ("deprecated", deprecation.is_some().to_string()),
+        ("status", response.status().as_u16().to_string()),
+    ];
+    counter!("api_version_requests_total", &labels).increment(1);
+
+    response
+}
+
+fn unsupported_version(requested: &str) -> AppError {
+    app_error!(
+        AppErrorCode::NotAcceptable,
+        format!(
+            "API version {requested} is not supported, supported versions are: {}",
+            ApiVersion::ALL
+                .iter()
+                .map(|version| version.number().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    )
+}
@@ -1,10 +1,16 @@
 pub mod config;
+pub mod events;
+mod graphql;
+pub mod grpc;
 mod handlers;
 pub mod layers;
 pub mod models;
+pub mod notifications;
+pub mod outbox;
 pub mod repositories;
 pub mod routes;
 mod server;
+pub mod tasks;
 mod types;
 mod utils;
 
@@ -15,4 +21,5 @@ extern crate tracing;
 pub const APP_NAME: &str = "Book API";
 
 pub use server::start_server;
-pub use types::{AppError, AppErrorCode, AppResult, CliError, CliResult};
+pub use types::{AppError, AppErrorCode, AppResult, CliError, CliResult, ProblemDetails};
+pub use utils::versioning::{API_BASE_PATH, ApiVersion};
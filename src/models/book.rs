@@ -1,3 +1,8 @@
+use crate::{
+    models::revision::BookRevision,
+    types::{AppError, AppErrorMessage, AppResult},
+    utils::negotiation::negotiate,
+};
 use serde::{Deserialize, Serialize};
 use sqlx::types::chrono::{DateTime, Utc};
 use uuid::Uuid;
@@ -10,6 +15,8 @@ pub struct Book {
     pub author: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: Option<DateTime<Utc>>,
+    pub version: i64,
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
 impl Book {
@@ -20,12 +27,293 @@ impl Book {
             author: book.author,
             created_at: Utc::now(),
             updated_at: None,
+            version: 1,
+            deleted_at: None,
+        }
+    }
+
+    /// Date of the last modification of the book
+    pub fn last_modified(&self) -> DateTime<Utc> {
+        self.updated_at.unwrap_or(self.created_at)
+    }
+}
+
+/// Fields which can be selected with the `fields` query parameter
+pub const BOOK_FIELDS: &[&str] = &[
+    "id",
+    "title",
+    "author",
+    "created_at",
+    "updated_at",
+    "version",
+    "deleted_at",
+];
+
+/// Book restricted to a sparse fieldset, where only the selected fields are serialized
+#[derive(Serialize, Debug, Default)]
+pub struct PartialBook {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<Option<DateTime<Utc>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<Option<DateTime<Utc>>>,
+}
+
+impl From<Book> for PartialBook {
+    fn from(book: Book) -> Self {
+        Self {
+            id: Some(book.id),
+            title: Some(book.title),
+            author: Some(book.author),
+            created_at: Some(book.created_at),
+            updated_at: Some(book.updated_at),
+            version: Some(book.version),
+            deleted_at: Some(book.deleted_at),
         }
     }
 }
 
+/// Criteria restricting a list of books, matched case-insensitively on a part of the field
+#[derive(Debug, Default)]
+pub struct BookFilter {
+    pub title: Option<String>,
+    pub author: Option<String>,
+}
+
+/// Related resources which can be embedded with the `include` query parameter
+pub const BOOK_RELATIONS: &[&str] = &["revisions"];
+
+/// Related resources embedded in a book
+#[derive(Serialize, Debug, Default)]
+pub struct BookIncludes {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revisions: Option<Vec<BookRevision>>,
+}
+
+/// Book with its embedded related resources
+#[derive(Serialize, Debug)]
+pub struct BookWithIncludes<B: Serialize> {
+    #[serde(flatten)]
+    pub book: B,
+    #[serde(flatten)]
+    pub includes: BookIncludes,
+}
+
 #[derive(Serialize, Deserialize, Debug, Validate)]
 pub struct BookCreation {
     pub title: String,
     pub author: String,
 }
+
+impl From<Book> for BookCreation {
+    fn from(book: Book) -> Self {
+        Self {
+            title: book.title,
+            author: book.author,
+        }
+    }
+}
+
+/// Maximum number of operations accepted in a single batch
+const BATCH_MAX_OPERATIONS: u64 = 1000;
+
+/// How a batch of operations is executed
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BookBatchMode {
+    /// All operations are run in a single transaction, which is rolled back on the first failure
+    #[default]
+    Atomic,
+
+    /// Each operation is run on its own and reports its own status
+    BestEffort,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BookBatchOperation {
+    Create {
+        data: BookCreation,
+    },
+    Update {
+        id: Uuid,
+        data: BookCreation,
+        version: Option<i64>,
+    },
+    Delete {
+        id: Uuid,
+        version: Option<i64>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Validate)]
+pub struct BookBatch {
+    #[serde(default)]
+    pub mode: BookBatchMode,
+
+    #[validate(length(min = 1, max = BATCH_MAX_OPERATIONS))]
+    pub operations: Vec<BookBatchOperation>,
+}
+
+/// Result of a single batch operation
+#[derive(Serialize, Debug)]
+pub struct BookBatchResult {
+    pub index: usize,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Book>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<AppErrorMessage>,
+}
+
+/// Format of a book export
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BookExportFormat {
+    /// Newline delimited JSON
+    #[default]
+    Ndjson,
+    Csv,
+}
+
+impl BookExportFormat {
+    /// Select the format from the `Accept` request header, `None` if no format is acceptable
+    pub fn from_accept(accept: &str) -> Option<Self> {
+        negotiate(
+            Some(accept),
+            &[Self::Ndjson, Self::Csv],
+            |format| match format {
+                Self::Ndjson => &["application/x-ndjson", "application/ndjson"],
+                Self::Csv => &["text/csv"],
+            },
+        )
+    }
+
+    pub fn media_type(&self) -> &'static str {
+        match self {
+            Self::Ndjson => "application/x-ndjson",
+            Self::Csv => "text/csv; charset=utf-8",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Ndjson => "ndjson",
+            Self::Csv => "csv",
+        }
+    }
+}
+
+/// Query parameters of a book export
+#[derive(Deserialize, Debug, Default)]
+pub struct BookExportQuery {
+    /// Takes precedence over the `Accept` header
+    pub format: Option<BookExportFormat>,
+}
+
+/// Maximum number of row errors listed in an import report
+const IMPORT_MAX_ERRORS: usize = 1000;
+
+/// Query parameters of a book import
+#[derive(Deserialize, Debug, Default)]
+pub struct BookImportQuery {
+    /// Validate the rows without saving anything
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Row of a book import. The book is updated if its ID already exists.
+#[derive(Deserialize, Debug)]
+pub struct BookImportRow {
+    #[serde(default)]
+    pub id: Option<Uuid>,
+    pub title: String,
+    pub author: String,
+}
+
+impl From<BookImportRow> for BookCreation {
+    fn from(row: BookImportRow) -> Self {
+        Self {
+            title: row.title,
+            author: row.author,
+        }
+    }
+}
+
+/// What happened to an imported row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookImportOutcome {
+    Created,
+    Updated,
+    /// The book already exists with the same title and author
+    Skipped,
+}
+
+/// Error of an imported row
+#[derive(Serialize, Debug)]
+pub struct BookImportRowError {
+    /// Index of the row, starting at 1 (CSV header excluded)
+    pub row: u64,
+    #[serde(flatten)]
+    pub error: AppErrorMessage,
+}
+
+/// Rows of an import committed together, from `first_row` to `last_row` included
+#[derive(Serialize, Debug)]
+pub struct BookImportChunk {
+    pub first_row: u64,
+    pub last_row: u64,
+}
+
+/// Report of a book import
+#[derive(Serialize, Debug, Default)]
+pub struct BookImportReport {
+    pub dry_run: bool,
+    pub created: u64,
+    pub updated: u64,
+    pub skipped: u64,
+    pub failed: u64,
+    /// First row errors, up to `IMPORT_MAX_ERRORS`
+    pub errors: Vec<BookImportRowError>,
+    /// Chunks of rows committed (or released, and rolled back at the end for a dry run), by order
+    pub chunks: Vec<BookImportChunk>,
+}
+
+impl BookImportReport {
+    /// Count the result of a row
+    pub fn add(&mut self, row: u64, result: AppResult<BookImportOutcome>) {
+        match result {
+            Ok(BookImportOutcome::Created) => self.created += 1,
+            Ok(BookImportOutcome::Updated) => self.updated += 1,
+            Ok(BookImportOutcome::Skipped) => self.skipped += 1,
+            Err(err) => {
+                self.failed += 1;
+                if self.errors.len() < IMPORT_MAX_ERRORS {
+                    self.errors.push(BookImportRowError {
+                        row,
+                        error: AppErrorMessage::from(&err),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Error of an import interrupted after some chunks, with the number of committed rows
+    pub fn interrupted(&self, err: AppError) -> AppError {
+        let committed_rows = match self.dry_run {
+            true => 0,
+            false => self.chunks.last().map_or(0, |chunk| chunk.last_row),
+        };
+
+        err.with_extension("committed_rows", committed_rows)
+    }
+}
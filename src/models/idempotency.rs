@@ -0,0 +1,18 @@
+use sqlx::types::{
+    Json,
+    chrono::{DateTime, Utc},
+};
+
+/// Request recorded for an `Idempotency-Key`, with its response once completed
+#[derive(Debug)]
+pub struct IdempotencyKey {
+    pub key: String,
+    /// Hash of the request method, URI and body
+    pub fingerprint: String,
+    /// `None` while the first request is still being processed
+    pub status_code: Option<i16>,
+    pub headers: Option<Json<Vec<(String, String)>>>,
+    pub body: Option<Vec<u8>>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
@@ -1 +1,6 @@
 pub mod book;
+pub mod idempotency;
+pub mod outbox;
+pub mod revision;
+pub mod subscription;
+pub mod webhook;
@@ -0,0 +1,11 @@
+use crate::models::revision::BookRevision;
+use sqlx::types::chrono::{DateTime, Utc};
+
+/// Change of a book, written to the outbox in the transaction of the book write
+#[derive(Debug, Clone)]
+pub struct OutboxEvent {
+    /// Position in the outbox, which increases with each change
+    pub id: i64,
+    pub revision: BookRevision,
+    pub created_at: DateTime<Utc>,
+}
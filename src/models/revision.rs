@@ -0,0 +1,136 @@
+use crate::{
+    app_error,
+    types::{AppError, AppErrorCode, AppResult},
+};
+use axum::http::HeaderValue;
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::{DateTime, Utc};
+
+/// Maximum length of an actor, stored in a `varchar(255)` column
+pub const ACTOR_MAX_LENGTH: usize = 255;
+
+/// Who is making a change, recorded with every book revision
+#[derive(Debug, Clone, Default)]
+pub struct ChangeContext {
+    pub actor: String,
+    pub request_id: String,
+}
+
+impl ChangeContext {
+    pub fn new(actor: String, request_id: &HeaderValue) -> Self {
+        Self {
+            actor,
+            request_id: request_id.to_str().unwrap_or_default().to_owned(),
+        }
+    }
+
+    /// Read the actor from the value of the `X-Actor` header (or gRPC metadata).
+    ///
+    /// A missing or empty value is `anonymous`. Values longer than `ACTOR_MAX_LENGTH` or with
+    /// characters other than visible ASCII and spaces are rejected with `400 Bad Request`.
+    pub fn parse_actor(value: Option<&[u8]>) -> AppResult<String> {
+        let actor = match value {
+            Some(actor) if !actor.is_empty() => actor,
+            _ => return Ok(String::from("anonymous")),
+        };
+
+        let error = match actor {
+            actor if actor.len() > ACTOR_MAX_LENGTH => {
+                format!("`X-Actor` header must not exceed {ACTOR_MAX_LENGTH} characters")
+            }
+            actor if !actor.iter().all(|c| (b' '..=b'~').contains(c)) => String::from(
+                "`X-Actor` header must only contain visible ASCII characters and spaces",
+            ),
+            actor => return Ok(String::from_utf8_lossy(actor).into_owned()),
+        };
+
+        Err(app_error!(AppErrorCode::BadRequest, error).with_extension("header", "X-Actor"))
+    }
+}
+
+/// Operation which produced a revision
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "varchar", rename_all = "snake_case")]
+pub enum RevisionOperation {
+    /// State of a book which existed before revisions were recorded
+    Snapshot,
+    Create,
+    Update,
+    Delete,
+    Restore,
+    Revert,
+}
+
+impl RevisionOperation {
+    /// Name of the operation, as serialized
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Snapshot => "snapshot",
+            Self::Create => "create",
+            Self::Update => "update",
+            Self::Delete => "delete",
+            Self::Restore => "restore",
+            Self::Revert => "revert",
+        }
+    }
+}
+
+/// Immutable state of a book after a change
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BookRevision {
+    pub book_id: String,
+    pub revision: i64,
+    pub operation: RevisionOperation,
+    pub title: String,
+    pub author: String,
+    pub actor: String,
+    pub request_id: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Change of a single field between two revisions
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: Option<String>,
+    pub new: String,
+}
+
+/// A revision and the fields it changed
+#[derive(Serialize, Debug)]
+pub struct BookRevisionDiff {
+    pub revision: i64,
+    pub operation: RevisionOperation,
+    pub actor: String,
+    pub request_id: String,
+    pub created_at: DateTime<Utc>,
+    pub changes: Vec<FieldChange>,
+}
+
+impl BookRevision {
+    /// Compare the revision with the previous one
+    pub fn diff(&self, previous: Option<&BookRevision>) -> BookRevisionDiff {
+        let fields = [
+            ("title", previous.map(|p| &p.title), &self.title),
+            ("author", previous.map(|p| &p.author), &self.author),
+        ];
+
+        BookRevisionDiff {
+            revision: self.revision,
+            operation: self.operation,
+            actor: self.actor.clone(),
+            request_id: self.request_id.clone(),
+            created_at: self.created_at,
+            changes: fields
+                .into_iter()
+                .filter(|(_, old, new)| *old != Some(*new))
+                .map(|(field, old, new)| FieldChange {
+                    field: field.to_owned(),
+                    old: old.cloned(),
+                    new: new.clone(),
+                })
+                .collect(),
+        }
+    }
+}
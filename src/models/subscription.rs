@@ -0,0 +1,80 @@
+use super::revision::BookRevision;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Maximum number of subscriptions of a WebSocket connection
+pub const SUBSCRIPTION_MAX_PER_CONNECTION: usize = 100;
+
+/// Message sent by a WebSocket client
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Receive the changes of the books matching the filter, under the client-chosen `id`
+    Subscribe {
+        id: String,
+        #[serde(flatten)]
+        filter: BookSubscriptionFilter,
+    },
+    Unsubscribe {
+        id: String,
+    },
+}
+
+/// Books a subscription is about, either a single book or the books of an author
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct BookSubscriptionFilter {
+    pub book_id: Option<Uuid>,
+    /// Case-insensitive part of the author, as the `author` filter of the book list
+    pub author: Option<String>,
+}
+
+impl BookSubscriptionFilter {
+    /// Check that exactly one criterion is set
+    pub fn validate(&self) -> Result<(), &'static str> {
+        match (&self.book_id, &self.author) {
+            (Some(_), None) => Ok(()),
+            (None, Some(author)) if !author.trim().is_empty() => Ok(()),
+            (None, Some(_)) => Err("author must not be empty"),
+            _ => Err("exactly one of book_id and author must be set"),
+        }
+    }
+
+    /// Check if a change is about the books of the subscription
+    pub fn matches(&self, revision: &BookRevision) -> bool {
+        match (&self.book_id, &self.author) {
+            (Some(book_id), _) => revision.book_id == book_id.to_string(),
+            (None, Some(author)) => revision
+                .author
+                .to_lowercase()
+                .contains(&author.to_lowercase()),
+            (None, None) => false,
+        }
+    }
+}
+
+/// Message sent to a WebSocket client
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// A subscription or an unsubscription has been applied
+    Ack { id: String },
+    /// A client message has been rejected, `id` is set if the message could be read
+    Error { id: Option<String>, message: String },
+    /// Change matching some subscriptions, `event_id` being the one of the event stream
+    Event {
+        event_id: u64,
+        subscriptions: Vec<String>,
+        data: BookRevision,
+    },
+    /// Some changes have been missed, the subscribed books must be reloaded
+    Resync { event_id: u64 },
+}
+
+impl ServerMessage {
+    pub fn error(id: Option<String>, message: impl Into<String>) -> Self {
+        Self::Error {
+            id,
+            message: message.into(),
+        }
+    }
+}
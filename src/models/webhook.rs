@@ -0,0 +1,141 @@
+use crate::models::revision::RevisionOperation;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::types::{
+    Json,
+    chrono::{DateTime, Utc},
+};
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+/// Event types which can be subscribed to, named after the revision operations
+pub const WEBHOOK_EVENT_TYPES: &[RevisionOperation] = &[
+    RevisionOperation::Create,
+    RevisionOperation::Update,
+    RevisionOperation::Delete,
+    RevisionOperation::Restore,
+    RevisionOperation::Revert,
+];
+
+/// Endpoint notified of the book changes
+#[derive(Serialize, Debug)]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    /// Key of the `X-Webhook-Signature` HMAC, never returned
+    #[serde(skip_serializing)]
+    pub secret: String,
+    /// Subscribed event types, all of them if empty
+    pub event_types: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl Webhook {
+    pub fn new(webhook: WebhookCreation) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            url: webhook.url,
+            secret: webhook.secret,
+            event_types: webhook.event_types,
+            created_at: Utc::now(),
+            updated_at: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct WebhookCreation {
+    #[validate(url, length(max = 2048))]
+    pub url: String,
+    #[validate(length(min = 16, max = 255))]
+    pub secret: String,
+    #[serde(default)]
+    #[validate(custom(function = "validate_event_types"))]
+    pub event_types: Vec<String>,
+}
+
+fn validate_event_types(event_types: &[String]) -> Result<(), ValidationError> {
+    match event_types.iter().all(|event_type| {
+        WEBHOOK_EVENT_TYPES
+            .iter()
+            .any(|operation| operation.as_str() == event_type)
+    }) {
+        true => Ok(()),
+        false => Err(ValidationError::new("event_types").with_message(
+            format!(
+                "valid event types are: {}",
+                WEBHOOK_EVENT_TYPES
+                    .iter()
+                    .map(RevisionOperation::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+            .into(),
+        )),
+    }
+}
+
+/// State of a delivery
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "varchar", rename_all = "snake_case")]
+pub enum WebhookDeliveryStatus {
+    /// Waiting for its first attempt, or for a retry
+    Pending,
+    Succeeded,
+    /// Given up after too many failed attempts, until it is retried manually
+    Dead,
+}
+
+/// Event to send to a webhook, with its delivery state
+#[derive(Serialize, Debug)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub webhook_id: String,
+    pub event_type: String,
+    pub payload: Json<Value>,
+    pub status: WebhookDeliveryStatus,
+    pub attempts: i32,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Delivery due to be attempted, with its endpoint
+#[derive(Debug)]
+pub struct WebhookDispatch {
+    pub id: i64,
+    pub event_type: String,
+    pub payload: Json<Value>,
+    pub attempts: i32,
+    pub url: String,
+    pub secret: String,
+}
+
+/// Logged attempt of a delivery
+#[derive(Serialize, Debug)]
+pub struct WebhookAttempt {
+    pub delivery_id: i64,
+    /// Number of the attempt, from 1 again after a manual retry
+    pub attempt: i32,
+    /// `None` if no response has been received
+    pub status_code: Option<i16>,
+    pub error: Option<String>,
+    pub duration_ms: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Delivery with its attempts, as returned by the delivery log
+#[derive(Serialize, Debug)]
+pub struct WebhookDeliveryWithAttempts {
+    #[serde(flatten)]
+    pub delivery: WebhookDelivery,
+    pub log: Vec<WebhookAttempt>,
+}
+
+/// Filter of the delivery log
+#[derive(Deserialize, Debug, Default)]
+pub struct WebhookDeliveryQuery {
+    pub status: Option<WebhookDeliveryStatus>,
+}
@@ -0,0 +1,85 @@
+//! Book change notices shared between the instances.
+//!
+//! Each book write notifies the `book_changes` channel of PostgreSQL when its transaction
+//! commits, with a compact notice of the change. Every instance listens to the channel, so that
+//! the state kept in process, such as the event stream, follows the changes made by the others.
+//!
+//! PostgreSQL drops the notifications sent while a listener is disconnected: after reconnecting,
+//! the listener replays the missed changes from the outbox. Notices are sent at least once.
+
+use crate::models::{outbox::OutboxEvent, revision::RevisionOperation};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::{broadcast, watch};
+
+/// Channel of the book change notifications
+pub const BOOK_CHANGES_CHANNEL: &str = "book_changes";
+
+/// Capacity of the channel to the subscribers
+const NOTICE_CHANNEL_CAPACITY: usize = 256;
+
+/// Compact notice of a book change, which refers to its outbox event
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChangeNotice {
+    /// ID of the outbox event of the change
+    pub id: i64,
+    pub book_id: String,
+    pub revision: i64,
+    pub operation: RevisionOperation,
+}
+
+impl From<&OutboxEvent> for ChangeNotice {
+    fn from(event: &OutboxEvent) -> Self {
+        Self {
+            id: event.id,
+            book_id: event.revision.book_id.clone(),
+            revision: event.revision.revision,
+            operation: event.revision.operation,
+        }
+    }
+}
+
+/// Broadcasts the change notices received by the listener of this instance
+#[derive(Clone)]
+pub struct Notifications {
+    sender: broadcast::Sender<ChangeNotice>,
+    connections: Arc<watch::Sender<u64>>,
+}
+
+impl Default for Notifications {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(NOTICE_CHANNEL_CAPACITY);
+
+        Self {
+            sender,
+            connections: Arc::new(watch::Sender::new(0)),
+        }
+    }
+}
+
+impl Notifications {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribe to the notices received from now on.
+    ///
+    /// A subscriber which lags behind misses notices, and must reload the books.
+    pub fn subscribe(&self) -> broadcast::Receiver<ChangeNotice> {
+        self.sender.subscribe()
+    }
+
+    /// Number of times the listener has connected, which changes once it listens again
+    pub fn connections(&self) -> watch::Receiver<u64> {
+        self.connections.subscribe()
+    }
+
+    pub(crate) fn notify(&self, notice: ChangeNotice) {
+        // Sending only fails without subscriber
+        let _ = self.sender.send(notice);
+    }
+
+    pub(crate) fn connected(&self) {
+        self.connections.send_modify(|count| *count += 1);
+    }
+}
@@ -0,0 +1,78 @@
+//! Reliable feed of the book changes.
+//!
+//! Each change is written to the `book_outbox` table in the transaction of the book write,
+//! so that no change is lost if the process stops before it is published. The relay reads the
+//! outbox in order and hands the changes to its consumers, then marks them as processed.
+//!
+//! Delivery is at least once: a batch whose consumption fails, or is interrupted, is handed
+//! again to all the consumers by the next relay. Consumers recognize the events they already
+//! handled by their `id`.
+
+use crate::{
+    models::outbox::OutboxEvent, repositories::outbox::OutboxRepository, types::AppResult,
+};
+use futures::future::BoxFuture;
+use sqlx::{PgConnection, PgPool};
+use std::sync::Arc;
+
+/// Component which handles the book changes of the outbox
+pub trait OutboxConsumer: Send + Sync {
+    /// Handle a batch of changes, in order.
+    ///
+    /// Database writes made on `connection` are committed with the processing of the changes,
+    /// and rolled back if any consumer of the batch fails.
+    fn consume<'a>(
+        &'a self,
+        connection: &'a mut PgConnection,
+        events: &'a [OutboxEvent],
+    ) -> BoxFuture<'a, AppResult<()>>;
+
+    /// Called once the changes of a batch have been marked as processed
+    fn committed(&self) {}
+}
+
+/// Relays the changes of the outbox to the consumers
+#[derive(Clone)]
+pub struct OutboxRelay {
+    pool: PgPool,
+    consumers: Vec<Arc<dyn OutboxConsumer>>,
+}
+
+impl OutboxRelay {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            consumers: vec![],
+        }
+    }
+
+    /// Add a consumer, which is handed the batches after the ones added before
+    pub fn with_consumer(mut self, consumer: impl OutboxConsumer + 'static) -> Self {
+        self.consumers.push(Arc::new(consumer));
+        self
+    }
+
+    /// Relay the next `limit` changes at most, and return their number
+    pub async fn relay(&self, limit: i64) -> AppResult<usize> {
+        let mut transaction = self.pool.begin().await?;
+
+        let events = OutboxRepository::lock_pending(&mut transaction, limit).await?;
+        if events.is_empty() {
+            return Ok(0);
+        }
+
+        for consumer in &self.consumers {
+            consumer.consume(&mut transaction, &events).await?;
+        }
+
+        let ids = events.iter().map(|event| event.id).collect::<Vec<_>>();
+        OutboxRepository::mark_processed(&mut *transaction, &ids).await?;
+        transaction.commit().await?;
+
+        for consumer in &self.consumers {
+            consumer.committed();
+        }
+
+        Ok(events.len())
+    }
+}
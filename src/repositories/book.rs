@@ -1,47 +1,111 @@
 use crate::{
-    models::book::{Book, BookCreation},
-    types::AppResult,
+    app_error,
+    models::{
+        book::{Book, BookCreation, BookFilter, PartialBook},
+        revision::{BookRevision, ChangeContext, RevisionOperation},
+    },
+    types::{AppError, AppErrorCode, AppResult},
     utils::query::{PaginateResponse, PaginateSort},
 };
-use chrono::Utc;
-use futures::TryStreamExt;
-use sqlx::{PgPool, Row};
+use async_stream::try_stream;
+use chrono::{DateTime, Utc};
+use futures::{TryStreamExt, stream::BoxStream};
+use sqlx::{PgExecutor, PgPool, Postgres, Row, postgres::PgRow};
+
+/// Unique index of the title and author of the active books
+pub const TITLE_AUTHOR_KEY: &str = "book_title_author_key";
+
+/// Code of the conflict on the ID of a purged book, whose revisions are kept
+pub const PURGED_ID_CODE: &str = "purged_id";
 
 pub struct BookRepository;
 
 impl BookRepository {
-    /// Add a new book
-    #[tracing::instrument(skip(pool))]
-    pub async fn create(pool: &PgPool, book: &mut Book) -> AppResult<()> {
-        sqlx::query!(
+    /// Add a new book, and return its first revision.
+    ///
+    /// The ID of a purged book cannot be reused, since its history is kept.
+    #[tracing::instrument(skip(executor))]
+    pub async fn create<'e>(
+        executor: impl PgExecutor<'e>,
+        book: &mut Book,
+        context: &ChangeContext,
+    ) -> AppResult<BookRevision> {
+        let revision = sqlx::query_as!(
+            BookRevision,
             r#"
-                INSERT INTO book (id, title, author, created_at)
-                VALUES ( $1, $2, $3, $4)
+                WITH created AS (
+                    INSERT INTO book (id, title, author, created_at)
+                    SELECT $1::varchar, $2::varchar, $3::varchar, $4::timestamptz
+                    WHERE NOT EXISTS (SELECT 1 FROM book_revision WHERE book_id = $1::varchar)
+                    RETURNING id, version, title, author, created_at
+                )
+                INSERT INTO book_revision (book_id, revision, operation, title, author, actor, request_id, created_at)
+                SELECT id, version, 'create', title, author, $5, $6, created_at
+                FROM created
+                RETURNING book_id, revision, operation AS "operation: RevisionOperation",
+                    title, author, actor, request_id, created_at
             "#,
             book.id,
             book.title,
             book.author,
             book.created_at,
+            context.actor,
+            context.request_id,
         )
-        .execute(pool)
+        .fetch_optional(executor)
         .await?;
 
-        Ok(())
+        revision.ok_or_else(|| {
+            app_error!(
+                AppErrorCode::Conflict,
+                format!("book ID {} belongs to a purged book", book.id)
+            )
+            .with_extension("code", PURGED_ID_CODE)
+        })
     }
 
-    /// Returns all books
+    /// Returns all books matching the filter
     #[instrument(skip(pool))]
     pub async fn get_all<'a>(
         pool: &'a PgPool,
         paginate_sort: &'a PaginateSort,
-    ) -> AppResult<PaginateResponse<Vec<Book>>> {
-        let total = Self::get_total(pool).await?;
+        fields: &'a [&'a str],
+        filter: &'a BookFilter,
+    ) -> AppResult<PaginateResponse<Vec<PartialBook>>> {
+        Self::get_page(pool, paginate_sort, fields, filter, false).await
+    }
+
+    /// Returns all deleted books
+    #[instrument(skip(pool))]
+    pub async fn get_trash<'a>(
+        pool: &'a PgPool,
+        paginate_sort: &'a PaginateSort,
+        fields: &'a [&'a str],
+    ) -> AppResult<PaginateResponse<Vec<PartialBook>>> {
+        Self::get_page(pool, paginate_sort, fields, &BookFilter::default(), true).await
+    }
+
+    /// Returns a page of either active or deleted books.
+    ///
+    /// Only the `fields` columns are selected, they must be taken from `BOOK_FIELDS`.
+    async fn get_page<'a>(
+        pool: &'a PgPool,
+        paginate_sort: &'a PaginateSort,
+        fields: &'a [&'a str],
+        filter: &'a BookFilter,
+        deleted: bool,
+    ) -> AppResult<PaginateResponse<Vec<PartialBook>>> {
+        let total = Self::get_total(pool, filter, deleted).await?;
 
-        let mut query = String::from(
+        let mut query = format!(
             "
-            SELECT id, title, author, created_at, updated_at
+            SELECT {}
             FROM book
+            WHERE (deleted_at IS NOT NULL) = $3
+                AND ($4::text IS NULL OR strpos(lower(title), lower($4)) > 0)
+                AND ($5::text IS NULL OR strpos(lower(author), lower($5)) > 0)
             ",
+            fields.join(", ")
         );
 
         // Sorts and pagination
@@ -51,39 +115,63 @@ impl BookRepository {
             "author",
             "created_at",
             "updated_at",
+            "deleted_at",
         ])));
         query.push_str(&paginate_sort.get_pagination_sql());
 
         let mut rows = sqlx::query(&query)
             .bind(i32::try_from(paginate_sort.limit)?)
             .bind(i32::try_from(paginate_sort.offset)?)
+            .bind(deleted)
+            .bind(&filter.title)
+            .bind(&filter.author)
             .fetch(pool);
 
         let mut books = vec![];
         while let Some(row) = rows.try_next().await? {
-            books.push(Book {
-                id: row.try_get("id")?,
-                title: row.try_get("title")?,
-                author: row.try_get("author")?,
-                created_at: row.try_get("created_at")?,
-                updated_at: row.try_get("updated_at")?,
+            books.push(PartialBook {
+                id: Self::try_get_field(&row, fields, "id")?,
+                title: Self::try_get_field(&row, fields, "title")?,
+                author: Self::try_get_field(&row, fields, "author")?,
+                created_at: Self::try_get_field(&row, fields, "created_at")?,
+                updated_at: Self::try_get_field(&row, fields, "updated_at")?,
+                version: Self::try_get_field(&row, fields, "version")?,
+                deleted_at: Self::try_get_field(&row, fields, "deleted_at")?,
             });
         }
         Ok(PaginateResponse { data: books, total })
     }
 
-    /// Returns a book by its ID
-    #[instrument(skip(pool))]
-    pub async fn get_by_id(pool: &PgPool, id: String) -> AppResult<Option<Book>> {
+    /// Decode a column only if it has been selected
+    fn try_get_field<'r, T>(row: &'r PgRow, fields: &[&str], name: &str) -> AppResult<Option<T>>
+    where
+        T: sqlx::Decode<'r, Postgres> + sqlx::Type<Postgres>,
+    {
+        match fields.contains(&name) {
+            true => Ok(Some(row.try_get(name)?)),
+            false => Ok(None),
+        }
+    }
+
+    /// Returns the active book with the given title and author
+    #[instrument(skip(executor))]
+    pub async fn get_by_title_and_author<'e>(
+        executor: impl PgExecutor<'e>,
+        title: &str,
+        author: &str,
+    ) -> AppResult<Option<Book>> {
         let result = sqlx::query!(
             r#"
                 SELECT *
                 FROM book
-                WHERE id = $1
+                WHERE title = $1
+                    AND author = $2
+                    AND deleted_at IS NULL
             "#,
-            id
+            title,
+            author
         )
-        .fetch_optional(pool)
+        .fetch_optional(executor)
         .await?;
 
         match result {
@@ -93,55 +181,280 @@ impl BookRepository {
                 author: result.author,
                 created_at: result.created_at,
                 updated_at: result.updated_at,
+                version: result.version,
+                deleted_at: result.deleted_at,
             })),
             None => Ok(None),
         }
     }
 
-    /// Delete a book
+    /// Check if a book exists, including deleted books
+    #[instrument(skip(executor))]
+    pub async fn exists<'e>(executor: impl PgExecutor<'e>, id: String) -> AppResult<bool> {
+        let result = sqlx::query!(
+            r#"
+                SELECT EXISTS(SELECT 1 FROM book WHERE id = $1) AS "exists!"
+            "#,
+            id
+        )
+        .fetch_one(executor)
+        .await?;
+
+        Ok(result.exists)
+    }
+
+    /// Returns all active books, oldest first, as a stream read from a database cursor
     #[instrument(skip(pool))]
-    pub async fn delete(pool: &PgPool, id: String) -> AppResult<u64> {
+    pub fn stream_all(pool: PgPool) -> BoxStream<'static, AppResult<Book>> {
+        Box::pin(try_stream! {
+            let mut books = sqlx::query_as!(
+                Book,
+                r#"
+                    SELECT id, title, author, created_at, updated_at, version, deleted_at
+                    FROM book
+                    WHERE deleted_at IS NULL
+                    ORDER BY created_at, id
+                "#
+            )
+            .fetch(&pool);
+
+            while let Some(book) = books.try_next().await? {
+                yield book;
+            }
+        })
+    }
+
+    /// Returns a book by its ID
+    #[instrument(skip(executor))]
+    pub async fn get_by_id<'e>(
+        executor: impl PgExecutor<'e>,
+        id: String,
+    ) -> AppResult<Option<Book>> {
         let result = sqlx::query!(
             r#"
-                DELETE FROM book
+                SELECT *
+                FROM book
                 WHERE id = $1
+                    AND deleted_at IS NULL
             "#,
             id
         )
-        .execute(pool)
+        .fetch_optional(executor)
         .await?;
 
-        Ok(result.rows_affected())
+        match result {
+            Some(result) => Ok(Some(Book {
+                id: result.id,
+                title: result.title,
+                author: result.author,
+                created_at: result.created_at,
+                updated_at: result.updated_at,
+                version: result.version,
+                deleted_at: result.deleted_at,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    /// Delete a book by moving it to the trash
+    ///
+    /// Returns the recorded revision, or `None` if no book has been deleted.
+    ///
+    /// If `versions` is set, the book is only deleted if its current version is one of them.
+    #[instrument(skip(executor))]
+    pub async fn delete<'e>(
+        executor: impl PgExecutor<'e>,
+        id: String,
+        versions: Option<&[i64]>,
+        context: &ChangeContext,
+    ) -> AppResult<Option<BookRevision>> {
+        Ok(sqlx::query_as!(
+            BookRevision,
+            r#"
+                WITH deleted AS (
+                    UPDATE book
+                    SET deleted_at = $3, version = version + 1
+                    WHERE id = $1
+                        AND deleted_at IS NULL
+                        AND ($2::bigint[] IS NULL OR version = ANY($2))
+                    RETURNING id, version, title, author, deleted_at
+                )
+                INSERT INTO book_revision (book_id, revision, operation, title, author, actor, request_id, created_at)
+                SELECT id, version, 'delete', title, author, $4, $5, deleted_at
+                FROM deleted
+                RETURNING book_id, revision, operation AS "operation: RevisionOperation",
+                    title, author, actor, request_id, created_at
+            "#,
+            id,
+            versions as _,
+            Utc::now(),
+            context.actor,
+            context.request_id,
+        )
+        .fetch_optional(executor)
+        .await?)
     }
 
     /// Update a book
-    #[instrument(skip(pool))]
-    pub async fn update(pool: &PgPool, id: String, book: &BookCreation) -> AppResult<()> {
-        sqlx::query!(
+    ///
+    /// Returns the recorded revision, or `None` if no book has been updated.
+    ///
+    /// If `versions` is set, the book is only updated if its current version is one of them.
+    #[instrument(skip(executor))]
+    pub async fn update<'e>(
+        executor: impl PgExecutor<'e>,
+        id: String,
+        book: &BookCreation,
+        versions: Option<&[i64]>,
+        context: &ChangeContext,
+    ) -> AppResult<Option<BookRevision>> {
+        Self::update_with_operation(
+            executor,
+            id,
+            book,
+            versions,
+            context,
+            RevisionOperation::Update,
+        )
+        .await
+    }
+
+    /// Bring a book back to the state of a previous revision
+    ///
+    /// Returns the recorded revision, or `None` if no book has been updated.
+    ///
+    /// If `versions` is set, the book is only updated if its current version is one of them.
+    #[instrument(skip(executor))]
+    pub async fn revert<'e>(
+        executor: impl PgExecutor<'e>,
+        id: String,
+        book: &BookCreation,
+        versions: Option<&[i64]>,
+        context: &ChangeContext,
+    ) -> AppResult<Option<BookRevision>> {
+        Self::update_with_operation(
+            executor,
+            id,
+            book,
+            versions,
+            context,
+            RevisionOperation::Revert,
+        )
+        .await
+    }
+
+    async fn update_with_operation<'e>(
+        executor: impl PgExecutor<'e>,
+        id: String,
+        book: &BookCreation,
+        versions: Option<&[i64]>,
+        context: &ChangeContext,
+        operation: RevisionOperation,
+    ) -> AppResult<Option<BookRevision>> {
+        Ok(sqlx::query_as!(
+            BookRevision,
             r#"
-                UPDATE book
-                SET title = $1, author = $2, updated_at = $3
-                WHERE id = $4
+                WITH updated AS (
+                    UPDATE book
+                    SET title = $1, author = $2, updated_at = $3, version = version + 1
+                    WHERE id = $4
+                        AND deleted_at IS NULL
+                        AND ($5::bigint[] IS NULL OR version = ANY($5))
+                    RETURNING id, version, title, author, updated_at
+                )
+                INSERT INTO book_revision (book_id, revision, operation, title, author, actor, request_id, created_at)
+                SELECT id, version, $6, title, author, $7, $8, updated_at
+                FROM updated
+                RETURNING book_id, revision, operation AS "operation: RevisionOperation",
+                    title, author, actor, request_id, created_at
             "#,
             book.title,
             book.author,
             Some(Utc::now()),
-            id
+            id,
+            versions as _,
+            operation as _,
+            context.actor,
+            context.request_id,
+        )
+        .fetch_optional(executor)
+        .await?)
+    }
+
+    /// Restore a deleted book
+    ///
+    /// Returns the recorded revision, or `None` if no book has been restored.
+    #[instrument(skip(executor))]
+    pub async fn restore<'e>(
+        executor: impl PgExecutor<'e>,
+        id: String,
+        context: &ChangeContext,
+    ) -> AppResult<Option<BookRevision>> {
+        Ok(sqlx::query_as!(
+            BookRevision,
+            r#"
+                WITH restored AS (
+                    UPDATE book
+                    SET deleted_at = NULL, updated_at = $2, version = version + 1
+                    WHERE id = $1
+                        AND deleted_at IS NOT NULL
+                    RETURNING id, version, title, author, updated_at
+                )
+                INSERT INTO book_revision (book_id, revision, operation, title, author, actor, request_id, created_at)
+                SELECT id, version, 'restore', title, author, $3, $4, updated_at
+                FROM restored
+                RETURNING book_id, revision, operation AS "operation: RevisionOperation",
+                    title, author, actor, request_id, created_at
+            "#,
+            id,
+            Some(Utc::now()),
+            context.actor,
+            context.request_id,
         )
-        .execute(pool)
+        .fetch_optional(executor)
+        .await?)
+    }
+
+    /// Permanently remove books deleted before the given date
+    #[instrument(skip(executor))]
+    pub async fn purge<'e>(
+        executor: impl PgExecutor<'e>,
+        deleted_before: DateTime<Utc>,
+    ) -> AppResult<u64> {
+        let result = sqlx::query!(
+            r#"
+                DELETE FROM book
+                WHERE deleted_at < $1
+            "#,
+            deleted_before,
+        )
+        .execute(executor)
         .await?;
 
-        Ok(())
+        Ok(result.rows_affected())
     }
 
     /// Get amount of existing books
     #[instrument(skip(pool))]
-    async fn get_total(pool: &PgPool) -> Result<i64, sqlx::Error> {
+    async fn get_total(
+        pool: &PgPool,
+        filter: &BookFilter,
+        deleted: bool,
+    ) -> Result<i64, sqlx::Error> {
         let query = r#"
             SELECT COUNT(id) AS n
             FROM book
+            WHERE (deleted_at IS NOT NULL) = $1
+                AND ($2::text IS NULL OR strpos(lower(title), lower($2)) > 0)
+                AND ($3::text IS NULL OR strpos(lower(author), lower($3)) > 0)
         "#;
 
-        Ok(sqlx::query(query).fetch_one(pool).await?.get("n"))
+        Ok(sqlx::query(query)
+            .bind(deleted)
+            .bind(&filter.title)
+            .bind(&filter.author)
+            .fetch_one(pool)
+            .await?
+            .get("n"))
     }
 }
@@ -0,0 +1,146 @@
+use crate::{models::idempotency::IdempotencyKey, types::AppResult};
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, types::Json};
+
+pub struct IdempotencyKeyRepository;
+
+impl IdempotencyKeyRepository {
+    /// Reserve a key for a new request, locked by `lock_id` until `locked_until`
+    ///
+    /// The lock of the same request which has not been completed in time is taken over, so that
+    /// a request interrupted before storing its response can be retried.
+    /// Returns `false` if the key is already used by a request which has not expired.
+    #[instrument(skip(executor))]
+    pub async fn reserve<'e>(
+        executor: impl PgExecutor<'e>,
+        key: &str,
+        fingerprint: &str,
+        lock_id: &str,
+        locked_until: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> AppResult<bool> {
+        let result = sqlx::query!(
+            r#"
+                INSERT INTO idempotency_key (key, fingerprint, created_at, expires_at, lock_id, locked_until)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                ON CONFLICT (key) DO UPDATE
+                SET fingerprint = EXCLUDED.fingerprint,
+                    status_code = NULL,
+                    headers = NULL,
+                    body = NULL,
+                    created_at = EXCLUDED.created_at,
+                    expires_at = EXCLUDED.expires_at,
+                    lock_id = EXCLUDED.lock_id,
+                    locked_until = EXCLUDED.locked_until
+                WHERE idempotency_key.expires_at <= EXCLUDED.created_at
+                    OR (
+                        idempotency_key.status_code IS NULL
+                        AND idempotency_key.fingerprint = EXCLUDED.fingerprint
+                        AND idempotency_key.locked_until <= EXCLUDED.created_at
+                    )
+            "#,
+            key,
+            fingerprint,
+            Utc::now(),
+            expires_at,
+            lock_id,
+            locked_until,
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Returns a key
+    #[instrument(skip(executor))]
+    pub async fn get<'e>(
+        executor: impl PgExecutor<'e>,
+        key: &str,
+    ) -> AppResult<Option<IdempotencyKey>> {
+        Ok(sqlx::query_as!(
+            IdempotencyKey,
+            r#"
+                SELECT key, fingerprint, status_code,
+                    headers AS "headers: Json<Vec<(String, String)>>",
+                    body, created_at, expires_at
+                FROM idempotency_key
+                WHERE key = $1
+            "#,
+            key
+        )
+        .fetch_optional(executor)
+        .await?)
+    }
+
+    /// Store the response of the request which holds the lock of the key
+    ///
+    /// Returns `false` if the lock has been taken over by a retry in the meantime.
+    #[instrument(skip(executor, headers, body))]
+    pub async fn complete<'e>(
+        executor: impl PgExecutor<'e>,
+        key: &str,
+        lock_id: &str,
+        status_code: i16,
+        headers: Vec<(String, String)>,
+        body: &[u8],
+    ) -> AppResult<bool> {
+        let result = sqlx::query!(
+            r#"
+                UPDATE idempotency_key
+                SET status_code = $3, headers = $4, body = $5, locked_until = NULL
+                WHERE key = $1
+                    AND lock_id = $2
+                    AND status_code IS NULL
+            "#,
+            key,
+            lock_id,
+            status_code,
+            Json(headers) as _,
+            body,
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Release a key without storing a response, so that the request can be retried
+    #[instrument(skip(executor))]
+    pub async fn release<'e>(
+        executor: impl PgExecutor<'e>,
+        key: &str,
+        lock_id: &str,
+    ) -> AppResult<()> {
+        sqlx::query!(
+            r#"
+                DELETE FROM idempotency_key
+                WHERE key = $1
+                    AND lock_id = $2
+                    AND status_code IS NULL
+            "#,
+            key,
+            lock_id
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove expired keys
+    #[instrument(skip(executor))]
+    pub async fn purge<'e>(executor: impl PgExecutor<'e>) -> AppResult<u64> {
+        let result = sqlx::query!(
+            r#"
+                DELETE FROM idempotency_key
+                WHERE expires_at <= $1
+            "#,
+            Utc::now(),
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
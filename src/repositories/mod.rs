@@ -1 +1,5 @@
 pub mod book;
+pub mod idempotency;
+pub mod outbox;
+pub mod revision;
+pub mod webhook;
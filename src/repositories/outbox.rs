@@ -0,0 +1,193 @@
+use crate::{
+    models::{outbox::OutboxEvent, revision::BookRevision},
+    types::AppResult,
+};
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgExecutor, types::Json};
+
+pub struct OutboxRepository;
+
+impl OutboxRepository {
+    /// Returns up to `limit` events which have not been processed, in order.
+    ///
+    /// They stay locked until the end of the transaction, so that concurrent relays process them one after the other.
+    #[instrument(skip(connection))]
+    pub async fn lock_pending(
+        connection: &mut PgConnection,
+        limit: i64,
+    ) -> AppResult<Vec<OutboxEvent>> {
+        let rows = sqlx::query!(
+            r#"
+                SELECT id, payload AS "payload: Json<BookRevision>", created_at
+                FROM book_outbox
+                WHERE processed_at IS NULL
+                ORDER BY id
+                LIMIT $1
+                FOR UPDATE
+            "#,
+            limit
+        )
+        .fetch_all(connection)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| OutboxEvent {
+                id: row.id,
+                revision: row.payload.0,
+                created_at: row.created_at,
+            })
+            .collect())
+    }
+
+    /// Returns up to `limit` events after the `after_id` one, in order
+    #[instrument(skip(executor))]
+    pub async fn get_since<'e>(
+        executor: impl PgExecutor<'e>,
+        after_id: i64,
+        limit: i64,
+    ) -> AppResult<Vec<OutboxEvent>> {
+        let rows = sqlx::query!(
+            r#"
+                SELECT id, payload AS "payload: Json<BookRevision>", created_at
+                FROM book_outbox
+                WHERE id > $1
+                ORDER BY id
+                LIMIT $2
+            "#,
+            after_id,
+            limit
+        )
+        .fetch_all(executor)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| OutboxEvent {
+                id: row.id,
+                revision: row.payload.0,
+                created_at: row.created_at,
+            })
+            .collect())
+    }
+
+    /// Returns an event
+    #[instrument(skip(executor))]
+    pub async fn get_by_id<'e>(
+        executor: impl PgExecutor<'e>,
+        id: i64,
+    ) -> AppResult<Option<OutboxEvent>> {
+        let row = sqlx::query!(
+            r#"
+                SELECT id, payload AS "payload: Json<BookRevision>", created_at
+                FROM book_outbox
+                WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(executor)
+        .await?;
+
+        Ok(row.map(|row| OutboxEvent {
+            id: row.id,
+            revision: row.payload.0,
+            created_at: row.created_at,
+        }))
+    }
+
+    /// Returns the events among `ids`, ordered by ID
+    #[instrument(skip(executor))]
+    pub async fn get_by_ids<'e>(
+        executor: impl PgExecutor<'e>,
+        ids: &[i64],
+    ) -> AppResult<Vec<OutboxEvent>> {
+        let rows = sqlx::query!(
+            r#"
+                SELECT id, payload AS "payload: Json<BookRevision>", created_at
+                FROM book_outbox
+                WHERE id = ANY($1)
+                ORDER BY id
+            "#,
+            ids
+        )
+        .fetch_all(executor)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| OutboxEvent {
+                id: row.id,
+                revision: row.payload.0,
+                created_at: row.created_at,
+            })
+            .collect())
+    }
+
+    /// Returns the ID of the last event, or 0 if there is none
+    #[instrument(skip(executor))]
+    pub async fn get_last_id<'e>(executor: impl PgExecutor<'e>) -> AppResult<i64> {
+        Ok(sqlx::query_scalar!(
+            r#"
+                SELECT COALESCE(MAX(id), 0) AS "id!"
+                FROM book_outbox
+            "#
+        )
+        .fetch_one(executor)
+        .await?)
+    }
+
+    /// Mark events as processed
+    #[instrument(skip(executor))]
+    pub async fn mark_processed<'e>(executor: impl PgExecutor<'e>, ids: &[i64]) -> AppResult<u64> {
+        let result = sqlx::query!(
+            r#"
+                UPDATE book_outbox
+                SET processed_at = $2
+                WHERE id = ANY($1)
+            "#,
+            ids,
+            Utc::now(),
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Returns the number of events which have not been processed
+    #[instrument(skip(executor))]
+    pub async fn count_pending<'e>(executor: impl PgExecutor<'e>) -> AppResult<i64> {
+        Ok(sqlx::query_scalar!(
+            r#"
+                SELECT COUNT(*) AS "total!"
+                FROM book_outbox
+                WHERE processed_at IS NULL
+            "#
+        )
+        .fetch_one(executor)
+        .await?)
+    }
+
+    /// Delete the events processed before the given date, and the pending events created before it
+    /// if `pending` is set
+    #[instrument(skip(executor))]
+    pub async fn purge<'e>(
+        executor: impl PgExecutor<'e>,
+        processed_before: DateTime<Utc>,
+        pending: bool,
+    ) -> AppResult<u64> {
+        let result = sqlx::query!(
+            r#"
+                DELETE FROM book_outbox
+                WHERE processed_at < $1
+                    OR ($2 AND processed_at IS NULL AND created_at < $1)
+            "#,
+            processed_before,
+            pending,
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,74 @@
+use crate::{
+    models::revision::{BookRevision, RevisionOperation},
+    types::AppResult,
+};
+use sqlx::PgExecutor;
+
+pub struct BookRevisionRepository;
+
+impl BookRevisionRepository {
+    /// Returns all revisions of a book, oldest first
+    #[instrument(skip(executor))]
+    pub async fn get_all<'e>(
+        executor: impl PgExecutor<'e>,
+        book_id: String,
+    ) -> AppResult<Vec<BookRevision>> {
+        Ok(sqlx::query_as!(
+            BookRevision,
+            r#"
+                SELECT book_id, revision, operation AS "operation: RevisionOperation",
+                    title, author, actor, request_id, created_at
+                FROM book_revision
+                WHERE book_id = $1
+                ORDER BY revision
+            "#,
+            book_id
+        )
+        .fetch_all(executor)
+        .await?)
+    }
+
+    /// Returns all revisions of several books, oldest first
+    #[instrument(skip(executor))]
+    pub async fn get_all_by_book_ids<'e>(
+        executor: impl PgExecutor<'e>,
+        book_ids: &[String],
+    ) -> AppResult<Vec<BookRevision>> {
+        Ok(sqlx::query_as!(
+            BookRevision,
+            r#"
+                SELECT book_id, revision, operation AS "operation: RevisionOperation",
+                    title, author, actor, request_id, created_at
+                FROM book_revision
+                WHERE book_id = ANY($1)
+                ORDER BY book_id, revision
+            "#,
+            book_ids
+        )
+        .fetch_all(executor)
+        .await?)
+    }
+
+    /// Returns a revision of a book
+    #[instrument(skip(executor))]
+    pub async fn get<'e>(
+        executor: impl PgExecutor<'e>,
+        book_id: String,
+        revision: i64,
+    ) -> AppResult<Option<BookRevision>> {
+        Ok(sqlx::query_as!(
+            BookRevision,
+            r#"
+                SELECT book_id, revision, operation AS "operation: RevisionOperation",
+                    title, author, actor, request_id, created_at
+                FROM book_revision
+                WHERE book_id = $1
+                    AND revision = $2
+            "#,
+            book_id,
+            revision
+        )
+        .fetch_optional(executor)
+        .await?)
+    }
+}
@@ -0,0 +1,324 @@
+use crate::{
+    models::webhook::{
+        Webhook, WebhookAttempt, WebhookCreation, WebhookDelivery, WebhookDeliveryStatus,
+        WebhookDispatch,
+    },
+    types::{AppError, AppResult},
+};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use sqlx::{PgExecutor, types::Json};
+
+pub struct WebhookRepository;
+
+impl WebhookRepository {
+    /// Returns all webhooks, oldest first
+    #[instrument(skip(executor))]
+    pub async fn get_all<'e>(executor: impl PgExecutor<'e>) -> AppResult<Vec<Webhook>> {
+        Ok(sqlx::query_as!(
+            Webhook,
+            r#"
+                SELECT id, url, secret, event_types, created_at, updated_at
+                FROM webhook
+                ORDER BY created_at, id
+            "#
+        )
+        .fetch_all(executor)
+        .await?)
+    }
+
+    /// Returns a webhook
+    #[instrument(skip(executor))]
+    pub async fn get_by_id<'e>(
+        executor: impl PgExecutor<'e>,
+        id: String,
+    ) -> AppResult<Option<Webhook>> {
+        Ok(sqlx::query_as!(
+            Webhook,
+            r#"
+                SELECT id, url, secret, event_types, created_at, updated_at
+                FROM webhook
+                WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(executor)
+        .await?)
+    }
+
+    /// Add a new webhook
+    #[instrument(skip(executor, webhook))]
+    pub async fn create<'e>(executor: impl PgExecutor<'e>, webhook: &Webhook) -> AppResult<()> {
+        sqlx::query!(
+            r#"
+                INSERT INTO webhook (id, url, secret, event_types, created_at)
+                VALUES ($1, $2, $3, $4, $5)
+            "#,
+            webhook.id,
+            webhook.url,
+            webhook.secret,
+            &webhook.event_types,
+            webhook.created_at,
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Replace a webhook, and return it or `None` if it does not exist
+    #[instrument(skip(executor, webhook))]
+    pub async fn update<'e>(
+        executor: impl PgExecutor<'e>,
+        id: String,
+        webhook: &WebhookCreation,
+    ) -> AppResult<Option<Webhook>> {
+        Ok(sqlx::query_as!(
+            Webhook,
+            r#"
+                UPDATE webhook
+                SET url = $2, secret = $3, event_types = $4, updated_at = $5
+                WHERE id = $1
+                RETURNING id, url, secret, event_types, created_at, updated_at
+            "#,
+            id,
+            webhook.url,
+            webhook.secret,
+            &webhook.event_types,
+            Some(Utc::now()),
+        )
+        .fetch_optional(executor)
+        .await?)
+    }
+
+    /// Delete a webhook with its deliveries
+    #[instrument(skip(executor))]
+    pub async fn delete<'e>(executor: impl PgExecutor<'e>, id: String) -> AppResult<u64> {
+        let result = sqlx::query!("DELETE FROM webhook WHERE id = $1", id)
+            .execute(executor)
+            .await
+            .map_err(AppError::from_referenced)?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Create a pending delivery of an event for each webhook subscribed to its type
+    #[instrument(skip(executor, payload))]
+    pub async fn enqueue<'e>(
+        executor: impl PgExecutor<'e>,
+        event_type: &str,
+        payload: &Value,
+    ) -> AppResult<u64> {
+        let now = Utc::now();
+        let result = sqlx::query!(
+            r#"
+                INSERT INTO webhook_delivery
+                    (webhook_id, event_type, payload, status, attempts, next_attempt_at, created_at)
+                SELECT id, $1, $2, $3, 0, $4, $4
+                FROM webhook
+                WHERE cardinality(event_types) = 0
+                    OR $1::varchar = ANY(event_types)
+                ORDER BY created_at, id
+            "#,
+            event_type,
+            payload,
+            WebhookDeliveryStatus::Pending as _,
+            now,
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Take up to `limit` pending deliveries which are due.
+    ///
+    /// They are not due again before `lease_until`, so that they are not attempted concurrently,
+    /// but are retried if the attempt is not recorded by then.
+    #[instrument(skip(executor))]
+    pub async fn claim<'e>(
+        executor: impl PgExecutor<'e>,
+        limit: i64,
+        lease_until: DateTime<Utc>,
+    ) -> AppResult<Vec<WebhookDispatch>> {
+        Ok(sqlx::query_as!(
+            WebhookDispatch,
+            r#"
+                UPDATE webhook_delivery AS d
+                SET next_attempt_at = $3
+                FROM webhook AS w
+                WHERE w.id = d.webhook_id
+                    AND d.id IN (
+                        SELECT id
+                        FROM webhook_delivery
+                        WHERE status = $1
+                            AND next_attempt_at <= $2
+                        ORDER BY next_attempt_at, id
+                        LIMIT $4
+                        FOR UPDATE SKIP LOCKED
+                    )
+                RETURNING d.id, d.event_type, d.payload AS "payload: Json<Value>", d.attempts,
+                    w.url, w.secret
+            "#,
+            WebhookDeliveryStatus::Pending as _,
+            Utc::now(),
+            lease_until,
+            limit,
+        )
+        .fetch_all(executor)
+        .await?)
+    }
+
+    /// Log an attempt of a delivery
+    #[instrument(skip(executor))]
+    pub async fn add_attempt<'e>(
+        executor: impl PgExecutor<'e>,
+        attempt: &WebhookAttempt,
+    ) -> AppResult<()> {
+        sqlx::query!(
+            r#"
+                INSERT INTO webhook_attempt
+                    (delivery_id, attempt, status_code, error, duration_ms, created_at)
+                VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            attempt.delivery_id,
+            attempt.attempt,
+            attempt.status_code,
+            attempt.error,
+            attempt.duration_ms,
+            attempt.created_at,
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Save the state of a delivery after an attempt
+    #[instrument(skip(executor))]
+    pub async fn update_delivery<'e>(
+        executor: impl PgExecutor<'e>,
+        id: i64,
+        status: WebhookDeliveryStatus,
+        attempts: i32,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) -> AppResult<()> {
+        sqlx::query!(
+            r#"
+                UPDATE webhook_delivery
+                SET status = $2, attempts = $3, next_attempt_at = $4, updated_at = $5
+                WHERE id = $1
+            "#,
+            id,
+            status as _,
+            attempts,
+            next_attempt_at,
+            Utc::now(),
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Make a dead delivery pending again, for a new series of attempts
+    #[instrument(skip(executor))]
+    pub async fn retry_delivery<'e>(
+        executor: impl PgExecutor<'e>,
+        webhook_id: String,
+        id: i64,
+    ) -> AppResult<Option<WebhookDelivery>> {
+        let now = Utc::now();
+        Ok(sqlx::query_as!(
+            WebhookDelivery,
+            r#"
+                UPDATE webhook_delivery
+                SET status = $3, attempts = 0, next_attempt_at = $4, updated_at = $4
+                WHERE webhook_id = $1
+                    AND id = $2
+                    AND status = $5
+                RETURNING id, webhook_id, event_type, payload AS "payload: Json<Value>",
+                    status AS "status: WebhookDeliveryStatus", attempts, next_attempt_at,
+                    created_at, updated_at
+            "#,
+            webhook_id,
+            id,
+            WebhookDeliveryStatus::Pending as _,
+            now,
+            WebhookDeliveryStatus::Dead as _,
+        )
+        .fetch_optional(executor)
+        .await?)
+    }
+
+    /// Returns a page of the deliveries of a webhook, newest first
+    #[instrument(skip(executor))]
+    pub async fn get_deliveries<'e>(
+        executor: impl PgExecutor<'e>,
+        webhook_id: String,
+        status: Option<WebhookDeliveryStatus>,
+        limit: i64,
+        offset: i64,
+    ) -> AppResult<Vec<WebhookDelivery>> {
+        Ok(sqlx::query_as!(
+            WebhookDelivery,
+            r#"
+                SELECT id, webhook_id, event_type, payload AS "payload: Json<Value>",
+                    status AS "status: WebhookDeliveryStatus", attempts, next_attempt_at,
+                    created_at, updated_at
+                FROM webhook_delivery
+                WHERE webhook_id = $1
+                    AND ($2::varchar IS NULL OR status = $2)
+                ORDER BY id DESC
+                LIMIT $3 OFFSET $4
+            "#,
+            webhook_id,
+            status as _,
+            limit,
+            offset,
+        )
+        .fetch_all(executor)
+        .await?)
+    }
+
+    /// Returns the number of deliveries of a webhook
+    #[instrument(skip(executor))]
+    pub async fn get_deliveries_total<'e>(
+        executor: impl PgExecutor<'e>,
+        webhook_id: String,
+        status: Option<WebhookDeliveryStatus>,
+    ) -> AppResult<i64> {
+        Ok(sqlx::query_scalar!(
+            r#"
+                SELECT COUNT(*) AS "total!"
+                FROM webhook_delivery
+                WHERE webhook_id = $1
+                    AND ($2::varchar IS NULL OR status = $2)
+            "#,
+            webhook_id,
+            status as _,
+        )
+        .fetch_one(executor)
+        .await?)
+    }
+
+    /// Returns the attempts of several deliveries, in order
+    #[instrument(skip(executor))]
+    pub async fn get_attempts<'e>(
+        executor: impl PgExecutor<'e>,
+        delivery_ids: &[i64],
+    ) -> AppResult<Vec<WebhookAttempt>> {
+        Ok(sqlx::query_as!(
+            WebhookAttempt,
+            r#"
+                SELECT delivery_id, attempt, status_code, error, duration_ms, created_at
+                FROM webhook_attempt
+                WHERE delivery_id = ANY($1)
+                ORDER BY delivery_id, id
+            "#,
+            delivery_ids
+        )
+        .fetch_all(executor)
+        .await?)
+    }
+}
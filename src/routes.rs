@@ -1,9 +1,10 @@
-use crate::handlers;
+use crate::{config::Config, graphql, handlers, layers};
 use axum::{
-    Router,
+    Extension, Router, middleware,
     response::Redirect,
-    routing::{delete, get, post, put},
+    routing::{delete, get, patch, post, put},
 };
+use tower_http::compression::CompressionLayer;
 
 pub fn web() -> Router<()> {
     Router::new()
@@ -14,11 +15,72 @@ pub fn web() -> Router<()> {
         .route("/health-check", get(handlers::web::health_check))
 }
 
+/// REST API, served under the prefix of each version and under `/api` for the negotiated one
 pub fn api() -> Router<()> {
+    Router::new()
+        .nest("/book", book())
+        .nest("/webhook", webhook())
+}
+
+pub fn webhook() -> Router<()> {
+    Router::new()
+        .route("/", post(handlers::webhook::create))
+        .route("/", get(handlers::webhook::get_all))
+        .route("/{id}", get(handlers::webhook::get_by_id))
+        .route("/{id}", put(handlers::webhook::update))
+        .route("/{id}", delete(handlers::webhook::delete))
+        .route("/{id}/deliveries", get(handlers::webhook::get_deliveries))
+        .route(
+            "/{id}/deliveries/{delivery_id}/retry",
+            post(handlers::webhook::retry_delivery),
+        )
+        .route_layer(middleware::from_fn(layers::negotiation::require_acceptable))
+        .route_layer(middleware::from_fn(layers::jsonapi::without_json_api))
+}
+
+pub fn ws() -> Router<()> {
+    Router::new().route("/ws", get(handlers::ws::connect))
+}
+
+pub fn book() -> Router<()> {
+    let without_json_api = || middleware::from_fn(layers::jsonapi::without_json_api);
+
     Router::new()
         .route("/", post(handlers::book::create))
         .route("/", get(handlers::book::get_all))
+        .route("/trash", get(handlers::book::get_trash))
         .route("/{id}", get(handlers::book::get_by_id))
         .route("/{id}", put(handlers::book::update))
+        .route("/{id}", patch(handlers::book::patch))
         .route("/{id}", delete(handlers::book::delete))
+        .route("/{id}/restore", post(handlers::book::restore))
+        .route("/{id}/revert/{revision}", post(handlers::book::revert))
+        .route_layer(middleware::from_fn(layers::jsonapi::book_documents))
+        // The batch results, import reports and histories are not resources
+        .route(
+            "/batch",
+            post(handlers::book::batch).layer(without_json_api()),
+        )
+        .route(
+            "/import",
+            post(handlers::book::import).layer(without_json_api()),
+        )
+        .route(
+            "/{id}/history",
+            get(handlers::book::get_history).layer(without_json_api()),
+        )
+        .route_layer(middleware::from_fn(layers::negotiation::require_acceptable))
+        // The export negotiates its own formats
+        .route(
+            "/export",
+            get(handlers::book::export).layer(CompressionLayer::new()),
+        )
+        // The event stream is always sent as `text/event-stream`
+        .route("/events", get(handlers::book::events))
+}
+
+pub fn graphql(settings: &Config) -> Router<()> {
+    Router::new()
+        .route("/graphql", post(handlers::graphql::execute))
+        .layer(Extension(graphql::schema(settings)))
 }
@@ -1,22 +1,36 @@
 use crate::{
     config::{Config, databases, logger},
-    layers::{self, MakeRequestUuid, prometheus::PrometheusMetric},
-    routes,
+    events::EventBus,
+    grpc,
+    layers::{
+        self, MakeRequestUuid, idempotency::IdempotencyLayer, prometheus::PrometheusMetric,
+        versioning::ApiVersioning,
+    },
+    notifications::Notifications,
+    outbox::OutboxRelay,
+    routes, tasks,
+    utils::versioning::{API_BASE_PATH, ApiVersion},
 };
 use axum::{Extension, Router, middleware, routing::get};
-use color_eyre::Result;
+use color_eyre::{Report, Result};
+use sqlx::PgPool;
 use std::future::ready;
 use std::net::SocketAddr;
+use std::time::Duration;
 use tokio::signal;
 use tower::ServiceBuilder;
 use tower_http::{ServiceBuilderExt, services::ServeDir};
 
-/// Starts API server
+/// Starts API server, and the gRPC server if enabled
 pub async fn start_server() -> Result<()> {
     color_eyre::install()?;
 
     let settings = Config::from_env()?;
-    let app = get_app(&settings).await?;
+    logger::init(&settings.environment)?;
+
+    let pool = databases::init_db_pool(&settings).await?;
+    let events = EventBus::new(settings.event_buffer_size);
+    let app = get_app(&settings, pool.clone(), events.clone()).await?;
 
     let addr = format!("{}:{}", settings.bind_address, settings.bind_port);
     let listener = tokio::net::TcpListener::bind(&addr).await?;
@@ -28,26 +42,115 @@ pub async fn start_server() -> Result<()> {
     );
 
     // No graceful shutdown in development environment
-    if &settings.environment == "development" {
-        Ok(server.await?)
-    } else {
-        Ok(server.with_graceful_shutdown(shutdown_signal()).await?)
+    let graceful = &settings.environment != "development";
+    let http = async {
+        match graceful {
+            true => server.with_graceful_shutdown(shutdown_signal()).await,
+            false => server.await,
+        }
+        .map_err(Report::from)
+    };
+
+    match settings.grpc_port {
+        0 => http.await,
+        port => {
+            let addr: SocketAddr = format!("{}:{}", settings.bind_address, port).parse()?;
+            info!("Starting gRPC server on {}...", &addr);
+
+            let grpc = async {
+                let server = tonic::transport::Server::builder()
+                    .add_service(grpc::service(pool, &settings, events));
+                match graceful {
+                    true => server.serve_with_shutdown(addr, shutdown_signal()).await,
+                    false => server.serve(addr).await,
+                }
+                .map_err(Report::from)
+            };
+
+            tokio::try_join!(http, grpc).map(|_| ())
+        }
     }
 }
 
-pub async fn get_app(settings: &Config) -> Result<Router> {
-    logger::init(&settings.environment)?;
+pub async fn get_app(settings: &Config, pool: PgPool, events: EventBus) -> Result<Router> {
+    if settings.trash_retention > 0 && settings.trash_purge_interval > 0 {
+        tasks::trash::spawn_purge(
+            pool.clone(),
+            Duration::from_secs(settings.trash_retention),
+            Duration::from_secs(settings.trash_purge_interval),
+        );
+    }
 
-    let pool = databases::init_db_pool(settings).await?;
+    let idempotency = match settings.idempotency_key_ttl {
+        0 => None,
+        ttl => {
+            let ttl = Duration::from_secs(ttl);
+            tasks::idempotency::spawn_purge(pool.clone(), ttl);
 
+            let lock_timeout = match settings.idempotency_lock_timeout {
+                0 => ttl,
+                lock_timeout => Duration::from_secs(lock_timeout),
+            };
+
+            Some(IdempotencyLayer::new(pool.clone(), ttl, lock_timeout))
+        }
+    };
+
+    let versioning = ApiVersioning::new(settings)?;
     let layers = ServiceBuilder::new()
         .set_x_request_id(MakeRequestUuid)
         .layer(layers::logger::LoggerLayer)
-        .propagate_x_request_id();
+        .propagate_x_request_id()
+        .layer(middleware::from_fn(
+            layers::negotiation::content_negotiation,
+        ))
+        .layer(middleware::from_fn_with_state(
+            settings.legacy_error_format,
+            layers::problem::error_context,
+        ))
+        .layer(middleware::from_fn_with_state(
+            versioning,
+            layers::versioning::api_version,
+        ))
+        .option_layer(idempotency);
+
+    let notifications = Notifications::new();
+    tasks::notifications::spawn_listener(pool.clone(), notifications.clone(), events.clone());
+
+    if settings.outbox_poll_interval > 0 {
+        let mut relay = OutboxRelay::new(pool.clone());
+        if settings.webhook_max_attempts > 0 {
+            let (enqueuer, _) = tasks::webhook::spawn_delivery(
+                pool.clone(),
+                settings.webhook_max_attempts,
+                Duration::from_secs(settings.webhook_retry_base_delay),
+                &settings.webhook_allowed_hosts,
+            );
+            relay = relay.with_consumer(enqueuer);
+        }
+
+        tasks::outbox::spawn_relay(
+            relay,
+            events.clone(),
+            Duration::from_secs(settings.outbox_poll_interval),
+        );
+    }
+    if settings.outbox_retention > 0 {
+        tasks::outbox::spawn_purge(
+            pool.clone(),
+            Duration::from_secs(settings.outbox_retention),
+            settings.outbox_poll_interval > 0,
+        );
+    }
 
-    let mut app = Router::new().nest("/api/v1/book", routes::api());
+    let mut app = Router::new().nest(API_BASE_PATH, routes::api());
+    for version in ApiVersion::ALL {
+        app = app.nest(&version.prefix(), routes::api());
+    }
 
     app = app.merge(routes::web());
+    app = app.merge(routes::ws());
+    app = app.merge(routes::graphql(settings));
 
     if settings.prometheus_metrics_enabled {
         let handle = PrometheusMetric::get_handle()?;
@@ -63,6 +166,9 @@ pub async fn get_app(settings: &Config) -> Result<Router> {
         .fallback_service(ServeDir::new("assets").append_index_html_on_directories(true))
         .layer(middleware::from_fn(layers::override_http_errors))
         .layer(Extension(pool))
+        .layer(Extension(events))
+        .layer(Extension(notifications))
+        .layer(Extension(settings.clone()))
         .layer(layers);
 
     Ok(app)
@@ -0,0 +1,20 @@
+use crate::repositories::idempotency::IdempotencyKeyRepository;
+use sqlx::PgPool;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Periodically remove expired idempotency keys
+pub fn spawn_purge(pool: PgPool, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+
+            match IdempotencyKeyRepository::purge(&pool).await {
+                Ok(0) => (),
+                Ok(count) => info!("{count} expired idempotency key(s) purged"),
+                Err(err) => error!("Failed to purge idempotency keys: {err}"),
+            }
+        }
+    })
+}
@@ -0,0 +1,5 @@
+pub mod idempotency;
+pub mod notifications;
+pub mod outbox;
+pub mod trash;
+pub mod webhook;
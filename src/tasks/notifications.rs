@@ -0,0 +1,262 @@
+use crate::{
+    events::EventBus,
+    models::outbox::OutboxEvent,
+    notifications::{BOOK_CHANGES_CHANNEL, ChangeNotice, Notifications},
+    repositories::outbox::OutboxRepository,
+    types::AppResult,
+};
+use sqlx::{PgPool, postgres::PgListener};
+use std::{collections::BTreeMap, time::Duration};
+use tokio::{task::JoinHandle, time::Instant};
+
+/// Number of missed changes replayed at once after a reconnection
+const REPLAY_BATCH_SIZE: i64 = 100;
+
+/// Maximum number of gaps tracked in the sequence of the outbox IDs
+const MAX_GAPS: usize = 1000;
+
+/// Duration after which a gap is considered as a rolled back change
+const GAP_RETENTION: Duration = Duration::from_secs(600);
+
+/// Delay before the first reconnection attempt after a failure, doubled after each one
+const RECONNECT_MIN_DELAY: Duration = Duration::from_millis(500);
+
+/// Maximum delay between two reconnection attempts
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Position of the listener in the outbox
+#[derive(Default)]
+struct Cursor {
+    /// ID of the last published change
+    last_id: Option<i64>,
+    /// IDs below `last_id` which have not been published yet, with the time they were skipped:
+    /// their changes may be rolled back, or still in progress
+    gaps: BTreeMap<i64, Instant>,
+    /// Number of gaps which could not be tracked, since the last reconnection
+    untracked: usize,
+}
+
+impl Cursor {
+    /// Move the cursor to a published change, and track the IDs it skips
+    fn advance(&mut self, id: i64) {
+        match self.last_id {
+            Some(last_id) if id > last_id => {
+                let now = Instant::now();
+                for gap in last_id + 1..id {
+                    match self.gaps.len() < MAX_GAPS {
+                        true => {
+                            self.gaps.insert(gap, now);
+                        }
+                        false => self.untracked += 1,
+                    }
+                }
+                self.last_id = Some(id);
+            }
+            Some(_) => {
+                self.gaps.remove(&id);
+            }
+            None => self.last_id = Some(id),
+        }
+    }
+
+    /// Forget the gaps which have been open for longer than `GAP_RETENTION`
+    fn expire_gaps(&mut self) {
+        let expired = self
+            .gaps
+            .iter()
+            .filter(|(_, at)| at.elapsed() >= GAP_RETENTION)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        if !expired.is_empty() {
+            debug!("Outbox IDs {expired:?} considered as rolled back");
+        }
+        for id in expired {
+            self.gaps.remove(&id);
+        }
+    }
+}
+
+/// Listen to the book change notifications, and publish the changes to `events`.
+///
+/// The connection is reestablished whenever it is lost, and the changes notified in the meantime
+/// are replayed from the outbox. The changes which are committed out of ID order are tracked as
+/// gaps in the sequence of the IDs, and replayed as well; the subscribers are asked to resync when
+/// some gaps cannot be tracked.
+pub fn spawn_listener(
+    pool: PgPool,
+    notifications: Notifications,
+    events: EventBus,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut cursor = Cursor::default();
+        let mut disconnected_at = None;
+        let mut delay = RECONNECT_MIN_DELAY;
+
+        loop {
+            let result = listen(
+                &pool,
+                &notifications,
+                &events,
+                &mut cursor,
+                &mut disconnected_at,
+            )
+            .await;
+            // The disconnection starts with the connection loss, and lasts until a listener connects again
+            if cursor.last_id.is_some() && disconnected_at.is_none() {
+                disconnected_at = Some(Instant::now());
+            }
+
+            match result {
+                Ok(()) => {
+                    warn!("Connection of the change notifications listener lost, reconnecting");
+                    delay = RECONNECT_MIN_DELAY;
+                }
+                Err(err) => {
+                    error!(
+                        "Change notifications listener failed, reconnecting in {delay:?}: {err}"
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(RECONNECT_MAX_DELAY);
+                }
+            }
+        }
+    })
+}
+
+/// Listen until the connection is lost, after replaying the changes missed since the `cursor`
+async fn listen(
+    pool: &PgPool,
+    notifications: &Notifications,
+    events: &EventBus,
+    cursor: &mut Cursor,
+    disconnected_at: &mut Option<Instant>,
+) -> AppResult<()> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.eager_reconnect(false);
+    listener.listen(BOOK_CHANGES_CHANNEL).await?;
+
+    match cursor.last_id {
+        None => cursor.advance(OutboxRepository::get_last_id(pool).await?),
+        Some(after_id) => {
+            let missed = replay_gaps(pool, notifications, events, cursor).await?
+                + replay(pool, notifications, events, cursor).await?;
+            let duration = disconnected_at
+                .take()
+                .map(|at| at.elapsed())
+                .unwrap_or_default();
+            match missed {
+                0 => info!("No change notification missed during {duration:?} without connection"),
+                _ => warn!(
+                    "{missed} change notification(s) after ID {after_id} missed during {duration:?} without connection, replayed from the outbox"
+                ),
+            }
+        }
+    }
+    notifications.connected();
+    info!("Listening to the book change notifications");
+
+    while let Some(notification) = listener.try_recv().await? {
+        let notice = match serde_json::from_str::<ChangeNotice>(notification.payload()) {
+            Ok(notice) => notice,
+            Err(err) => {
+                warn!("Invalid change notification: {err}");
+                continue;
+            }
+        };
+
+        match OutboxRepository::get_by_id(pool, notice.id).await? {
+            Some(event) => publish(notifications, events, cursor, [event]),
+            None => warn!(
+                "Outbox event {} of a change notification not found",
+                notice.id
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+/// Replay the changes committed in the gaps of the `cursor`, and return their number.
+///
+/// The subscribers are asked to resync if some gaps could not be tracked, since their changes
+/// may have been missed.
+async fn replay_gaps(
+    pool: &PgPool,
+    notifications: &Notifications,
+    events: &EventBus,
+    cursor: &mut Cursor,
+) -> AppResult<usize> {
+    let gaps = cursor.gaps.keys().copied().collect::<Vec<_>>();
+    let batch = match gaps.is_empty() {
+        true => vec![],
+        false => OutboxRepository::get_by_ids(pool, &gaps).await?,
+    };
+    let count = batch.len();
+    if count > 0 {
+        warn!(
+            "{count} change(s) committed out of order among the outbox IDs {gaps:?}, replayed from the outbox"
+        );
+    }
+    publish(notifications, events, cursor, batch);
+    cursor.expire_gaps();
+    if !cursor.gaps.is_empty() {
+        info!(
+            "Outbox IDs {:?} still not committed",
+            cursor.gaps.keys().collect::<Vec<_>>()
+        );
+    }
+
+    if cursor.untracked > 0 {
+        warn!(
+            "{} gap(s) in the outbox IDs could not be tracked, asking the subscribers to resync",
+            cursor.untracked
+        );
+        cursor.untracked = 0;
+        events.resync();
+    }
+
+    Ok(count)
+}
+
+/// Replay the changes made after the last published one, and return their number
+async fn replay(
+    pool: &PgPool,
+    notifications: &Notifications,
+    events: &EventBus,
+    cursor: &mut Cursor,
+) -> AppResult<usize> {
+    let mut count = 0;
+    loop {
+        let batch = OutboxRepository::get_since(
+            pool,
+            cursor.last_id.unwrap_or_default(),
+            REPLAY_BATCH_SIZE,
+        )
+        .await?;
+        let size = batch.len();
+        count += size;
+        publish(notifications, events, cursor, batch);
+
+        if size < REPLAY_BATCH_SIZE as usize {
+            return Ok(count);
+        }
+    }
+}
+
+fn publish(
+    notifications: &Notifications,
+    events: &EventBus,
+    cursor: &mut Cursor,
+    batch: impl IntoIterator<Item = OutboxEvent>,
+) {
+    cursor.expire_gaps();
+
+    let mut revisions = vec![];
+    for event in batch {
+        cursor.advance(event.id);
+        notifications.notify(ChangeNotice::from(&event));
+        revisions.push(event.revision);
+    }
+
+    events.publish(revisions);
+}
@@ -0,0 +1,66 @@
+use crate::{events::EventBus, outbox::OutboxRelay, repositories::outbox::OutboxRepository};
+use chrono::{TimeDelta, Utc};
+use futures::{StreamExt, pin_mut};
+use sqlx::PgPool;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Number of changes relayed at once
+const RELAY_BATCH_SIZE: i64 = 100;
+
+/// Interval between the purges of the processed changes
+const PURGE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Relay the outbox every `interval`, and as soon as a change is published by this instance
+pub fn spawn_relay(relay: OutboxRelay, events: EventBus, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let events = events.subscribe(None);
+        pin_mut!(events);
+        let mut interval = tokio::time::interval(interval);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => (),
+                Some(_) = events.next() => (),
+            }
+
+            loop {
+                match relay.relay(RELAY_BATCH_SIZE).await {
+                    Ok(count) if count < RELAY_BATCH_SIZE as usize => break,
+                    Ok(_) => (),
+                    Err(err) => {
+                        error!("Failed to relay the outbox: {err}");
+                        break;
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Periodically remove the changes which have been processed for longer than `retention`.
+///
+/// Without relay (`relayed` unset), the changes are never processed: they are removed once they
+/// are older than `retention`.
+pub fn spawn_purge(pool: PgPool, retention: Duration, relayed: bool) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            let processed_before = match TimeDelta::from_std(retention) {
+                Ok(retention) => Utc::now() - retention,
+                Err(err) => {
+                    error!("Invalid outbox retention: {err}");
+                    return;
+                }
+            };
+
+            match OutboxRepository::purge(&pool, processed_before, !relayed).await {
+                Ok(0) => (),
+                Ok(count) => info!("{count} outbox change(s) purged"),
+                Err(err) => error!("Failed to purge the outbox: {err}"),
+            }
+        }
+    })
+}
@@ -0,0 +1,29 @@
+use crate::repositories::book::BookRepository;
+use chrono::{TimeDelta, Utc};
+use sqlx::PgPool;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Periodically remove books which have been in the trash for longer than `retention`
+pub fn spawn_purge(pool: PgPool, retention: Duration, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+
+            let deleted_before = match TimeDelta::from_std(retention) {
+                Ok(retention) => Utc::now() - retention,
+                Err(err) => {
+                    error!("Invalid trash retention: {err}");
+                    return;
+                }
+            };
+
+            match BookRepository::purge(&pool, deleted_before).await {
+                Ok(0) => (),
+                Ok(count) => info!("{count} book(s) purged from the trash"),
+                Err(err) => error!("Failed to purge the trash: {err}"),
+            }
+        }
+    })
+}
@@ -0,0 +1,297 @@
+//! Delivery of the book changes to the webhooks.
+//!
+//! Each change relayed from the outbox becomes a pending delivery for every webhook subscribed
+//! to its type, in the relay transaction. Pending deliveries are sent as signed `POST` requests;
+//! a failed attempt is retried with an exponential backoff, and the delivery is marked as dead
+//! after `max_attempts` failures. Deliveries are sent at least once, and not necessarily in order:
+//! receivers can use the `X-Webhook-Id` header and the `event_id` of the payload to deduplicate
+//! and reorder them.
+//!
+//! Deliveries are only sent to public addresses, checked after the DNS resolution, unless their
+//! host is allowed; redirections and proxies are not followed.
+
+use crate::{
+    APP_NAME,
+    models::{
+        outbox::OutboxEvent,
+        webhook::{WebhookAttempt, WebhookDeliveryStatus, WebhookDispatch},
+    },
+    outbox::OutboxConsumer,
+    repositories::webhook::WebhookRepository,
+    types::AppResult,
+    utils::webhook::{
+        check_url, is_allowed_host, is_public_ip, parse_allowed_hosts, retry_delay, signature,
+    },
+};
+use chrono::{TimeDelta, Utc};
+use futures::future::{BoxFuture, join_all};
+use reqwest::{
+    Client,
+    dns::{Addrs, Name, Resolve, Resolving},
+    header::CONTENT_TYPE,
+    redirect,
+};
+use serde_json::json;
+use sqlx::{PgConnection, PgPool};
+use std::{sync::Arc, time::Duration};
+use tokio::{sync::Notify, task::JoinHandle, time::Instant};
+
+/// Number of deliveries attempted concurrently
+const DISPATCH_BATCH_SIZE: i64 = 20;
+
+/// Time given to an endpoint to answer
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time after which a delivery whose attempt has not been recorded is attempted again
+const DELIVERY_LEASE: TimeDelta = TimeDelta::seconds(60);
+
+/// Interval between the checks for deliveries which are due to be retried
+const DISPATCH_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Outbox consumer which creates the deliveries of the book changes
+pub struct WebhookEnqueuer {
+    enqueued: Arc<Notify>,
+}
+
+impl OutboxConsumer for WebhookEnqueuer {
+    fn consume<'a>(
+        &'a self,
+        connection: &'a mut PgConnection,
+        events: &'a [OutboxEvent],
+    ) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            for event in events {
+                let event_type = event.revision.operation.as_str();
+                let payload = json!({
+                    "event_id": event.id,
+                    "type": event_type,
+                    "data": event.revision,
+                });
+
+                WebhookRepository::enqueue(&mut *connection, event_type, &payload).await?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn committed(&self) {
+        self.enqueued.notify_one();
+    }
+}
+
+/// DNS resolver of the deliveries, which refuses the domains resolved to non-public addresses
+struct PublicResolver {
+    allowed_hosts: Arc<[String]>,
+}
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_owned();
+        let allowed = is_allowed_host(&host, &self.allowed_hosts);
+
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .collect::<Vec<_>>();
+            if !allowed && let Some(addr) = addrs.iter().find(|addr| !is_public_ip(addr.ip())) {
+                return Err(format!(
+                    "host `{host}` resolves to the non-public address {}",
+                    addr.ip()
+                )
+                .into());
+            }
+
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Send the webhook deliveries, which are created by the returned outbox consumer.
+///
+/// The comma-separated `allowed_hosts` may be targeted even if they are not public addresses.
+pub fn spawn_delivery(
+    pool: PgPool,
+    max_attempts: u32,
+    retry_base_delay: Duration,
+    allowed_hosts: &str,
+) -> (WebhookEnqueuer, JoinHandle<()>) {
+    let enqueued = Arc::new(Notify::new());
+    let enqueuer = WebhookEnqueuer {
+        enqueued: enqueued.clone(),
+    };
+
+    let allowed_hosts = Arc::<[String]>::from(parse_allowed_hosts(allowed_hosts));
+    let client = match Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .user_agent(APP_NAME)
+        .redirect(redirect::Policy::none())
+        .no_proxy()
+        .dns_resolver(PublicResolver {
+            allowed_hosts: allowed_hosts.clone(),
+        })
+        .build()
+    {
+        Ok(client) => client,
+        Err(err) => {
+            error!("Failed to build the webhook HTTP client: {err}");
+            return (enqueuer, tokio::spawn(async {}));
+        }
+    };
+
+    let handle = tokio::spawn(dispatch(
+        pool,
+        client,
+        allowed_hosts,
+        enqueued,
+        max_attempts,
+        retry_base_delay,
+    ));
+
+    (enqueuer, handle)
+}
+
+/// Attempt the due deliveries, as soon as they are created or due to be retried
+async fn dispatch(
+    pool: PgPool,
+    client: Client,
+    allowed_hosts: Arc<[String]>,
+    enqueued: Arc<Notify>,
+    max_attempts: u32,
+    retry_base_delay: Duration,
+) {
+    let mut interval = tokio::time::interval(DISPATCH_POLL_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => (),
+            _ = enqueued.notified() => (),
+        }
+
+        loop {
+            let deliveries = match WebhookRepository::claim(
+                &pool,
+                DISPATCH_BATCH_SIZE,
+                Utc::now() + DELIVERY_LEASE,
+            )
+            .await
+            {
+                Ok(deliveries) => deliveries,
+                Err(err) => {
+                    error!("Failed to get the due webhook deliveries: {err}");
+                    break;
+                }
+            };
+            let count = deliveries.len();
+
+            join_all(deliveries.into_iter().map(|delivery| async {
+                if let Err(err) = attempt(
+                    &pool,
+                    &client,
+                    &allowed_hosts,
+                    delivery,
+                    max_attempts,
+                    retry_base_delay,
+                )
+                .await
+                {
+                    error!("Failed to record a webhook delivery attempt: {err}");
+                }
+            }))
+            .await;
+
+            if count < DISPATCH_BATCH_SIZE as usize {
+                break;
+            }
+        }
+    }
+}
+
+/// Send a delivery and record the attempt
+async fn attempt(
+    pool: &PgPool,
+    client: &Client,
+    allowed_hosts: &[String],
+    delivery: WebhookDispatch,
+    max_attempts: u32,
+    retry_base_delay: Duration,
+) -> AppResult<()> {
+    let body = delivery.payload.0.to_string().into_bytes();
+    let timestamp = Utc::now().timestamp();
+    let created_at = Utc::now();
+    let start = Instant::now();
+
+    // The IP addresses of the URL are checked here, those of the domains by the resolver
+    let result = match check_url(&delivery.url, allowed_hosts) {
+        Ok(()) => client
+            .post(&delivery.url)
+            .header(CONTENT_TYPE, "application/json")
+            .header("X-Webhook-Id", delivery.id.to_string())
+            .header("X-Webhook-Event", &delivery.event_type)
+            .header("X-Webhook-Timestamp", timestamp.to_string())
+            .header(
+                "X-Webhook-Signature",
+                signature(&delivery.secret, timestamp, &body),
+            )
+            .body(body)
+            .send()
+            .await
+            .map_err(|err| err.to_string()),
+        Err(err) => Err(format!("refused URL: {err}")),
+    };
+
+    let duration_ms = start.elapsed().as_millis().try_into().unwrap_or(i64::MAX);
+    let (status_code, error) = match result {
+        Ok(response) if response.status().is_success() => {
+            (Some(response.status().as_u16() as i16), None)
+        }
+        Ok(response) => (
+            Some(response.status().as_u16() as i16),
+            Some(format!("unexpected status {}", response.status())),
+        ),
+        Err(err) => (None, Some(err)),
+    };
+
+    let attempts = delivery.attempts + 1;
+    let (status, next_attempt_at) = match error {
+        None => (WebhookDeliveryStatus::Succeeded, None),
+        Some(_) if attempts as u32 >= max_attempts => (WebhookDeliveryStatus::Dead, None),
+        Some(_) => {
+            let delay = retry_delay(retry_base_delay, attempts as u32);
+            (
+                WebhookDeliveryStatus::Pending,
+                Some(Utc::now() + TimeDelta::from_std(delay).unwrap_or(TimeDelta::MAX)),
+            )
+        }
+    };
+    if status == WebhookDeliveryStatus::Dead {
+        warn!(
+            "Webhook delivery {} is dead after {attempts} attempts",
+            delivery.id
+        );
+    }
+
+    let mut transaction = pool.begin().await?;
+    WebhookRepository::add_attempt(
+        &mut *transaction,
+        &WebhookAttempt {
+            delivery_id: delivery.id,
+            attempt: attempts,
+            status_code,
+            error,
+            duration_ms,
+            created_at,
+        },
+    )
+    .await?;
+    WebhookRepository::update_delivery(
+        &mut *transaction,
+        delivery.id,
+        status,
+        attempts,
+        next_attempt_at,
+    )
+    .await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
@@ -1,23 +1,51 @@
+use crate::utils::{
+    jsonapi::{JSON_API_MEDIA_TYPE, JsonApiErrors},
+    negotiation::ContentFormat,
+    problem::{ErrorContext, PROBLEM_TYPE_BASE, problem_media_type},
+};
 use axum::{
     Json,
-    http::StatusCode,
+    http::{StatusCode, header::CONTENT_TYPE},
     response::{IntoResponse, Response},
 };
 use color_eyre::eyre::Result as EyreResult;
 use derive_more::{Display, Error};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use serde_json::{Map, Value, json};
+use sqlx::{
+    error::{DatabaseError, ErrorKind},
+    postgres::PgDatabaseError,
+};
 
 /// Custom Result type for `AppError`
 pub type AppResult<T> = EyreResult<T, AppError>;
 
 /// Represents the custom error message
-#[derive(Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct AppErrorMessage {
     pub code: u16,
     pub message: String,
 }
 
+/// Error response body, as defined by RFC 9457
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ProblemDetails {
+    /// URI reference which identifies the problem type
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    /// Short summary of the problem type
+    pub title: String,
+    pub status: u16,
+    /// Explanation specific to this occurrence of the problem
+    pub detail: String,
+    /// ID of the request in which the problem occurred
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    /// Extension members, specific to the problem type
+    #[serde(flatten)]
+    pub extensions: Map<String, Value>,
+}
+
 #[derive(Debug)]
 pub enum AppErrorCode {
     InternalError,
@@ -25,6 +53,12 @@ pub enum AppErrorCode {
     NotFound,
     UnprocessableEntity,
     MethodNotAllowed,
+    Conflict,
+    UnsupportedMediaType,
+    PreconditionFailed,
+    PreconditionRequired,
+    PayloadTooLarge,
+    NotAcceptable,
 }
 
 /// Defines available errors
@@ -44,26 +78,213 @@ pub enum AppError {
 
     #[display("Method Not Allowed")]
     MethodNotAllowed,
+
+    #[display("{message}")]
+    Conflict { message: String },
+
+    #[display("{message}")]
+    UnsupportedMediaType { message: String },
+
+    #[display("{message}")]
+    PreconditionFailed { message: String },
+
+    #[display("{message}")]
+    PreconditionRequired { message: String },
+
+    #[display("{message}")]
+    PayloadTooLarge { message: String },
+
+    #[display("{message}")]
+    NotAcceptable { message: String },
+
+    /// Error with extension members, added to its problem details
+    #[display("{error}")]
+    WithExtensions {
+        error: Box<AppError>,
+        extensions: Map<String, Value>,
+    },
 }
 
 // Axum errors
 // ------------
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        let status = match self {
+impl AppError {
+    /// HTTP status code matching the error
+    pub fn status_code(&self) -> StatusCode {
+        match self {
             AppError::InternalError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::NotFound { .. } => StatusCode::NOT_FOUND,
             AppError::BadRequest { .. } => StatusCode::BAD_REQUEST,
-            AppError::MethodNotAllowed { .. } => StatusCode::METHOD_NOT_ALLOWED,
+            AppError::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
             AppError::UnprocessableEntity { .. } => StatusCode::UNPROCESSABLE_ENTITY,
-        };
+            AppError::Conflict { .. } => StatusCode::CONFLICT,
+            AppError::UnsupportedMediaType { .. } => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            AppError::PreconditionFailed { .. } => StatusCode::PRECONDITION_FAILED,
+            AppError::PreconditionRequired { .. } => StatusCode::PRECONDITION_REQUIRED,
+            AppError::PayloadTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::NotAcceptable { .. } => StatusCode::NOT_ACCEPTABLE,
+            AppError::WithExtensions { error, .. } => error.status_code(),
+        }
+    }
+
+    /// Name of the problem type of the error, stable for clients to match on
+    pub fn problem_type(&self) -> &'static str {
+        match self {
+            AppError::InternalError { .. } => "internal-error",
+            AppError::NotFound { .. } => "not-found",
+            AppError::BadRequest { .. } => "bad-request",
+            AppError::MethodNotAllowed => "method-not-allowed",
+            AppError::UnprocessableEntity { .. } => "unprocessable-entity",
+            AppError::Conflict { .. } => "conflict",
+            AppError::UnsupportedMediaType { .. } => "unsupported-media-type",
+            AppError::PreconditionFailed { .. } => "precondition-failed",
+            AppError::PreconditionRequired { .. } => "precondition-required",
+            AppError::PayloadTooLarge { .. } => "payload-too-large",
+            AppError::NotAcceptable { .. } => "not-acceptable",
+            AppError::WithExtensions { error, .. } => error.problem_type(),
+        }
+    }
+
+    /// Add an extension member to the problem details of the error
+    pub fn with_extension(self, name: &str, value: impl Into<Value>) -> Self {
+        match self {
+            AppError::WithExtensions {
+                error,
+                mut extensions,
+            } => {
+                extensions.insert(name.to_owned(), value.into());
+                AppError::WithExtensions { error, extensions }
+            }
+            error => AppError::WithExtensions {
+                error: Box::new(error),
+                extensions: Map::from_iter([(name.to_owned(), value.into())]),
+            },
+        }
+    }
+
+    /// Prefix the message of the error with its `context`, keeping its type and extension members
+    pub fn with_context(self, context: &str) -> Self {
+        match self {
+            AppError::WithExtensions { error, extensions } => AppError::WithExtensions {
+                error: Box::new(error.with_context(context)),
+                extensions,
+            },
+            AppError::MethodNotAllowed => self,
+            error => AppError::from_status(error.status_code(), format!("{context}: {error}"))
+                .unwrap_or(error),
+        }
+    }
 
-        let body = Json(json!(AppErrorMessage {
-            code: status.as_u16(),
-            message: self.to_string(),
-        }));
+    /// Extension member of the error, if it has one
+    pub fn extension(&self, name: &str) -> Option<&Value> {
+        match self {
+            AppError::WithExtensions { extensions, .. } => extensions.get(name),
+            _ => None,
+        }
+    }
 
-        (status, body).into_response()
+    /// Error matching an HTTP status code, if there is one
+    pub fn from_status(status: StatusCode, message: String) -> Option<Self> {
+        Some(match status {
+            StatusCode::INTERNAL_SERVER_ERROR => AppError::InternalError { message },
+            StatusCode::NOT_FOUND => AppError::NotFound { message },
+            StatusCode::BAD_REQUEST => AppError::BadRequest { message },
+            StatusCode::METHOD_NOT_ALLOWED => AppError::MethodNotAllowed,
+            StatusCode::UNPROCESSABLE_ENTITY => AppError::UnprocessableEntity { message },
+            StatusCode::CONFLICT => AppError::Conflict { message },
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => AppError::UnsupportedMediaType { message },
+            StatusCode::PRECONDITION_FAILED => AppError::PreconditionFailed { message },
+            StatusCode::PRECONDITION_REQUIRED => AppError::PreconditionRequired { message },
+            StatusCode::PAYLOAD_TOO_LARGE => AppError::PayloadTooLarge { message },
+            StatusCode::NOT_ACCEPTABLE => AppError::NotAcceptable { message },
+            _ => return None,
+        })
+    }
+}
+
+impl ProblemDetails {
+    /// Problem details of an error, which occurred in the request `instance`
+    pub fn new(error: &AppError, instance: Option<String>) -> Self {
+        let status = error.status_code();
+
+        Self {
+            problem_type: format!("{PROBLEM_TYPE_BASE}{}", error.problem_type()),
+            title: status.canonical_reason().unwrap_or_default().to_owned(),
+            status: status.as_u16(),
+            detail: error.to_string(),
+            instance,
+            extensions: match error {
+                AppError::WithExtensions { extensions, .. } => extensions.clone(),
+                _ => Map::new(),
+            },
+        }
+    }
+}
+
+impl From<&AppError> for AppErrorMessage {
+    fn from(error: &AppError) -> Self {
+        Self {
+            code: error.status_code().as_u16(),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status_code();
+        let context = ErrorContext::current();
+
+        // Errors are rendered in the format negotiated for the request,
+        // as JSON:API error objects whatever the error format for JSON:API clients
+        let format = ContentFormat::current();
+        if format == ContentFormat::JsonApi {
+            let errors = JsonApiErrors::from(ProblemDetails::new(&self, context.request_id));
+            return (status, [(CONTENT_TYPE, JSON_API_MEDIA_TYPE)], Json(errors)).into_response();
+        }
+
+        if context.legacy {
+            let message = AppErrorMessage::from(&self);
+            return match format.serialize(&message) {
+                Ok(body) => (status, [(CONTENT_TYPE, format.media_type())], body).into_response(),
+                Err(_) => (status, Json(json!(message))).into_response(),
+            };
+        }
+
+        let problem = ProblemDetails::new(&self, context.request_id);
+        match format.serialize(&problem) {
+            Ok(body) => {
+                (status, [(CONTENT_TYPE, problem_media_type(format))], body).into_response()
+            }
+            Err(_) => (
+                status,
+                [(CONTENT_TYPE, problem_media_type(ContentFormat::Json))],
+                Json(json!(problem)),
+            )
+                .into_response(),
+        }
+    }
+}
+
+// gRPC errors
+// -----------
+impl From<AppError> for tonic::Status {
+    fn from(error: AppError) -> Self {
+        let message = error.to_string();
+        match error {
+            AppError::WithExtensions { error, .. } => Self::from(*error),
+            AppError::InternalError { .. } => Self::internal(message),
+            AppError::BadRequest { .. }
+            | AppError::UnprocessableEntity { .. }
+            | AppError::UnsupportedMediaType { .. }
+            | AppError::NotAcceptable { .. } => Self::invalid_argument(message),
+            AppError::NotFound { .. } => Self::not_found(message),
+            AppError::MethodNotAllowed => Self::unimplemented(message),
+            AppError::Conflict { .. } => Self::already_exists(message),
+            AppError::PreconditionFailed { .. } | AppError::PreconditionRequired { .. } => {
+                Self::failed_precondition(message)
+            }
+            AppError::PayloadTooLarge { .. } => Self::resource_exhausted(message),
+        }
     }
 }
 
@@ -71,12 +292,65 @@ impl IntoResponse for AppError {
 // -----------
 impl From<sqlx::Error> for AppError {
     fn from(error: sqlx::Error) -> Self {
+        Self::from_sqlx(error, false)
+    }
+}
+
+impl AppError {
+    /// Error of a statement which deletes or updates rows of a referenced table.
+    ///
+    /// PostgreSQL reports the same foreign key violation whichever side of the reference is
+    /// written: it means here that the rows are still referenced (409), rather than that a
+    /// referenced row does not exist (422).
+    pub fn from_referenced(error: sqlx::Error) -> Self {
+        Self::from_sqlx(error, true)
+    }
+
+    fn from_sqlx(error: sqlx::Error, referenced: bool) -> Self {
+        if let Some(error) = error
+            .as_database_error()
+            .and_then(|error| Self::from_database_error(error, referenced))
+        {
+            return error;
+        }
+
         error!("Database error: {error:?}");
 
         Self::InternalError {
             message: "Database Error".to_owned(),
         }
     }
+
+    /// Error of a constraint violation caused by the request data, with the violated constraint
+    fn from_database_error(error: &dyn DatabaseError, referenced: bool) -> Option<Self> {
+        let message = error.message().to_owned();
+        // The detail tells which key is duplicated, or which reference is broken
+        let detail = error
+            .try_downcast_ref::<PgDatabaseError>()
+            .and_then(|error| error.detail())
+            .map(ToOwned::to_owned)
+            .unwrap_or_else(|| message.clone());
+
+        let app_error = match (error.kind(), error.code().as_deref()) {
+            (ErrorKind::UniqueViolation, _) => Self::Conflict { message: detail },
+            // Deleting or updating a row which is still referenced
+            (ErrorKind::ForeignKeyViolation, _) if referenced => Self::Conflict { message: detail },
+            // Referencing a row which does not exist
+            (ErrorKind::ForeignKeyViolation, _) => Self::UnprocessableEntity { message: detail },
+            (ErrorKind::CheckViolation | ErrorKind::NotNullViolation, _) => {
+                Self::UnprocessableEntity { message }
+            }
+            // `string_data_right_truncation` (e.g. too long `varchar`) and `numeric_value_out_of_range`
+            (_, Some("22001" | "22003")) => Self::UnprocessableEntity { message },
+            _ => return None,
+        };
+        warn!("Database constraint violation: {error}");
+
+        Some(match error.constraint() {
+            Some(constraint) => app_error.with_extension("constraint", constraint),
+            None => app_error,
+        })
+    }
 }
 
 impl From<std::num::TryFromIntError> for AppError {
@@ -146,6 +420,24 @@ macro_rules! app_error {
             AppErrorCode::UnprocessableEntity => AppError::UnprocessableEntity {
                 message: String::from("Unprocessable Entity"),
             },
+            AppErrorCode::Conflict => AppError::Conflict {
+                message: String::from("Conflict"),
+            },
+            AppErrorCode::UnsupportedMediaType => AppError::UnsupportedMediaType {
+                message: String::from("Unsupported Media Type"),
+            },
+            AppErrorCode::PreconditionFailed => AppError::PreconditionFailed {
+                message: String::from("Precondition Failed"),
+            },
+            AppErrorCode::PreconditionRequired => AppError::PreconditionRequired {
+                message: String::from("Precondition Required"),
+            },
+            AppErrorCode::PayloadTooLarge => AppError::PayloadTooLarge {
+                message: String::from("Payload Too Large"),
+            },
+            AppErrorCode::NotAcceptable => AppError::NotAcceptable {
+                message: String::from("Not Acceptable"),
+            },
         }
     };
 
@@ -167,6 +459,24 @@ macro_rules! app_error {
             AppErrorCode::UnprocessableEntity => AppError::UnprocessableEntity {
                 message: $message.to_string(),
             },
+            AppErrorCode::Conflict => AppError::Conflict {
+                message: $message.to_string(),
+            },
+            AppErrorCode::UnsupportedMediaType => AppError::UnsupportedMediaType {
+                message: $message.to_string(),
+            },
+            AppErrorCode::PreconditionFailed => AppError::PreconditionFailed {
+                message: $message.to_string(),
+            },
+            AppErrorCode::PreconditionRequired => AppError::PreconditionRequired {
+                message: $message.to_string(),
+            },
+            AppErrorCode::PayloadTooLarge => AppError::PayloadTooLarge {
+                message: $message.to_string(),
+            },
+            AppErrorCode::NotAcceptable => AppError::NotAcceptable {
+                message: $message.to_string(),
+            },
         }
     };
 
@@ -188,6 +498,24 @@ macro_rules! app_error {
             AppErrorCode::UnprocessableEntity => AppError::UnprocessableEntity {
                 message: $message.to_string(),
             },
+            AppErrorCode::Conflict => AppError::Conflict {
+                message: $message.to_string(),
+            },
+            AppErrorCode::UnsupportedMediaType => AppError::UnsupportedMediaType {
+                message: $message.to_string(),
+            },
+            AppErrorCode::PreconditionFailed => AppError::PreconditionFailed {
+                message: $message.to_string(),
+            },
+            AppErrorCode::PreconditionRequired => AppError::PreconditionRequired {
+                message: $message.to_string(),
+            },
+            AppErrorCode::PayloadTooLarge => AppError::PayloadTooLarge {
+                message: $message.to_string(),
+            },
+            AppErrorCode::NotAcceptable => AppError::NotAcceptable {
+                message: $message.to_string(),
+            },
         }
     };
 }
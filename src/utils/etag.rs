@@ -0,0 +1,241 @@
+use crate::{
+    app_error,
+    types::{AppError, AppErrorCode, AppResult},
+    utils::{hash::sha256_hex, negotiation::ContentFormat},
+};
+use axum::http::{
+    HeaderMap,
+    header::{IF_MATCH, IF_MODIFIED_SINCE, IF_NONE_MATCH},
+};
+use chrono::{DateTime, Utc};
+
+/// Format a resource version as a strong `ETag`
+pub fn etag(version: i64) -> String {
+    format!("\"{version}\"")
+}
+
+/// Format a resource version as a strong `ETag` of one of its representations.
+///
+/// The plain JSON representation is tagged with the version alone (e.g. `"3"`), the other ones
+/// with the version followed by the format and the embedded relations (e.g. `"3-csv"` or
+/// `"3-json+revisions"`), so that a representation is never validated by the tag of another one.
+pub fn representation_etag(version: i64, format: ContentFormat, relations: &[&str]) -> String {
+    if format == ContentFormat::Json && relations.is_empty() {
+        return etag(version);
+    }
+
+    let mut variant = String::from(format.name());
+    for relation in relations {
+        variant.push('+');
+        variant.push_str(relation);
+    }
+    format!("\"{version}-{variant}\"")
+}
+
+/// Build a strong `ETag` from a hash of the response content
+pub fn content_etag(content: &[u8]) -> String {
+    format!("\"{}\"", sha256_hex(content))
+}
+
+/// Format a date as an HTTP date (e.g. `Sun, 06 Nov 1994 08:49:37 GMT`)
+pub fn http_date(date: &DateTime<Utc>) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// Check if the client representation is still fresh, in which case `304 Not Modified` can be returned.
+///
+/// `If-None-Match` uses the weak comparison and takes precedence over `If-Modified-Since`,
+/// which is only compared to the second.
+pub fn is_not_modified(
+    headers: &HeaderMap,
+    etag: &str,
+    last_modified: Option<&DateTime<Utc>>,
+) -> bool {
+    let mut values = headers.get_all(IF_NONE_MATCH).iter().peekable();
+    if values.peek().is_some() {
+        let etag = etag.trim_start_matches("W/");
+        return values.any(|value| {
+            value
+                .to_str()
+                .unwrap_or_default()
+                .split(',')
+                .map(|tag| tag.trim())
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+        });
+    }
+
+    match (headers.get(IF_MODIFIED_SINCE), last_modified) {
+        (Some(since), Some(last_modified)) => {
+            match DateTime::parse_from_rfc2822(since.to_str().unwrap_or_default()) {
+                Ok(since) => last_modified.timestamp() <= since.timestamp(),
+                Err(_) => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// Return the versions accepted by the `If-Match` request header.
+///
+/// `None` means that any version is accepted (no header or `*`).
+/// Weak and malformed entity tags never match because `If-Match` uses the strong comparison,
+/// and the tags of all the representations of a version match this version.
+/// If `required` is set, a missing header is rejected with `428 Precondition Required`.
+pub fn if_match_versions(headers: &HeaderMap, required: bool) -> AppResult<Option<Vec<i64>>> {
+    let mut values = headers.get_all(IF_MATCH).iter().peekable();
+    if values.peek().is_none() {
+        return match required {
+            true => Err(app_error!(
+                AppErrorCode::PreconditionRequired,
+                "`If-Match` header is required"
+            )),
+            false => Ok(None),
+        };
+    }
+
+    let mut versions = vec![];
+    for value in values {
+        for tag in value.to_str().unwrap_or_default().split(',') {
+            let tag = tag.trim();
+            if tag == "*" {
+                return Ok(None);
+            }
+
+            if let Some(version) = tag
+                .strip_prefix('"')
+                .and_then(|tag| tag.strip_suffix('"'))
+                .map(|tag| tag.split_once('-').map_or(tag, |(version, _)| version))
+                .and_then(|tag| tag.parse().ok())
+            {
+                versions.push(version);
+            }
+        }
+    }
+
+    Ok(Some(versions))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::http::HeaderValue;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_etag() {
+        assert_eq!(String::from("\"3\""), etag(3));
+    }
+
+    #[test]
+    fn test_representation_etag() {
+        let revisions = ["revisions"];
+        assert_eq!(
+            String::from("\"3\""),
+            representation_etag(3, ContentFormat::Json, &[])
+        );
+        assert_eq!(
+            String::from("\"3-json+revisions\""),
+            representation_etag(3, ContentFormat::Json, &revisions)
+        );
+        assert_eq!(
+            String::from("\"3-csv\""),
+            representation_etag(3, ContentFormat::Csv, &[])
+        );
+        assert_eq!(
+            String::from("\"3-jsonapi+revisions\""),
+            representation_etag(3, ContentFormat::JsonApi, &revisions)
+        );
+    }
+
+    #[test]
+    fn test_content_etag() {
+        assert_eq!(
+            String::from("\"2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae\""),
+            content_etag(b"foo")
+        );
+    }
+
+    #[test]
+    fn test_http_date() {
+        let date = Utc.with_ymd_and_hms(1994, 11, 6, 8, 49, 37).unwrap();
+        assert_eq!(
+            String::from("Sun, 06 Nov 1994 08:49:37 GMT"),
+            http_date(&date)
+        );
+    }
+
+    #[test]
+    fn test_is_not_modified_if_none_match() {
+        let mut headers = HeaderMap::new();
+        assert!(!is_not_modified(&headers, "\"1\"", None));
+
+        headers.insert(IF_NONE_MATCH, HeaderValue::from_static("\"2\", W/\"1\""));
+        assert!(is_not_modified(&headers, "\"1\"", None));
+
+        headers.insert(IF_NONE_MATCH, HeaderValue::from_static("\"2\""));
+        assert!(!is_not_modified(&headers, "\"1\"", None));
+
+        headers.insert(IF_NONE_MATCH, HeaderValue::from_static("*"));
+        assert!(is_not_modified(&headers, "\"1\"", None));
+    }
+
+    #[test]
+    fn test_is_not_modified_if_modified_since() {
+        let last_modified = Utc.with_ymd_and_hms(1994, 11, 6, 8, 49, 37).unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            IF_MODIFIED_SINCE,
+            HeaderValue::from_static("Sun, 06 Nov 1994 08:49:37 GMT"),
+        );
+        assert!(is_not_modified(&headers, "\"1\"", Some(&last_modified)));
+        assert!(!is_not_modified(&headers, "\"1\"", None));
+
+        headers.insert(
+            IF_MODIFIED_SINCE,
+            HeaderValue::from_static("Sun, 06 Nov 1994 08:49:36 GMT"),
+        );
+        assert!(!is_not_modified(&headers, "\"1\"", Some(&last_modified)));
+
+        // `If-None-Match` takes precedence
+        headers.insert(
+            IF_MODIFIED_SINCE,
+            HeaderValue::from_static("Sun, 06 Nov 1994 08:49:37 GMT"),
+        );
+        headers.insert(IF_NONE_MATCH, HeaderValue::from_static("\"2\""));
+        assert!(!is_not_modified(&headers, "\"1\"", Some(&last_modified)));
+    }
+
+    #[test]
+    fn test_if_match_versions_without_header() {
+        let headers = HeaderMap::new();
+
+        assert_eq!(Ok(None), if_match_versions(&headers, false));
+        assert!(matches!(
+            if_match_versions(&headers, true),
+            Err(AppError::PreconditionRequired { .. })
+        ));
+    }
+
+    #[test]
+    fn test_if_match_versions() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            IF_MATCH,
+            HeaderValue::from_static("\"1\", W/\"2\", \"3\", foo"),
+        );
+        assert_eq!(Ok(Some(vec![1, 3])), if_match_versions(&headers, true));
+
+        headers.insert(
+            IF_MATCH,
+            HeaderValue::from_static("\"4-csv\", \"5-json+revisions\""),
+        );
+        assert_eq!(Ok(Some(vec![4, 5])), if_match_versions(&headers, true));
+
+        headers.insert(IF_MATCH, HeaderValue::from_static("*"));
+        assert_eq!(Ok(None), if_match_versions(&headers, true));
+
+        headers.insert(IF_MATCH, HeaderValue::from_static("W/\"2\""));
+        assert_eq!(Ok(Some(vec![])), if_match_versions(&headers, false));
+    }
+}
@@ -1,11 +1,21 @@
 use crate::{
     app_error,
+    models::revision::ChangeContext,
     types::{AppError, AppErrorCode},
+    utils::{
+        jsonapi::{JSON_API_MEDIA_TYPE, invalid_document, resource_attributes},
+        problem::ErrorContext,
+        validation::{deserialization_field_error, invalid_request_data},
+    },
 };
 use axum::{
-    extract::{FromRequestParts, path::ErrorKind, rejection::PathRejection},
+    extract::{FromRequest, FromRequestParts, Request, path::ErrorKind, rejection::PathRejection},
     http::StatusCode,
-    http::{header::HeaderValue, request::Parts},
+    http::{
+        header::{CONTENT_TYPE, HeaderValue},
+        request::Parts,
+    },
+    response::{IntoResponse, Response},
 };
 use serde::de::DeserializeOwned;
 
@@ -26,6 +36,21 @@ where
     }
 }
 
+/// Actor extractor from HTTP headers, used to attribute changes
+pub struct ExtractActor(pub String);
+
+impl<S> FromRequestParts<S> for ExtractActor
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let actor = parts.headers.get("x-actor").map(HeaderValue::as_bytes);
+        ChangeContext::parse_actor(actor).map(ExtractActor)
+    }
+}
+
 // We define our own `Path` extractor that customises the error from `axum::extract::Path`
 pub struct Path<T>(pub T);
 
@@ -51,17 +76,30 @@ where
                                 app_error!(AppErrorCode::BadRequest, kind.to_string())
                             }
 
-                            ErrorKind::ParseErrorAtKey { .. } => {
-                                app_error!(AppErrorCode::BadRequest, kind.to_string())
-                            }
-
-                            ErrorKind::ParseErrorAtIndex { .. } => {
-                                app_error!(AppErrorCode::BadRequest, kind.to_string())
-                            }
-
-                            ErrorKind::ParseError { .. } => {
-                                app_error!(AppErrorCode::BadRequest, kind.to_string())
-                            }
+                            ErrorKind::ParseErrorAtKey {
+                                key,
+                                value,
+                                expected_type,
+                            } => app_error!(AppErrorCode::BadRequest, kind.to_string())
+                                .with_extension("parameter", key.as_str())
+                                .with_extension("value", value.as_str())
+                                .with_extension("expected_type", *expected_type),
+
+                            ErrorKind::ParseErrorAtIndex {
+                                index,
+                                value,
+                                expected_type,
+                            } => app_error!(AppErrorCode::BadRequest, kind.to_string())
+                                .with_extension("index", *index)
+                                .with_extension("value", value.as_str())
+                                .with_extension("expected_type", *expected_type),
+
+                            ErrorKind::ParseError {
+                                value,
+                                expected_type,
+                            } => app_error!(AppErrorCode::BadRequest, kind.to_string())
+                                .with_extension("value", value.as_str())
+                                .with_extension("expected_type", *expected_type),
 
                             ErrorKind::InvalidUtf8InPathParam { .. } => {
                                 app_error!(AppErrorCode::BadRequest, kind.to_string())
@@ -126,3 +164,53 @@ where
         Ok(Query(value))
     }
 }
+
+// We define our own `Json` extractor which reports the invalid fields of the body,
+// and which reads the attributes of the JSON:API request documents
+pub struct Json<T>(pub T);
+
+impl<T, S> FromRequest<S> for Json<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let json_api = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<mime::Mime>().ok())
+            .is_some_and(|mime| mime.essence_str() == JSON_API_MEDIA_TYPE);
+
+        // Content type and syntax errors are reported by axum, as is with the legacy format
+        let value = match axum::Json::<serde_json::Value>::from_request(req, state).await {
+            Ok(value) => value.0,
+            Err(rejection) if ErrorContext::current().legacy => {
+                return Err(rejection.into_response());
+            }
+            Err(rejection) => {
+                let error = AppError::from_status(rejection.status(), rejection.body_text())
+                    .unwrap_or_else(|| {
+                        app_error!(AppErrorCode::InternalError, rejection.body_text())
+                    });
+                return Err(error.into_response());
+            }
+        };
+        let value = match json_api {
+            true => resource_attributes(value).ok_or_else(|| invalid_document().into_response())?,
+            false => value,
+        };
+
+        serde_path_to_error::deserialize(value)
+            .map(Json)
+            .map_err(|err| {
+                invalid_request_data(
+                    AppErrorCode::UnprocessableEntity,
+                    vec![deserialization_field_error(&err)],
+                )
+                .into_response()
+            })
+    }
+}
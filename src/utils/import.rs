@@ -0,0 +1,335 @@
+use crate::{
+    app_error,
+    types::{AppError, AppErrorCode, AppResult},
+};
+use axum::body::Body;
+use futures::{StreamExt, TryStreamExt, stream::BoxStream};
+use serde::de::DeserializeOwned;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+use tokio_util::io::StreamReader;
+
+/// CSV media type
+pub const CSV_CONTENT_TYPE: &str = "text/csv";
+
+/// Newline delimited JSON media types
+pub const NDJSON_CONTENT_TYPES: &[&str] = &["application/x-ndjson", "application/ndjson"];
+
+/// Maximum size of a row (a CSV record or an NDJSON line, with its line break), in bytes
+pub const MAX_ROW_SIZE: usize = 64 * 1024;
+
+/// Stream of the rows of a request body.
+///
+/// An outer error aborts the import (e.g. the body cannot be read),
+/// an inner error only rejects the row.
+pub type RowStream<T> = BoxStream<'static, AppResult<AppResult<T>>>;
+
+/// Parse a CSV or NDJSON request body as a stream of rows, without buffering it.
+///
+/// The format is selected with the request `Content-Type`. CSV bodies must have a header row,
+/// and blank lines are ignored. Rows longer than `MAX_ROW_SIZE` are skipped and rejected with
+/// `413 Payload Too Large`, so that a single row cannot exhaust the memory.
+pub fn parse_rows<T>(content_type: &str, body: Body) -> AppResult<RowStream<T>>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    let mime = content_type
+        .parse::<mime::Mime>()
+        .map_err(|err| app_error!(AppErrorCode::UnsupportedMediaType, err.to_string()))?;
+    let reader = StreamReader::new(body.into_data_stream().map_err(std::io::Error::other));
+
+    match mime.essence_str() {
+        CSV_CONTENT_TYPE => Ok(futures::stream::try_unfold(
+            (RowReader::new(reader, true), None),
+            |(mut reader, mut headers)| async move {
+                loop {
+                    let Some(row) = reader.next_row().await? else {
+                        return Ok(None);
+                    };
+                    let record = match row.and_then(parse_csv_record) {
+                        Ok(Some(record)) => record,
+                        Ok(None) => continue,
+                        Err(err) => return Ok(Some((Err(err), (reader, headers)))),
+                    };
+
+                    let Some(header) = &headers else {
+                        headers = Some(record);
+                        continue;
+                    };
+                    let row = match record.len() == header.len() {
+                        true => record
+                            .deserialize(Some(header))
+                            .map_err(|err| unprocessable_row(err.to_string())),
+                        false => Err(unprocessable_row(format!(
+                            "row has {} fields, but the header row has {}",
+                            record.len(),
+                            header.len()
+                        ))),
+                    };
+                    return Ok(Some((row, (reader, headers))));
+                }
+            },
+        )
+        .boxed()),
+        content_type if NDJSON_CONTENT_TYPES.contains(&content_type) => Ok(
+            futures::stream::try_unfold(RowReader::new(reader, false), |mut reader| async move {
+                loop {
+                    return match reader.next_row().await? {
+                        None => Ok(None),
+                        Some(Ok(line)) if line.trim_ascii().is_empty() => continue,
+                        Some(line) => {
+                            let row = line.and_then(|line| {
+                                serde_json::from_slice(line)
+                                    .map_err(|err| unprocessable_row(err.to_string()))
+                            });
+                            Ok(Some((row, reader)))
+                        }
+                    };
+                }
+            })
+            .boxed(),
+        ),
+        _ => Err(app_error!(
+            AppErrorCode::UnsupportedMediaType,
+            format!(
+                "content type must be `{CSV_CONTENT_TYPE}` or one of `{}`",
+                NDJSON_CONTENT_TYPES.join("`, `")
+            )
+        )),
+    }
+}
+
+/// Error of a row which cannot be parsed
+fn unprocessable_row(message: String) -> AppError {
+    app_error!(AppErrorCode::UnprocessableEntity, message)
+}
+
+/// Parse a CSV record, `None` if the row is blank
+fn parse_csv_record(row: &[u8]) -> AppResult<Option<csv::StringRecord>> {
+    let mut record = csv::StringRecord::new();
+    let read = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .trim(csv::Trim::All)
+        .from_reader(row)
+        .read_record(&mut record)
+        .map_err(|err| unprocessable_row(err.to_string()))?;
+
+    // The positions are relative to the row, which would be misleading in the errors
+    record.set_position(None);
+    Ok(read.then_some(record))
+}
+
+/// Reader which splits a body in rows, one per line, and skips the rows longer than `MAX_ROW_SIZE`
+struct RowReader<R> {
+    reader: R,
+    /// Line breaks between double quotes are part of the row (CSV)
+    quoted: bool,
+    row: Vec<u8>,
+}
+
+impl<R: AsyncBufRead + Unpin> RowReader<R> {
+    fn new(reader: R, quoted: bool) -> Self {
+        Self {
+            reader,
+            quoted,
+            row: vec![],
+        }
+    }
+
+    /// Read the next row, `None` at the end of the body.
+    ///
+    /// A row longer than `MAX_ROW_SIZE` is read up to its end without being kept, and returned
+    /// as an error.
+    async fn next_row(&mut self) -> AppResult<Option<AppResult<&[u8]>>> {
+        self.row.clear();
+        let mut in_quotes = false;
+        let mut too_long = false;
+        let mut empty = true;
+
+        loop {
+            let buffer = self
+                .reader
+                .fill_buf()
+                .await
+                .map_err(|err| app_error!(AppErrorCode::BadRequest, err.to_string()))?;
+            if buffer.is_empty() {
+                break;
+            }
+            empty = false;
+
+            let mut end = None;
+            for (index, byte) in buffer.iter().enumerate() {
+                match byte {
+                    b'"' if self.quoted => in_quotes = !in_quotes,
+                    b'\n' if !in_quotes => {
+                        end = Some(index + 1);
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+
+            let length = end.unwrap_or(buffer.len());
+            if self.row.len() + length > MAX_ROW_SIZE {
+                too_long = true;
+                self.row.clear();
+            }
+            if !too_long {
+                self.row.extend_from_slice(&buffer[..length]);
+            }
+            self.reader.consume(length);
+
+            if end.is_some() {
+                break;
+            }
+        }
+
+        Ok(match (empty, too_long) {
+            (true, _) => None,
+            (false, true) => Some(Err(app_error!(
+                AppErrorCode::PayloadTooLarge,
+                format!("row must not exceed {MAX_ROW_SIZE} bytes")
+            ))),
+            (false, false) => Some(Ok(&self.row)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Deserialize, Debug, PartialEq, Eq)]
+    struct Row {
+        #[serde(default)]
+        id: Option<u32>,
+        title: String,
+    }
+
+    async fn collect(content_type: &str, body: impl Into<Body>) -> AppResult<Vec<AppResult<Row>>> {
+        parse_rows::<Row>(content_type, body.into())?
+            .try_collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_parse_rows_csv() {
+        let rows = collect(
+            "text/csv; charset=utf-8",
+            "id,title\n1, foo \n,\"bar, baz\"\nx,qux\n",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(rows.len(), 3);
+        assert_eq!(
+            rows[0],
+            Ok(Row {
+                id: Some(1),
+                title: "foo".to_owned()
+            })
+        );
+        assert_eq!(
+            rows[1],
+            Ok(Row {
+                id: None,
+                title: "bar, baz".to_owned()
+            })
+        );
+        assert!(rows[2].is_err());
+    }
+
+    #[tokio::test]
+    async fn test_parse_rows_ndjson() {
+        let rows = collect(
+            "application/x-ndjson",
+            "{\"title\": \"foo\"}\n\n{\"id\": 2, \"title\": \"bar\"}\n{\"id\": 3}\n",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(rows.len(), 3);
+        assert_eq!(
+            rows[0],
+            Ok(Row {
+                id: None,
+                title: "foo".to_owned()
+            })
+        );
+        assert_eq!(
+            rows[1],
+            Ok(Row {
+                id: Some(2),
+                title: "bar".to_owned()
+            })
+        );
+        assert!(rows[2].is_err());
+    }
+
+    #[tokio::test]
+    async fn test_parse_rows_csv_quoted_line_break() {
+        let rows = collect(
+            "text/csv",
+            "id,title\n1,\"foo\nbar\"\n\n2,baz,qux\n3,\"\"\"a\"\"\"",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(rows.len(), 3);
+        assert_eq!(
+            rows[0],
+            Ok(Row {
+                id: Some(1),
+                title: "foo\nbar".to_owned()
+            })
+        );
+        assert!(matches!(rows[1], Err(AppError::UnprocessableEntity { .. })));
+        assert_eq!(
+            rows[2],
+            Ok(Row {
+                id: Some(3),
+                title: "\"a\"".to_owned()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parse_rows_too_long() {
+        let title = "a".repeat(MAX_ROW_SIZE);
+
+        let rows = collect(
+            "text/csv",
+            format!("id,title\n1,{title}\n2,\"{title}\n\"\n3,foo\n"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(rows.len(), 3);
+        assert!(matches!(rows[0], Err(AppError::PayloadTooLarge { .. })));
+        assert!(matches!(rows[1], Err(AppError::PayloadTooLarge { .. })));
+        assert_eq!(
+            rows[2],
+            Ok(Row {
+                id: Some(3),
+                title: "foo".to_owned()
+            })
+        );
+
+        let rows = collect(
+            "application/x-ndjson",
+            format!("{{\"title\": \"{title}\"}}\n{{\"title\": \"foo\"}}"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(rows.len(), 2);
+        assert!(matches!(rows[0], Err(AppError::PayloadTooLarge { .. })));
+        assert!(rows[1].is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_parse_rows_unsupported_content_type() {
+        assert!(matches!(
+            collect("application/json", "[]").await,
+            Err(AppError::UnsupportedMediaType { .. })
+        ));
+    }
+}
@@ -0,0 +1,421 @@
+use crate::{
+    app_error,
+    types::{AppError, AppErrorCode, ProblemDetails},
+    utils::{
+        problem::PROBLEM_TYPE_BASE,
+        query::{PaginateSort, PaginateSortQuery},
+    },
+};
+use serde::Serialize;
+use serde_json::{Map, Value, json};
+
+/// JSON:API media type
+pub const JSON_API_MEDIA_TYPE: &str = "application/vnd.api+json";
+
+/// Type of the book resources
+const BOOK_TYPE: &str = "books";
+
+/// Type of the revision resources, included with `?include=revisions`
+const REVISION_TYPE: &str = "revisions";
+
+/// Query parameters of the pagination, replaced by `page[number]` and `page[size]` in the links
+const PAGINATION_PARAMETERS: [&str; 4] = ["p", "l", "page[number]", "page[size]"];
+
+/// Convert the body of a book endpoint to a JSON:API document.
+///
+/// Books become `books` resource objects, and their embedded revisions are moved to `included`.
+/// Pages of books also get their `meta.total` and their pagination links.
+/// `collection` is the path of the book collection (e.g. `/api/v2/book`), and `path` and `query`
+/// those of the request. Returns `None` if the body is neither a book nor a page of books.
+pub fn book_document(
+    body: Value,
+    collection: &str,
+    path: &str,
+    query: Option<&str>,
+) -> Option<Value> {
+    let Value::Object(mut body) = body else {
+        return None;
+    };
+
+    let mut included = vec![];
+    let mut document = match body.remove("data") {
+        Some(Value::Array(books)) => {
+            // The total is at the top level in v1, and in `meta` from v2
+            let total = body
+                .get("total")
+                .or_else(|| body.get("meta").and_then(|meta| meta.get("total")))
+                .and_then(Value::as_i64)?;
+            let data = books
+                .into_iter()
+                .map(|book| book_resource(book, collection, &mut included))
+                .collect::<Option<Vec<_>>>()?;
+
+            json!({
+                "data": data,
+                "meta": { "total": total },
+                "links": page_links(path, query, total),
+            })
+        }
+        Some(_) => return None,
+        None => {
+            let data = book_resource(Value::Object(body), collection, &mut included)?;
+            let links = json!({ "self": data["links"]["self"] });
+
+            json!({ "data": data, "links": links })
+        }
+    };
+
+    if !included.is_empty() {
+        document["included"] = Value::Array(included);
+    }
+
+    Some(document)
+}
+
+/// Resource object of a book, its embedded revisions being added to `included`
+fn book_resource(book: Value, collection: &str, included: &mut Vec<Value>) -> Option<Value> {
+    let Value::Object(mut attributes) = book else {
+        return None;
+    };
+    let id = match attributes.remove("id")? {
+        Value::String(id) => id,
+        _ => return None,
+    };
+
+    let mut resource = json!({
+        "type": BOOK_TYPE,
+        "id": id,
+        "links": { "self": format!("{collection}/{id}") },
+    });
+
+    if let Some(Value::Array(revisions)) = attributes.remove("revisions") {
+        let mut identifiers = vec![];
+        for revision in revisions {
+            let Value::Object(mut revision) = revision else {
+                continue;
+            };
+            revision.remove("book_id");
+            let number = revision.get("revision").cloned().unwrap_or_default();
+            let identifier = json!({ "type": REVISION_TYPE, "id": format!("{id}-{number}") });
+
+            included.push(json!({
+                "type": REVISION_TYPE,
+                "id": identifier["id"],
+                "attributes": revision,
+                "relationships": { "book": { "data": { "type": BOOK_TYPE, "id": id } } },
+            }));
+            identifiers.push(identifier);
+        }
+        resource["relationships"] = json!({ "revisions": { "data": identifiers } });
+    }
+
+    resource["attributes"] = Value::Object(attributes);
+
+    Some(resource)
+}
+
+/// Links of a page, which keep the other query parameters of the request
+fn page_links(path: &str, query: Option<&str>, total: i64) -> Value {
+    let query = query.unwrap_or_default();
+    let paginate_sort = PaginateSort::from(
+        serde_urlencoded::from_str::<PaginateSortQuery>(query).unwrap_or_default(),
+    );
+    let parameters = serde_urlencoded::from_str::<Vec<(String, String)>>(query)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(name, _)| !PAGINATION_PARAMETERS.contains(&name.as_str()))
+        .collect::<Vec<_>>();
+
+    let link = |page: u32| {
+        let mut parameters = parameters.clone();
+        parameters.push((String::from("page[number]"), page.to_string()));
+        parameters.push((String::from("page[size]"), paginate_sort.limit.to_string()));
+
+        format!(
+            "{path}?{}",
+            serde_urlencoded::to_string(parameters).unwrap_or_default()
+        )
+    };
+
+    let page = paginate_sort.page;
+    let limit = i64::from(paginate_sort.limit);
+    let last = u32::try_from((total.max(1) + limit - 1) / limit).unwrap_or(u32::MAX);
+
+    json!({
+        "self": match query {
+            "" => path.to_owned(),
+            query => format!("{path}?{query}"),
+        },
+        "first": link(1),
+        "prev": (page > 1).then(|| link((page - 1).min(last))),
+        "next": (page < last).then(|| link(page + 1)),
+        "last": link(last),
+    })
+}
+
+/// Attributes of the resource object of a JSON:API request document (e.g. `{data: {type, attributes}}`)
+pub fn resource_attributes(document: Value) -> Option<Value> {
+    match document {
+        Value::Object(mut document) => match document.remove("data")? {
+            Value::Object(mut data) => data.remove("attributes").filter(Value::is_object),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Error returned for a JSON:API request document without resource attributes
+pub fn invalid_document() -> AppError {
+    app_error!(
+        AppErrorCode::BadRequest,
+        "JSON:API documents must have a resource object with `attributes` as `data`"
+    )
+}
+
+/// JSON:API document of an error
+#[derive(Debug, Serialize)]
+pub struct JsonApiErrors {
+    pub errors: Vec<JsonApiError>,
+}
+
+/// JSON:API error object
+#[derive(Debug, Serialize)]
+pub struct JsonApiError {
+    /// ID of the request in which the error occurred
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// HTTP status code, as a string
+    pub status: String,
+    /// Problem type (e.g. `not-found`), or code of the failed rule for a field error
+    pub code: String,
+    pub title: String,
+    pub detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<JsonApiErrorSource>,
+    /// Extension members of the problem details
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub meta: Map<String, Value>,
+}
+
+/// Part of the request document which caused an error
+#[derive(Debug, Serialize)]
+pub struct JsonApiErrorSource {
+    /// JSON pointer to the attribute (e.g. `/data/attributes/title`)
+    pub pointer: String,
+}
+
+/// Problem details as JSON:API error objects, one by invalid field if any
+impl From<ProblemDetails> for JsonApiErrors {
+    fn from(mut problem: ProblemDetails) -> Self {
+        let status = problem.status.to_string();
+        let code = problem
+            .problem_type
+            .strip_prefix(PROBLEM_TYPE_BASE)
+            .unwrap_or(&problem.problem_type)
+            .to_owned();
+        let fields = match problem.extensions.remove("errors") {
+            Some(Value::Array(fields)) => fields,
+            _ => vec![],
+        };
+
+        if fields.is_empty() {
+            return Self {
+                errors: vec![JsonApiError {
+                    id: problem.instance,
+                    status,
+                    code,
+                    title: problem.title,
+                    detail: problem.detail,
+                    source: None,
+                    meta: problem.extensions,
+                }],
+            };
+        }
+
+        let errors = fields
+            .iter()
+            .map(|field| {
+                let text = |name: &str| field[name].as_str().unwrap_or_default().to_owned();
+                let mut meta = problem.extensions.clone();
+                if let Some(Value::Object(params)) = field.get("params")
+                    && !params.is_empty()
+                {
+                    meta.insert(String::from("params"), Value::Object(params.clone()));
+                }
+
+                JsonApiError {
+                    id: problem.instance.clone(),
+                    status: status.clone(),
+                    code: text("code"),
+                    title: problem.title.clone(),
+                    detail: text("message"),
+                    source: Some(JsonApiErrorSource {
+                        pointer: attribute_pointer(&text("field")),
+                    }),
+                    meta,
+                }
+            })
+            .collect();
+
+        Self { errors }
+    }
+}
+
+/// JSON pointer to the attribute at a field path (e.g. `operations[0].title`)
+fn attribute_pointer(field: &str) -> String {
+    let path = field.replace('[', ".").replace(']', "");
+
+    path.split('.')
+        .filter(|part| !part.is_empty())
+        .fold(String::from("/data/attributes"), |pointer, part| {
+            format!("{pointer}/{part}")
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_book_document() {
+        let book = json!({"id": "1", "title": "foo", "version": 1});
+        assert_eq!(
+            book_document(book, "/api/v2/book", "/api/v2/book/1", None),
+            Some(json!({
+                "data": {
+                    "type": "books",
+                    "id": "1",
+                    "attributes": {"title": "foo", "version": 1},
+                    "links": {"self": "/api/v2/book/1"},
+                },
+                "links": {"self": "/api/v2/book/1"},
+            }))
+        );
+
+        let page = json!({
+            "data": [{
+                "id": "1",
+                "title": "foo",
+                "revisions": [{"book_id": "1", "revision": 1, "title": "foo"}],
+            }],
+            "meta": {"total": 1},
+        });
+        let document = book_document(page, "/api/book", "/api/book", None).unwrap();
+        assert_eq!(
+            document["data"][0]["relationships"],
+            json!({"revisions": {"data": [{"type": "revisions", "id": "1-1"}]}})
+        );
+        assert_eq!(
+            document["included"][0]["attributes"],
+            json!({"revision": 1, "title": "foo"})
+        );
+        assert_eq!(document["meta"], json!({"total": 1}));
+
+        assert_eq!(
+            book_document(json!([{"id": "1"}]), "/api/book", "/api/book", None),
+            None
+        );
+        assert_eq!(
+            book_document(json!({"data": [], "total": "?"}), "/book", "/book", None),
+            None
+        );
+    }
+
+    #[test]
+    fn test_page_links() {
+        let links = page_links("/api/book", Some("s=-title&page[number]=2&l=10"), 25);
+        assert_eq!(links["self"], "/api/book?s=-title&page[number]=2&l=10");
+        assert_eq!(
+            links["first"],
+            "/api/book?s=-title&page%5Bnumber%5D=1&page%5Bsize%5D=10"
+        );
+        assert_eq!(
+            links["prev"],
+            "/api/book?s=-title&page%5Bnumber%5D=1&page%5Bsize%5D=10"
+        );
+        assert_eq!(
+            links["next"],
+            "/api/book?s=-title&page%5Bnumber%5D=3&page%5Bsize%5D=10"
+        );
+        assert_eq!(links["last"], links["next"]);
+
+        let links = page_links("/api/book", None, 0);
+        assert_eq!(links["prev"], Value::Null);
+        assert_eq!(links["next"], Value::Null);
+        assert_eq!(
+            links["last"],
+            "/api/book?page%5Bnumber%5D=1&page%5Bsize%5D=500"
+        );
+    }
+
+    #[test]
+    fn test_errors_from_problem_details() {
+        let problem = ProblemDetails {
+            problem_type: String::from("/problems/unprocessable-entity"),
+            title: String::from("Unprocessable Entity"),
+            status: 422,
+            detail: String::from("invalid request data"),
+            instance: Some(String::from("abc")),
+            extensions: Map::from_iter([(
+                String::from("errors"),
+                json!([
+                    {"field": "title", "code": "length", "message": "too long", "params": {"max": 3}},
+                    {"field": "operations[0].author", "code": "required", "message": "missing", "params": {}},
+                ]),
+            )]),
+        };
+        assert_eq!(
+            json!(JsonApiErrors::from(problem)),
+            json!({"errors": [
+                {
+                    "id": "abc",
+                    "status": "422",
+                    "code": "length",
+                    "title": "Unprocessable Entity",
+                    "detail": "too long",
+                    "source": {"pointer": "/data/attributes/title"},
+                    "meta": {"params": {"max": 3}},
+                },
+                {
+                    "id": "abc",
+                    "status": "422",
+                    "code": "required",
+                    "title": "Unprocessable Entity",
+                    "detail": "missing",
+                    "source": {"pointer": "/data/attributes/operations/0/author"},
+                },
+            ]})
+        );
+
+        let problem = ProblemDetails {
+            problem_type: String::from("/problems/not-found"),
+            title: String::from("Not Found"),
+            status: 404,
+            detail: String::from("book could not be found"),
+            instance: None,
+            extensions: Map::new(),
+        };
+        assert_eq!(
+            json!(JsonApiErrors::from(problem)),
+            json!({"errors": [{
+                "status": "404",
+                "code": "not-found",
+                "title": "Not Found",
+                "detail": "book could not be found",
+            }]})
+        );
+    }
+
+    #[test]
+    fn test_resource_attributes() {
+        assert_eq!(
+            resource_attributes(json!({"data": {"type": "books", "attributes": {"title": "foo"}}})),
+            Some(json!({"title": "foo"}))
+        );
+        assert_eq!(resource_attributes(json!({"title": "foo"})), None);
+        assert_eq!(
+            resource_attributes(json!({"data": {"attributes": 1}})),
+            None
+        );
+    }
+}
@@ -1,3 +1,12 @@
+pub mod etag;
 pub mod extractors;
+pub mod hash;
+pub mod import;
+pub mod jsonapi;
+pub mod negotiation;
+pub mod patch;
+pub mod problem;
 pub mod query;
 pub mod validation;
+pub mod versioning;
+pub mod webhook;
@@ -0,0 +1,485 @@
+use crate::{
+    app_error,
+    types::{AppError, AppErrorCode},
+    utils::jsonapi::JSON_API_MEDIA_TYPE,
+};
+use axum::{
+    http::header::CONTENT_TYPE,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+tokio::task_local! {
+    /// Format negotiated for the current request, `None` if no supported format is acceptable
+    pub static CONTENT_FORMAT: Option<ContentFormat>;
+}
+
+/// Representation formats which can be selected with the `Accept` request header
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ContentFormat {
+    #[default]
+    Json,
+    Csv,
+    Xml,
+    Yaml,
+    MsgPack,
+    /// JSON:API documents, only offered by the book endpoints
+    JsonApi,
+}
+
+impl ContentFormat {
+    /// Supported formats, by order of preference when the client accepts several of them
+    const ALL: [Self; 6] = [
+        Self::Json,
+        Self::Csv,
+        Self::Xml,
+        Self::Yaml,
+        Self::MsgPack,
+        Self::JsonApi,
+    ];
+
+    /// Media type of the responses
+    pub fn media_type(&self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Xml => "application/xml; charset=utf-8",
+            Self::Yaml => "application/yaml; charset=utf-8",
+            Self::MsgPack => "application/msgpack",
+            Self::JsonApi => JSON_API_MEDIA_TYPE,
+        }
+    }
+
+    /// Short name of the format, identifying its representations (e.g. in entity tags)
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Csv => "csv",
+            Self::Xml => "xml",
+            Self::Yaml => "yaml",
+            Self::MsgPack => "msgpack",
+            Self::JsonApi => "jsonapi",
+        }
+    }
+
+    /// Media types accepted for the format
+    fn aliases(&self) -> &'static [&'static str] {
+        match self {
+            Self::Json => &["application/json"],
+            Self::Csv => &["text/csv"],
+            Self::Xml => &["application/xml", "text/xml"],
+            Self::Yaml => &["application/yaml", "application/x-yaml", "text/yaml"],
+            Self::MsgPack => &[
+                "application/msgpack",
+                "application/x-msgpack",
+                "application/vnd.msgpack",
+            ],
+            Self::JsonApi => &[JSON_API_MEDIA_TYPE],
+        }
+    }
+
+    /// Select the format from the `Accept` request header.
+    ///
+    /// Media ranges are tried by decreasing quality, then by decreasing specificity.
+    /// JSON is used without header, and `None` is returned if no supported format is acceptable.
+    pub fn from_accept(accept: Option<&str>) -> Option<Self> {
+        negotiate(accept, &Self::ALL, |format| format.aliases())
+    }
+
+    /// Select the format from the `Accept` request header, among the formats other than `excluded`
+    pub fn from_accept_except(accept: Option<&str>, excluded: Self) -> Option<Self> {
+        let formats = Self::ALL
+            .into_iter()
+            .filter(|format| *format != excluded)
+            .collect::<Vec<_>>();
+
+        negotiate(accept, &formats, |format| format.aliases())
+    }
+
+    /// Format negotiated for the current request, JSON outside of a request or if no format is acceptable
+    pub fn current() -> Self {
+        CONTENT_FORMAT
+            .try_with(|format| *format)
+            .ok()
+            .flatten()
+            .unwrap_or_default()
+    }
+
+    /// Serialize a value in the format
+    pub fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            Self::Json | Self::JsonApi => serde_json::to_vec(value).map_err(|err| err.to_string()),
+            Self::Csv => to_csv(&serde_json::to_value(value).map_err(|err| err.to_string())?),
+            Self::Xml => Ok(to_xml(
+                &serde_json::to_value(value).map_err(|err| err.to_string())?,
+            )),
+            Self::Yaml => serde_yaml::to_string(value)
+                .map(String::into_bytes)
+                .map_err(|err| err.to_string()),
+            // Maps of unknown length (e.g. flattened structs) are not supported by MessagePack
+            Self::MsgPack => rmp_serde::to_vec_named(
+                &serde_json::to_value(value).map_err(|err| err.to_string())?,
+            )
+            .map_err(|err| err.to_string()),
+        }
+    }
+}
+
+/// Select a format from the `Accept` request header, among `formats` by order of preference.
+///
+/// The formats are matched with their accepted media types, returned by `aliases`.
+/// Media ranges are tried by decreasing quality, then by decreasing specificity.
+/// The first format is used without header, and `None` is returned if no format is acceptable.
+pub fn negotiate<T: Copy + PartialEq>(
+    accept: Option<&str>,
+    formats: &[T],
+    aliases: impl Fn(T) -> &'static [&'static str],
+) -> Option<T> {
+    let mut ranges = accept
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|range| range.trim().parse::<mime::Mime>().ok())
+        .map(|range| {
+            let quality = range
+                .get_param("q")
+                .and_then(|q| q.as_str().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let specificity = match (range.type_(), range.subtype()) {
+                (mime::STAR, _) => 0,
+                (_, mime::STAR) => 1,
+                _ => 2,
+            };
+            (range, quality, specificity)
+        })
+        .collect::<Vec<_>>();
+
+    if ranges.is_empty() {
+        return formats.first().copied();
+    }
+
+    // Formats explicitly refused with `q=0` are not selected by a wildcard
+    let refused = formats
+        .iter()
+        .copied()
+        .filter(|format| {
+            ranges.iter().any(|(range, quality, specificity)| {
+                *quality <= 0.0 && *specificity == 2 && matches(aliases(*format), range)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    ranges.sort_by(|(_, q1, s1), (_, q2, s2)| q2.total_cmp(q1).then(s2.cmp(s1)));
+    ranges
+        .iter()
+        .filter(|(_, quality, _)| *quality > 0.0)
+        .find_map(|(range, _, _)| {
+            formats
+                .iter()
+                .copied()
+                .find(|format| !refused.contains(format) && matches(aliases(*format), range))
+        })
+}
+
+/// Check if one of the media types matches a media range (e.g. `text/csv`, `text/*` or `*/*`)
+fn matches(media_types: &[&str], range: &mime::Mime) -> bool {
+    match (range.type_(), range.subtype()) {
+        (mime::STAR, mime::STAR) => true,
+        (type_, mime::STAR) => media_types
+            .iter()
+            .any(|media_type| media_type.split('/').next() == Some(type_.as_str())),
+        _ => media_types.contains(&range.essence_str()),
+    }
+}
+
+/// Response serialized in the format negotiated for the current request
+pub struct Negotiated<T>(pub T);
+
+impl<T: Serialize> IntoResponse for Negotiated<T> {
+    fn into_response(self) -> Response {
+        let format = ContentFormat::current();
+        match format.serialize(&self.0) {
+            Ok(body) => ([(CONTENT_TYPE, format.media_type())], body).into_response(),
+            Err(err) => app_error!(AppErrorCode::InternalError, err).into_response(),
+        }
+    }
+}
+
+/// Convert a value to CSV.
+///
+/// Each element of an array, or of the `data` array of a paginated response, is a row.
+/// Any other object is a single row. Nested values are written as JSON.
+fn to_csv(value: &Value) -> Result<Vec<u8>, String> {
+    let rows = match value {
+        Value::Array(rows) => rows.iter().collect::<Vec<_>>(),
+        Value::Object(object) => match object.get("data") {
+            Some(Value::Array(rows)) => rows.iter().collect(),
+            _ => vec![value],
+        },
+        _ => vec![value],
+    };
+
+    let mut columns: Vec<&str> = vec![];
+    for row in &rows {
+        if let Value::Object(row) = row {
+            for column in row.keys() {
+                if !columns.contains(&column.as_str()) {
+                    columns.push(column);
+                }
+            }
+        }
+    }
+
+    let cell = |value: Option<&Value>| match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(value)) => escape_csv_formula(value.clone()),
+        Some(value) => value.to_string(),
+    };
+
+    let mut writer = csv::Writer::from_writer(vec![]);
+    if !columns.is_empty() {
+        writer
+            .write_record(&columns)
+            .map_err(|err| err.to_string())?;
+    }
+    for row in rows {
+        let record = match row {
+            Value::Object(row) => columns
+                .iter()
+                .map(|column| cell(row.get(*column)))
+                .collect::<Vec<_>>(),
+            value => vec![cell(Some(value))],
+        };
+        writer.write_record(record).map_err(|err| err.to_string())?;
+    }
+
+    writer.into_inner().map_err(|err| err.to_string())
+}
+
+/// Prefix with `'` a CSV cell which spreadsheets would evaluate as a formula.
+///
+/// Cells starting with `=`, `+`, `-`, `@`, a tab or a carriage return are escaped.
+pub fn escape_csv_formula(cell: String) -> String {
+    match cell.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        true => format!("'{cell}"),
+        false => cell,
+    }
+}
+
+/// Convert a value to an XML document with a `response` root element.
+///
+/// Array elements are written as `item` elements.
+fn to_xml(value: &Value) -> Vec<u8> {
+    fn write_object(xml: &mut String, object: &Map<String, Value>) {
+        for (name, value) in object {
+            write_element(xml, name, value);
+        }
+    }
+
+    fn write_element(xml: &mut String, name: &str, value: &Value) {
+        xml.push_str(&format!("<{name}>"));
+        match value {
+            Value::Null => {}
+            Value::String(value) => xml.push_str(&escape_xml(value)),
+            Value::Array(values) => {
+                for value in values {
+                    write_element(xml, "item", value);
+                }
+            }
+            Value::Object(object) => write_object(xml, object),
+            value => xml.push_str(&value.to_string()),
+        }
+        xml.push_str(&format!("</{name}>"));
+    }
+
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    write_element(&mut xml, "response", value);
+    xml.into_bytes()
+}
+
+/// Escape the XML special characters of a text
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Error returned when no supported format is acceptable
+pub fn not_acceptable() -> AppError {
+    app_error!(
+        AppErrorCode::NotAcceptable,
+        format!(
+            "supported media types are: {}",
+            ContentFormat::ALL
+                .iter()
+                .map(|format| format.aliases()[0])
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_from_accept() {
+        assert_eq!(Some(ContentFormat::Json), ContentFormat::from_accept(None));
+        assert_eq!(
+            Some(ContentFormat::Json),
+            ContentFormat::from_accept(Some(""))
+        );
+        assert_eq!(
+            Some(ContentFormat::Json),
+            ContentFormat::from_accept(Some("*/*"))
+        );
+        assert_eq!(
+            Some(ContentFormat::Csv),
+            ContentFormat::from_accept(Some("text/csv"))
+        );
+        assert_eq!(
+            Some(ContentFormat::Csv),
+            ContentFormat::from_accept(Some("text/*"))
+        );
+        assert_eq!(
+            Some(ContentFormat::Xml),
+            ContentFormat::from_accept(Some("text/html, application/xml;q=0.9, */*;q=0.8"))
+        );
+        assert_eq!(
+            Some(ContentFormat::Yaml),
+            ContentFormat::from_accept(Some("application/json;q=0.5, application/x-yaml"))
+        );
+        assert_eq!(
+            Some(ContentFormat::MsgPack),
+            ContentFormat::from_accept(Some("application/msgpack"))
+        );
+        assert_eq!(
+            Some(ContentFormat::JsonApi),
+            ContentFormat::from_accept(Some("application/vnd.api+json"))
+        );
+        assert_eq!(
+            Some(ContentFormat::Json),
+            ContentFormat::from_accept(Some("application/*"))
+        );
+    }
+
+    #[test]
+    fn test_from_accept_not_acceptable() {
+        assert_eq!(None, ContentFormat::from_accept(Some("text/html")));
+        assert_eq!(None, ContentFormat::from_accept(Some("text/csv;q=0")));
+        assert_eq!(
+            Some(ContentFormat::Csv),
+            ContentFormat::from_accept(Some("application/json;q=0, */*"))
+        );
+    }
+
+    #[test]
+    fn test_from_accept_except() {
+        assert_eq!(
+            Some(ContentFormat::Json),
+            ContentFormat::from_accept_except(
+                Some("application/vnd.api+json, application/json;q=0.5"),
+                ContentFormat::JsonApi
+            )
+        );
+        assert_eq!(
+            None,
+            ContentFormat::from_accept_except(
+                Some("application/vnd.api+json"),
+                ContentFormat::JsonApi
+            )
+        );
+    }
+
+    #[test]
+    fn test_serialize_csv() {
+        let value = json!({
+            "data": [
+                { "id": "1", "title": "foo, bar", "updated_at": null },
+                { "id": "2", "title": "baz", "tags": ["a"] },
+            ],
+            "total": 2,
+        });
+        assert_eq!(
+            Ok("id,title,updated_at,tags\n1,\"foo, bar\",,\n2,baz,,\"[\"\"a\"\"]\"\n".to_owned()),
+            ContentFormat::Csv
+                .serialize(&value)
+                .map(|csv| String::from_utf8(csv).unwrap())
+        );
+
+        let value = json!({ "code": 404, "message": "not found" });
+        assert_eq!(
+            Ok("code,message\n404,not found\n".to_owned()),
+            ContentFormat::Csv
+                .serialize(&value)
+                .map(|csv| String::from_utf8(csv).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_serialize_csv_formula() {
+        let value = json!([
+            { "title": "=HYPERLINK(\"http://example.com\")", "version": -1 },
+            { "title": "+1", "version": 1 },
+            { "title": "-1", "version": 1 },
+            { "title": "@SUM(A1)", "version": 1 },
+            { "title": "a=b", "version": 1 },
+        ]);
+        assert_eq!(
+            Ok(concat!(
+                "title,version\n",
+                "\"'=HYPERLINK(\"\"http://example.com\"\")\",-1\n",
+                "'+1,1\n",
+                "'-1,1\n",
+                "'@SUM(A1),1\n",
+                "a=b,1\n",
+            )
+            .to_owned()),
+            ContentFormat::Csv
+                .serialize(&value)
+                .map(|csv| String::from_utf8(csv).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_serialize_xml() {
+        let value = json!({
+            "data": [{ "title": "<foo> & bar", "updated_at": null }],
+            "total": 1,
+        });
+        assert_eq!(
+            Ok(String::from(
+                r#"<?xml version="1.0" encoding="UTF-8"?><response><data><item><title>&lt;foo&gt; &amp; bar</title><updated_at></updated_at></item></data><total>1</total></response>"#
+            )),
+            ContentFormat::Xml
+                .serialize(&value)
+                .map(|xml| String::from_utf8(xml).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_serialize_yaml_and_msgpack() {
+        let value = json!({ "code": 404, "message": "not found" });
+
+        assert_eq!(
+            Ok("code: 404\nmessage: not found\n".to_owned()),
+            ContentFormat::Yaml
+                .serialize(&value)
+                .map(|yaml| String::from_utf8(yaml).unwrap())
+        );
+
+        let msgpack = ContentFormat::MsgPack.serialize(&value).unwrap();
+        assert_eq!(value, rmp_serde::from_slice::<Value>(&msgpack).unwrap());
+    }
+}
@@ -0,0 +1,200 @@
+use crate::{
+    app_error,
+    types::{AppError, AppErrorCode, AppResult},
+    utils::jsonapi::{JSON_API_MEDIA_TYPE, invalid_document, resource_attributes},
+};
+use json_patch::{Patch, PatchErrorKind};
+use serde_json::Value;
+
+/// JSON Merge Patch media type (RFC 7396)
+pub const MERGE_PATCH_CONTENT_TYPE: &str = "application/merge-patch+json";
+
+/// JSON Patch media type (RFC 6902)
+pub const JSON_PATCH_CONTENT_TYPE: &str = "application/json-patch+json";
+
+/// Apply a PATCH request body to a JSON document.
+///
+/// The patch format is selected with the request `Content-Type`.
+/// After patching, the document may only contain the fields listed in `valid_fields`.
+pub fn apply_patch(
+    document: &mut Value,
+    content_type: &str,
+    body: &[u8],
+    valid_fields: &[&str],
+) -> AppResult<()> {
+    let mime = content_type
+        .parse::<mime::Mime>()
+        .map_err(|err| app_error!(AppErrorCode::UnsupportedMediaType, err.to_string()))?;
+
+    match mime.essence_str() {
+        MERGE_PATCH_CONTENT_TYPE => {
+            let patch: Value = serde_json::from_slice(body)
+                .map_err(|err| app_error!(AppErrorCode::BadRequest, err.to_string()))?;
+
+            json_patch::merge(document, &patch);
+        }
+        JSON_PATCH_CONTENT_TYPE => {
+            let patch: Patch = serde_json::from_slice(body)
+                .map_err(|err| app_error!(AppErrorCode::BadRequest, err.to_string()))?;
+
+            json_patch::patch(document, &patch).map_err(|err| match err.kind {
+                PatchErrorKind::TestFailed => app_error!(AppErrorCode::Conflict, err.to_string()),
+                _ => app_error!(AppErrorCode::UnprocessableEntity, err.to_string()),
+            })?;
+        }
+        // The attributes of a JSON:API resource object are merged into the document
+        JSON_API_MEDIA_TYPE => {
+            let patch: Value = serde_json::from_slice(body)
+                .map_err(|err| app_error!(AppErrorCode::BadRequest, err.to_string()))?;
+            let attributes = resource_attributes(patch).ok_or_else(invalid_document)?;
+
+            json_patch::merge(document, &attributes);
+        }
+        _ => {
+            return Err(app_error!(
+                AppErrorCode::UnsupportedMediaType,
+                format!(
+                    "content type must be `{MERGE_PATCH_CONTENT_TYPE}`, `{JSON_PATCH_CONTENT_TYPE}` or `{JSON_API_MEDIA_TYPE}`"
+                )
+            ));
+        }
+    }
+
+    match document.as_object() {
+        Some(fields) => {
+            if let Some(field) = fields
+                .keys()
+                .find(|field| !valid_fields.contains(&field.as_str()))
+            {
+                return Err(app_error!(
+                    AppErrorCode::UnprocessableEntity,
+                    format!("unknown field `{field}`")
+                ));
+            }
+        }
+        None => {
+            return Err(app_error!(
+                AppErrorCode::UnprocessableEntity,
+                "patched document must be an object"
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    const VALID_FIELDS: &[&str] = &["title", "author"];
+
+    #[test]
+    fn test_apply_merge_patch() {
+        let mut document = json!({ "title": "foo", "author": "bar" });
+        let body = json!({ "title": "baz" }).to_string();
+
+        apply_patch(
+            &mut document,
+            "application/merge-patch+json",
+            body.as_bytes(),
+            VALID_FIELDS,
+        )
+        .unwrap();
+        assert_eq!(json!({ "title": "baz", "author": "bar" }), document);
+    }
+
+    #[test]
+    fn test_apply_merge_patch_unknown_field() {
+        let mut document = json!({ "title": "foo", "author": "bar" });
+        let body = json!({ "isbn": "123" }).to_string();
+
+        assert_eq!(
+            Err(AppError::UnprocessableEntity {
+                message: "unknown field `isbn`".to_owned()
+            }),
+            apply_patch(
+                &mut document,
+                MERGE_PATCH_CONTENT_TYPE,
+                body.as_bytes(),
+                VALID_FIELDS,
+            )
+        );
+    }
+
+    #[test]
+    fn test_apply_json_patch() {
+        let mut document = json!({ "title": "foo", "author": "bar" });
+        let body = json!([
+            { "op": "test", "path": "/title", "value": "foo" },
+            { "op": "replace", "path": "/author", "value": "baz" },
+        ])
+        .to_string();
+
+        apply_patch(
+            &mut document,
+            "application/json-patch+json; charset=utf-8",
+            body.as_bytes(),
+            VALID_FIELDS,
+        )
+        .unwrap();
+        assert_eq!(json!({ "title": "foo", "author": "baz" }), document);
+    }
+
+    #[test]
+    fn test_apply_json_patch_errors() {
+        let mut document = json!({ "title": "foo", "author": "bar" });
+
+        let body = json!([{ "op": "test", "path": "/title", "value": "bar" }]).to_string();
+        let result = apply_patch(
+            &mut document,
+            JSON_PATCH_CONTENT_TYPE,
+            body.as_bytes(),
+            VALID_FIELDS,
+        );
+        assert!(matches!(result, Err(AppError::Conflict { .. })));
+
+        let body = json!([{ "op": "replace", "path": "/isbn", "value": "123" }]).to_string();
+        let result = apply_patch(
+            &mut document,
+            JSON_PATCH_CONTENT_TYPE,
+            body.as_bytes(),
+            VALID_FIELDS,
+        );
+        assert!(matches!(result, Err(AppError::UnprocessableEntity { .. })));
+        assert_eq!(json!({ "title": "foo", "author": "bar" }), document);
+    }
+
+    #[test]
+    fn test_apply_json_api_patch() {
+        let mut document = json!({ "title": "foo", "author": "bar" });
+        let body = json!({ "data": { "type": "books", "attributes": { "title": "baz" } } });
+
+        apply_patch(
+            &mut document,
+            JSON_API_MEDIA_TYPE,
+            body.to_string().as_bytes(),
+            VALID_FIELDS,
+        )
+        .unwrap();
+        assert_eq!(json!({ "title": "baz", "author": "bar" }), document);
+
+        let body = json!({ "title": "baz" }).to_string();
+        let result = apply_patch(
+            &mut document,
+            JSON_API_MEDIA_TYPE,
+            body.as_bytes(),
+            VALID_FIELDS,
+        );
+        assert!(matches!(result, Err(AppError::BadRequest { .. })));
+    }
+
+    #[test]
+    fn test_apply_patch_unsupported_content_type() {
+        let mut document = json!({ "title": "foo", "author": "bar" });
+
+        let result = apply_patch(&mut document, "application/json", b"{}", VALID_FIELDS);
+        assert!(matches!(result, Err(AppError::UnsupportedMediaType { .. })));
+    }
+}
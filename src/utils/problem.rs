@@ -0,0 +1,72 @@
+use crate::utils::negotiation::ContentFormat;
+
+tokio::task_local! {
+    /// Rendering of the errors of the current request
+    pub static ERROR_CONTEXT: ErrorContext;
+}
+
+/// Base of the URIs which identify the problem types
+pub const PROBLEM_TYPE_BASE: &str = "/problems/";
+
+/// How the errors of a request are rendered
+#[derive(Debug, Default, Clone)]
+pub struct ErrorContext {
+    /// Render errors as `{code, message}` instead of problem details
+    pub legacy: bool,
+    /// ID of the request, set as the `instance` of the problems
+    pub request_id: Option<String>,
+}
+
+impl ErrorContext {
+    /// Context of the current request, the default one outside of a request
+    pub fn current() -> Self {
+        ERROR_CONTEXT
+            .try_with(|context| context.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// Media type of the problem details in a format
+pub fn problem_media_type(format: ContentFormat) -> &'static str {
+    match format {
+        ContentFormat::Json => "application/problem+json",
+        ContentFormat::Xml => "application/problem+xml; charset=utf-8",
+        _ => format.media_type(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_error_context_current() {
+        assert!(ErrorContext::current().request_id.is_none());
+
+        let context = ErrorContext {
+            legacy: true,
+            request_id: Some(String::from("abc")),
+        };
+        let current = ERROR_CONTEXT
+            .scope(context, async { ErrorContext::current() })
+            .await;
+        assert!(current.legacy);
+        assert_eq!(current.request_id.as_deref(), Some("abc"));
+    }
+
+    #[test]
+    fn test_problem_media_type() {
+        assert_eq!(
+            problem_media_type(ContentFormat::Json),
+            "application/problem+json"
+        );
+        assert_eq!(
+            problem_media_type(ContentFormat::Xml),
+            "application/problem+xml; charset=utf-8"
+        );
+        assert_eq!(
+            problem_media_type(ContentFormat::Yaml),
+            "application/yaml; charset=utf-8"
+        );
+    }
+}
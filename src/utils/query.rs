@@ -1,27 +1,127 @@
-use serde::{Deserialize, Serialize};
+use crate::{
+    app_error,
+    types::{AppError, AppErrorCode, AppResult},
+    utils::versioning::ApiVersion,
+};
+use serde::{Deserialize, Serialize, Serializer, ser::SerializeStruct};
 use std::fmt::Display;
 
-const PAGINATION_MAX_LIMIT: u32 = 500;
+/// Maximum number of items in a page
+pub const PAGINATION_MAX_LIMIT: u32 = 500;
 
-#[derive(Serialize)]
+/// Page of a list, rendered as `{data, total}` in v1 and as `{data, meta: {total}}` from v2
 pub struct PaginateResponse<T: Serialize> {
     pub data: T,
     pub total: i64,
 }
 
-/// Query parameters used to paginate API
-#[derive(Debug, Deserialize, PartialEq, Eq)]
+/// Information about a page of a list, from v2
+#[derive(Serialize)]
+struct PaginateMeta {
+    total: i64,
+}
+
+impl<T: Serialize> Serialize for PaginateResponse<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("PaginateResponse", 2)?;
+        state.serialize_field("data", &self.data)?;
+        match ApiVersion::current() {
+            ApiVersion::V1 => state.serialize_field("total", &self.total)?,
+            ApiVersion::V2 => state.serialize_field("meta", &PaginateMeta { total: self.total })?,
+        }
+
+        state.end()
+    }
+}
+
+/// Query parameters used to paginate API,
+/// with the JSON:API `page[number]` and `page[size]` parameters as aliases of `p` and `l`
+#[derive(Debug, Default, Deserialize, PartialEq, Eq)]
 pub struct PaginateSortQuery {
-    #[serde(rename(deserialize = "p"))]
+    #[serde(rename(deserialize = "p"), alias = "page[number]")]
     pub page: Option<u32>,
 
-    #[serde(rename(deserialize = "l"))]
+    #[serde(rename(deserialize = "l"), alias = "page[size]")]
     pub limit: Option<u32>,
 
     #[serde(rename(deserialize = "s"))]
     pub sort: Option<String>,
 }
 
+/// Query parameter used to restrict the returned fields (sparse fieldset)
+/// Example: ?fields=id,title
+#[derive(Debug, Default, Deserialize, PartialEq, Eq)]
+pub struct FieldsQuery {
+    pub fields: Option<String>,
+}
+
+impl FieldsQuery {
+    /// Returns the requested fields, in the order of `valid_fields`.
+    ///
+    /// All valid fields are returned if no field is requested.
+    /// Unknown fields are rejected with `400 Bad Request`.
+    pub fn get_fields<'a>(&self, valid_fields: &[&'a str]) -> AppResult<Vec<&'a str>> {
+        let fields = parse_list(self.fields.as_deref(), valid_fields, "field", "fields")?;
+
+        match fields.is_empty() {
+            true => Ok(valid_fields.to_vec()),
+            false => Ok(fields),
+        }
+    }
+}
+
+/// Query parameter used to embed related resources
+/// Example: ?include=revisions
+#[derive(Debug, Default, Deserialize, PartialEq, Eq)]
+pub struct IncludeQuery {
+    pub include: Option<String>,
+}
+
+impl IncludeQuery {
+    /// Returns the requested relations, in the order of `valid_relations`.
+    ///
+    /// Unknown relations are rejected with `400 Bad Request`.
+    pub fn get_relations<'a>(&self, valid_relations: &[&'a str]) -> AppResult<Vec<&'a str>> {
+        parse_list(
+            self.include.as_deref(),
+            valid_relations,
+            "relation",
+            "include",
+        )
+    }
+}
+
+/// Parse a comma-separated list parameter against a whitelist
+fn parse_list<'a>(
+    value: Option<&str>,
+    valid_values: &[&'a str],
+    kind: &str,
+    parameter: &str,
+) -> AppResult<Vec<&'a str>> {
+    let requested = value
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .collect::<Vec<_>>();
+
+    if let Some(value) = requested.iter().find(|value| !valid_values.contains(value)) {
+        return Err(app_error!(
+            AppErrorCode::BadRequest,
+            format!(
+                "unknown {kind} `{value}` in `{parameter}`, valid {kind}s are: {}",
+                valid_values.join(", ")
+            )
+        ));
+    }
+
+    Ok(valid_values
+        .iter()
+        .filter(|value| requested.contains(value))
+        .copied()
+        .collect())
+}
+
 #[derive(Debug, Deserialize, PartialEq, Eq)]
 pub enum Sort {
     /// Ascending sort (`'+'` prefix)
@@ -148,6 +248,42 @@ impl PaginateSort {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::utils::versioning::API_VERSION;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_paginate_response_versions() {
+        let page = PaginateResponse {
+            data: vec![1, 2],
+            total: 5,
+        };
+        assert_eq!(json!(page), json!({"data": [1, 2], "total": 5}));
+
+        let value = API_VERSION
+            .scope(ApiVersion::V2, async { json!(page) })
+            .await;
+        assert_eq!(value, json!({"data": [1, 2], "meta": {"total": 5}}));
+    }
+
+    #[test]
+    fn test_paginate_sort_query_json_api_parameters() {
+        assert_eq!(
+            serde_urlencoded::from_str::<PaginateSortQuery>("page%5Bnumber%5D=2&page[size]=10"),
+            Ok(PaginateSortQuery {
+                page: Some(2),
+                limit: Some(10),
+                sort: None,
+            })
+        );
+        assert_eq!(
+            serde_urlencoded::from_str::<PaginateSortQuery>("p=3&l=5&s=-title"),
+            Ok(PaginateSortQuery {
+                page: Some(3),
+                limit: Some(5),
+                sort: Some(String::from("-title")),
+            })
+        );
+    }
 
     #[test]
     fn test_from_paginate_sort_query_paginate() {
@@ -392,4 +528,68 @@ mod test {
             paginate_sort.get_sorts_sql(valid_fields)
         );
     }
+
+    #[test]
+    fn test_get_fields() {
+        let valid_fields: &[&str] = &["id", "title", "author"];
+
+        let query = FieldsQuery::default();
+        assert_eq!(
+            Ok(vec!["id", "title", "author"]),
+            query.get_fields(valid_fields)
+        );
+
+        let query = FieldsQuery {
+            fields: Some(String::new()),
+        };
+        assert_eq!(
+            Ok(vec!["id", "title", "author"]),
+            query.get_fields(valid_fields)
+        );
+
+        let query = FieldsQuery {
+            fields: Some("author, id,author".to_owned()),
+        };
+        assert_eq!(Ok(vec!["id", "author"]), query.get_fields(valid_fields));
+    }
+
+    #[test]
+    fn test_get_fields_unknown_field() {
+        let query = FieldsQuery {
+            fields: Some("id,isbn".to_owned()),
+        };
+        assert_eq!(
+            Err(AppError::BadRequest {
+                message: "unknown field `isbn` in `fields`, valid fields are: id, title".to_owned()
+            }),
+            query.get_fields(&["id", "title"])
+        );
+    }
+
+    #[test]
+    fn test_get_relations() {
+        let valid_relations: &[&str] = &["revisions", "reviews"];
+
+        let query = IncludeQuery::default();
+        assert_eq!(Ok(vec![]), query.get_relations(valid_relations));
+
+        let query = IncludeQuery {
+            include: Some("reviews,revisions".to_owned()),
+        };
+        assert_eq!(
+            Ok(vec!["revisions", "reviews"]),
+            query.get_relations(valid_relations)
+        );
+
+        let query = IncludeQuery {
+            include: Some("copies".to_owned()),
+        };
+        assert_eq!(
+            Err(AppError::BadRequest {
+                message: "unknown relation `copies` in `include`, valid relations are: revisions, reviews"
+                    .to_owned()
+            }),
+            query.get_relations(valid_relations)
+        );
+    }
 }
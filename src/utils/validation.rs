@@ -2,15 +2,388 @@ use crate::{
     app_error,
     types::{AppError, AppErrorCode, AppResult},
 };
-use serde_json::json;
-use validator::Validate;
+use serde::Serialize;
+use serde_json::{Map, Value, error::Category, json};
+use validator::{Validate, ValidationError, ValidationErrors, ValidationErrorsKind};
+
+/// Invalid field of a request body
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FieldError {
+    /// Path of the field in the body (e.g. `operations[0].data.title`), empty for the whole body
+    pub field: String,
+    /// Stable code of the failed rule (e.g. `length` or `required`)
+    pub code: String,
+    pub message: String,
+    /// Parameters of the rule (e.g. `min` and `max` for `length`)
+    pub params: Map<String, Value>,
+}
 
 pub fn validate_request_data<T: Validate>(data: &T) -> AppResult<()> {
     match data.validate() {
         Ok(_) => Ok(()),
-        Err(errors) => Err(app_error!(
+        Err(errors) => Err(invalid_request_data(
             AppErrorCode::BadRequest,
-            json!(errors).to_string()
+            validation_field_errors(&errors),
         )),
     }
 }
+
+/// Error of an invalid request body, with its field errors as the `errors` extension member
+pub fn invalid_request_data(code: AppErrorCode, errors: Vec<FieldError>) -> AppError {
+    let message = errors
+        .iter()
+        .map(|error| match error.field.is_empty() {
+            true => error.message.clone(),
+            false => format!("{}: {}", error.field, error.message),
+        })
+        .collect::<Vec<_>>()
+        .join("; ");
+
+    app_error!(code, format!("invalid request data: {message}"))
+        .with_extension("errors", json!(errors))
+}
+
+/// Field errors of a `validator` validation, sorted by field
+pub fn validation_field_errors(errors: &ValidationErrors) -> Vec<FieldError> {
+    let mut field_errors = vec![];
+    collect_validation_errors(errors, "", &mut field_errors);
+    field_errors.sort_by(|a, b| a.field.cmp(&b.field));
+
+    field_errors
+}
+
+fn collect_validation_errors(
+    errors: &ValidationErrors,
+    prefix: &str,
+    output: &mut Vec<FieldError>,
+) {
+    for (field, kind) in errors.errors() {
+        // Errors of the whole struct are reported without field name
+        let path = match (prefix.is_empty(), field.as_ref()) {
+            (_, "__all__") => prefix.to_owned(),
+            (true, field) => field.to_owned(),
+            (false, field) => format!("{prefix}.{field}"),
+        };
+
+        match kind {
+            ValidationErrorsKind::Field(errors) => {
+                output.extend(errors.iter().map(|error| {
+                    FieldError {
+                        field: path.clone(),
+                        code: error.code.to_string(),
+                        message: validation_message(error),
+                        // The value is not sent back, as it may be a secret
+                        params: error
+                            .params
+                            .iter()
+                            .filter(|(name, _)| *name != "value")
+                            .map(|(name, value)| (name.to_string(), value.clone()))
+                            .collect(),
+                    }
+                }))
+            }
+            ValidationErrorsKind::Struct(errors) => {
+                collect_validation_errors(errors, &path, output)
+            }
+            ValidationErrorsKind::List(errors) => {
+                for (index, errors) in errors {
+                    collect_validation_errors(errors, &format!("{path}[{index}]"), output);
+                }
+            }
+        }
+    }
+}
+
+/// Message of a validation error, the default one of its rule if it has none
+fn validation_message(error: &ValidationError) -> String {
+    if let Some(message) = &error.message {
+        return message.to_string();
+    }
+
+    let param = |name: &str| error.params.get(name).map(Value::to_string);
+    match (
+        error.code.as_ref(),
+        param("min"),
+        param("max"),
+        param("equal"),
+    ) {
+        ("length", _, _, Some(equal)) => format!("length must be {equal}"),
+        ("length", Some(min), Some(max), _) => format!("length must be between {min} and {max}"),
+        ("length", Some(min), None, _) => format!("length must be at least {min}"),
+        ("length", None, Some(max), _) => format!("length must be at most {max}"),
+        ("range", Some(min), Some(max), _) => format!("must be between {min} and {max}"),
+        ("range", Some(min), None, _) => format!("must be at least {min}"),
+        ("range", None, Some(max), _) => format!("must be at most {max}"),
+        ("url", ..) => String::from("must be a valid URL"),
+        ("email", ..) => String::from("must be a valid email address"),
+        ("required", ..) => String::from("is required"),
+        _ => String::from("is invalid"),
+    }
+}
+
+/// Codes of the data errors, by prefix of their message.
+///
+/// `serde_json::Error` only tells the category of an error: the kind of a data error is only
+/// found in the messages of the `serde::de::Error` constructors used by the derived
+/// implementations, which are pinned by the tests. The other errors (syntax errors, or custom
+/// errors of a deserializer, e.g. of a date) have the `invalid` code.
+const DESERIALIZATION_ERROR_CODES: [(&str, &str); 6] = [
+    ("missing field", "required"),
+    ("unknown field", "unknown_field"),
+    ("unknown variant", "unknown_variant"),
+    ("invalid type", "invalid_type"),
+    ("invalid value", "invalid_value"),
+    ("invalid length", "invalid_length"),
+];
+
+/// Field error of a JSON body which could not be deserialized
+pub fn deserialization_field_error(
+    error: &serde_path_to_error::Error<serde_json::Error>,
+) -> FieldError {
+    let mut path = vec![];
+    for segment in error.path().iter() {
+        match segment {
+            serde_path_to_error::Segment::Seq { index } => path.push(format!("[{index}]")),
+            serde_path_to_error::Segment::Map { key } => path.push(format!(".{key}")),
+            serde_path_to_error::Segment::Enum { .. } | serde_path_to_error::Segment::Unknown => {}
+        }
+    }
+    let mut field = path.concat().trim_start_matches('.').to_owned();
+
+    let message = error.inner().to_string();
+    let code = deserialization_error_code(error.inner());
+
+    // Missing and unknown fields are reported on their parent
+    if let ("required" | "unknown_field", Some(name)) = (code, message.split('`').nth(1))
+        && !field.ends_with(name)
+    {
+        field = match field.is_empty() {
+            true => name.to_owned(),
+            false => format!("{field}.{name}"),
+        };
+    }
+
+    FieldError {
+        field,
+        code: code.to_owned(),
+        message: match code {
+            "required" => String::from("is required"),
+            _ => message,
+        },
+        params: Map::new(),
+    }
+}
+
+/// Code of a deserialization error
+fn deserialization_error_code(error: &serde_json::Error) -> &'static str {
+    match error.classify() {
+        Category::Data => {
+            let message = error.to_string();
+            DESERIALIZATION_ERROR_CODES
+                .iter()
+                .find(|(prefix, _)| message.starts_with(prefix))
+                .map_or("invalid", |(_, code)| code)
+        }
+        Category::Io | Category::Syntax | Category::Eof => "invalid",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Validate)]
+    struct Item {
+        #[validate(length(min = 1, max = 3))]
+        name: String,
+    }
+
+    #[derive(Debug, Validate)]
+    struct Body {
+        #[validate(url)]
+        url: String,
+        #[validate(nested)]
+        items: Vec<Item>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    enum Kind {
+        Novel,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    #[allow(unused)]
+    struct Data {
+        title: String,
+        count: u32,
+        items: Vec<Data>,
+        #[serde(default)]
+        kind: Option<Kind>,
+        #[serde(default)]
+        rating: Option<u8>,
+        #[serde(default)]
+        range: Option<(u32, u32)>,
+        #[serde(default)]
+        date: Option<chrono::NaiveDate>,
+    }
+
+    fn deserialize(body: Value) -> FieldError {
+        let error = serde_path_to_error::deserialize::<_, Data>(body).unwrap_err();
+        deserialization_field_error(&error)
+    }
+
+    /// Field and code of the error of a valid body with a replaced field
+    fn deserialize_field(name: &str, value: Value) -> (String, String) {
+        let mut body = json!({"title": "foo", "count": 1, "items": []});
+        body[name] = value;
+        let error = deserialize(body);
+
+        (error.field, error.code)
+    }
+
+    #[test]
+    fn test_validation_field_errors() {
+        let body = Body {
+            url: String::from("not a url"),
+            items: vec![
+                Item {
+                    name: String::from("foo"),
+                },
+                Item {
+                    name: String::from("toolong"),
+                },
+            ],
+        };
+
+        let errors = validation_field_errors(&body.validate().unwrap_err());
+        assert_eq!(
+            errors,
+            [
+                FieldError {
+                    field: String::from("items[1].name"),
+                    code: String::from("length"),
+                    message: String::from("length must be between 1 and 3"),
+                    params: Map::from_iter([
+                        (String::from("min"), json!(1)),
+                        (String::from("max"), json!(3)),
+                    ]),
+                },
+                FieldError {
+                    field: String::from("url"),
+                    code: String::from("url"),
+                    message: String::from("must be a valid URL"),
+                    params: Map::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_request_data() {
+        let error = invalid_request_data(
+            AppErrorCode::UnprocessableEntity,
+            vec![FieldError {
+                field: String::from("title"),
+                code: String::from("required"),
+                message: String::from("is required"),
+                params: Map::new(),
+            }],
+        );
+
+        assert_eq!(
+            error.to_string(),
+            "invalid request data: title: is required"
+        );
+        match error {
+            AppError::WithExtensions { extensions, .. } => {
+                assert_eq!(extensions["errors"][0]["code"], "required")
+            }
+            error => panic!("unexpected error {error:?}"),
+        }
+    }
+
+    #[test]
+    fn test_deserialization_field_error() {
+        let error = deserialize(json!({"count": 1, "items": []}));
+        assert_eq!(error.field, "title");
+        assert_eq!(error.code, "required");
+        assert_eq!(error.message, "is required");
+
+        let error = deserialize(json!({"title": "foo", "count": "1", "items": []}));
+        assert_eq!(error.field, "count");
+        assert_eq!(error.code, "invalid_type");
+        assert_eq!(error.message, "invalid type: string \"1\", expected u32");
+
+        let error = deserialize(json!({
+            "title": "foo",
+            "count": 1,
+            "items": [{"title": "bar", "count": 1, "items": [], "isbn": ""}],
+        }));
+        assert_eq!(error.field, "items[0].isbn");
+        assert_eq!(error.code, "unknown_field");
+    }
+
+    #[test]
+    fn test_deserialization_field_error_codes() {
+        for (name, value, code) in [
+            ("count", json!("1"), "invalid_type"),
+            ("isbn", json!(""), "unknown_field"),
+            ("kind", json!("poem"), "unknown_variant"),
+            ("rating", json!(300), "invalid_value"),
+            ("range", json!([1]), "invalid_length"),
+            // Custom errors of the deserializers
+            ("date", json!("foo"), "invalid"),
+        ] {
+            assert_eq!(
+                deserialize_field(name, value),
+                (name.to_owned(), code.to_owned()),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_deserialization_error_code() {
+        use serde::de::{Error, Unexpected};
+
+        let cases = [
+            (serde_json::Error::missing_field("title"), "required"),
+            (
+                serde_json::Error::unknown_field("isbn", &["title"]),
+                "unknown_field",
+            ),
+            (
+                serde_json::Error::unknown_variant("poem", &["novel"]),
+                "unknown_variant",
+            ),
+            (
+                serde_json::Error::invalid_type(Unexpected::Str("1"), &"u32"),
+                "invalid_type",
+            ),
+            (
+                serde_json::Error::invalid_value(Unexpected::Unsigned(300), &"u8"),
+                "invalid_value",
+            ),
+            (
+                serde_json::Error::invalid_length(1, &"a tuple of size 2"),
+                "invalid_length",
+            ),
+            (serde_json::Error::custom("invalid date"), "invalid"),
+            (serde_json::from_str::<Value>("{").unwrap_err(), "invalid"),
+        ];
+        for (error, code) in &cases {
+            assert_eq!(deserialization_error_code(error), *code, "{error}");
+        }
+
+        // Each message prefix is pinned by a case
+        for (prefix, code) in DESERIALIZATION_ERROR_CODES {
+            assert!(
+                cases.iter().any(|(_, case)| *case == code),
+                "{prefix} is not tested"
+            );
+        }
+    }
+}
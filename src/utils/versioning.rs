@@ -0,0 +1,207 @@
+use chrono::{DateTime, Utc};
+
+tokio::task_local! {
+    /// Version of the REST API requested by the current request
+    pub static API_VERSION: ApiVersion;
+}
+
+/// Base path of the REST API, followed by the version (e.g. `/api/v1`) or not
+pub const API_BASE_PATH: &str = "/api";
+
+/// Versions of the REST API.
+///
+/// The versions are served side by side, under their own path prefix (e.g. `/api/v2/book`), or
+/// under `/api` (e.g. `/api/book`) for the version selected with the `version` parameter of the
+/// `Accept` header (e.g. `application/json; version=2`), the latest one by default.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ApiVersion {
+    /// Lists are rendered as `{data, total}`
+    #[default]
+    V1,
+    /// Lists are rendered as `{data, meta: {total}}`, and errors are always problem details
+    V2,
+}
+
+impl ApiVersion {
+    pub const ALL: [Self; 2] = [Self::V1, Self::V2];
+
+    /// Version of the unversioned paths without `version` parameter
+    pub const LATEST: Self = Self::V2;
+
+    pub fn number(&self) -> u8 {
+        match self {
+            Self::V1 => 1,
+            Self::V2 => 2,
+        }
+    }
+
+    /// Label of the version (e.g. `v1`), as in its path prefix and in the metrics
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::V1 => "v1",
+            Self::V2 => "v2",
+        }
+    }
+
+    /// Path prefix of the version (e.g. `/api/v1`)
+    pub fn prefix(&self) -> String {
+        format!("{API_BASE_PATH}/{}", self.label())
+    }
+
+    /// Version with this number (e.g. `2` or `v2`)
+    pub fn parse(number: &str) -> Option<Self> {
+        let number = number.trim().trim_matches('"');
+        let number = number.strip_prefix('v').unwrap_or(number);
+
+        Self::ALL
+            .into_iter()
+            .find(|version| number.parse::<u8>().ok() == Some(version.number()))
+    }
+
+    /// Version of the path prefix, or `None` if the path has no version prefix
+    pub fn from_path(path: &str) -> Option<Self> {
+        let label = path
+            .strip_prefix(API_BASE_PATH)?
+            .strip_prefix('/')?
+            .split('/')
+            .next()?;
+
+        Self::ALL
+            .into_iter()
+            .find(|version| version.label() == label)
+    }
+
+    /// Version selected with the `version` parameter of the first media range which has one.
+    ///
+    /// Returns the latest version without parameter, and an error with the requested version if it
+    /// is not supported.
+    pub fn from_accept(accept: Option<&str>) -> Result<Self, String> {
+        let requested = accept
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|range| range.trim().parse::<mime::Mime>().ok())
+            .find_map(|range| {
+                range
+                    .get_param("version")
+                    .map(|version| version.as_str().to_owned())
+            });
+
+        match requested {
+            None => Ok(Self::LATEST),
+            Some(requested) => Self::parse(&requested).ok_or(requested),
+        }
+    }
+
+    /// Version of the current request, the first one outside of a request to the REST API
+    pub fn current() -> Self {
+        API_VERSION.try_with(|version| *version).unwrap_or_default()
+    }
+}
+
+/// Deprecation of a version, announced with the `Deprecation` and `Sunset` response headers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiDeprecation {
+    pub version: ApiVersion,
+    /// Date from which the version is deprecated, which may be in the future
+    pub deprecated_at: DateTime<Utc>,
+    /// Date after which the version may not be served anymore
+    pub sunset_at: Option<DateTime<Utc>>,
+}
+
+impl ApiDeprecation {
+    /// Deprecation of a version from the RFC 3339 dates of the configuration, `None` if the
+    /// deprecation date is empty
+    pub fn from_config(
+        version: ApiVersion,
+        deprecated_at: &str,
+        sunset_at: &str,
+    ) -> Result<Option<Self>, String> {
+        let parse = |date: &str| {
+            DateTime::parse_from_rfc3339(date)
+                .map(|date| date.with_timezone(&Utc))
+                .map_err(|err| {
+                    format!("invalid {} deprecation date {date}: {err}", version.label())
+                })
+        };
+
+        match (deprecated_at.trim(), sunset_at.trim()) {
+            ("", "") => Ok(None),
+            ("", _) => Err(format!(
+                "the {} sunset date requires a deprecation date",
+                version.label()
+            )),
+            (deprecated_at, sunset_at) => Ok(Some(Self {
+                version,
+                deprecated_at: parse(deprecated_at)?,
+                sunset_at: match sunset_at {
+                    "" => None,
+                    sunset_at => Some(parse(sunset_at)?),
+                },
+            })),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_api_version_from_path() {
+        assert_eq!(ApiVersion::from_path("/api/v1/book"), Some(ApiVersion::V1));
+        assert_eq!(ApiVersion::from_path("/api/v2"), Some(ApiVersion::V2));
+        assert_eq!(ApiVersion::from_path("/api/v3/book"), None);
+        assert_eq!(ApiVersion::from_path("/api/book"), None);
+        assert_eq!(ApiVersion::from_path("/apiv1/book"), None);
+        assert_eq!(ApiVersion::from_path("/graphql"), None);
+    }
+
+    #[test]
+    fn test_api_version_from_accept() {
+        assert_eq!(ApiVersion::from_accept(None), Ok(ApiVersion::LATEST));
+        assert_eq!(
+            ApiVersion::from_accept(Some("application/json")),
+            Ok(ApiVersion::LATEST)
+        );
+        assert_eq!(
+            ApiVersion::from_accept(Some("application/json; version=1")),
+            Ok(ApiVersion::V1)
+        );
+        assert_eq!(
+            ApiVersion::from_accept(Some("text/csv, application/json;version=\"v2\"")),
+            Ok(ApiVersion::V2)
+        );
+        assert_eq!(
+            ApiVersion::from_accept(Some("application/json; version=3")),
+            Err(String::from("3"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_api_version_current() {
+        assert_eq!(ApiVersion::current(), ApiVersion::V1);
+
+        let current = API_VERSION
+            .scope(ApiVersion::V2, async { ApiVersion::current() })
+            .await;
+        assert_eq!(current, ApiVersion::V2);
+    }
+
+    #[test]
+    fn test_api_deprecation_from_config() {
+        assert_eq!(
+            ApiDeprecation::from_config(ApiVersion::V1, "", ""),
+            Ok(None)
+        );
+
+        let deprecation =
+            ApiDeprecation::from_config(ApiVersion::V1, "2026-01-01T00:00:00Z", "").unwrap();
+        assert_eq!(
+            deprecation.map(|deprecation| deprecation.deprecated_at.timestamp()),
+            Some(1767225600)
+        );
+
+        assert!(ApiDeprecation::from_config(ApiVersion::V1, "", "2027-01-01T00:00:00Z").is_err());
+        assert!(ApiDeprecation::from_config(ApiVersion::V1, "tomorrow", "").is_err());
+    }
+}
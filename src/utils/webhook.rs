@@ -0,0 +1,183 @@
+use crate::utils::hash::hmac_sha256_hex;
+use reqwest::Url;
+use std::{net::IpAddr, time::Duration};
+
+/// Longest delay between two attempts of a delivery
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(6 * 3600);
+
+/// Value of the `X-Webhook-Signature` header: HMAC-SHA256 of `{timestamp}.{body}`,
+/// where `timestamp` is the value of the `X-Webhook-Timestamp` header
+pub fn signature(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut content = format!("{timestamp}.").into_bytes();
+    content.extend_from_slice(body);
+
+    format!("sha256={}", hmac_sha256_hex(secret.as_bytes(), &content))
+}
+
+/// Delay before the next attempt of a delivery which failed `attempts` times,
+/// doubled after each failure
+pub fn retry_delay(base: Duration, attempts: u32) -> Duration {
+    base.checked_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+        .unwrap_or(RETRY_MAX_DELAY)
+        .min(RETRY_MAX_DELAY)
+}
+
+/// Parse the comma-separated hosts which webhooks may target even if they are not public
+pub fn parse_allowed_hosts(hosts: &str) -> Vec<String> {
+    hosts
+        .split(',')
+        .map(|host| host.trim().trim_matches(['[', ']']).to_lowercase())
+        .filter(|host| !host.is_empty())
+        .collect()
+}
+
+/// Check if a host (a domain or an IP address) is one of the allowed hosts
+pub fn is_allowed_host(host: &str, allowed_hosts: &[String]) -> bool {
+    let host = host.trim_matches(['[', ']']);
+    allowed_hosts
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(host))
+}
+
+/// Check if an IP address is reachable from the internet, as opposed to the loopback, private,
+/// link-local, shared, multicast and reserved addresses
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || first == 0
+                // Shared address space (100.64.0.0/10) and reserved addresses (240.0.0.0/4)
+                || (first == 100 && second & 0xc0 == 64)
+                || first >= 240)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ip(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // Unique local (fc00::/7) and link-local (fe80::/10) addresses
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80)
+            }
+        },
+    }
+}
+
+/// Check that a webhook URL targets a public HTTP endpoint.
+///
+/// The scheme must be `http` or `https`, and an IP address host must be public. The addresses
+/// of a domain can change, so they are checked when the deliveries resolve them; only `localhost`
+/// domains are refused here. The `allowed_hosts` are accepted anyway (e.g. for tests).
+pub fn check_url(url: &str, allowed_hosts: &[String]) -> Result<(), String> {
+    let url = Url::parse(url).map_err(|err| err.to_string())?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(String::from("scheme must be `http` or `https`"));
+    }
+
+    let host = url
+        .host_str()
+        .ok_or_else(|| String::from("host is missing"))?;
+    if is_allowed_host(host, allowed_hosts) {
+        return Ok(());
+    }
+
+    let public = match host.trim_matches(['[', ']']).parse::<IpAddr>() {
+        Ok(ip) => is_public_ip(ip),
+        Err(_) => {
+            let domain = host.trim_end_matches('.');
+            domain != "localhost" && !domain.ends_with(".localhost")
+        }
+    };
+    match public {
+        true => Ok(()),
+        false => Err(format!("host `{host}` is not a public address")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_signature() {
+        // echo -n '1700000000.{"a":1}' | openssl dgst -sha256 -hmac 'secret'
+        assert_eq!(
+            signature("secret", 1_700_000_000, br#"{"a":1}"#),
+            "sha256=49f24e537407743fa4a0242bb63b94b9a47ee99cbbe071ccd8a22550ae411686"
+        );
+    }
+
+    #[test]
+    fn test_is_public_ip() {
+        for ip in [
+            "93.184.216.34",
+            "2606:2800:220:1::1",
+            "::ffff:93.184.216.34",
+        ] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[test]
+    fn test_check_url() {
+        assert_eq!(check_url("https://example.com/hook", &[]), Ok(()));
+        assert_eq!(check_url("http://93.184.216.34:8080/hook", &[]), Ok(()));
+
+        for url in [
+            "ftp://example.com/hook",
+            "file:///etc/passwd",
+            "http://localhost/hook",
+            "http://api.localhost./hook",
+            "http://127.0.0.1/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://[::1]/hook",
+            "http://[::ffff:10.0.0.1]/hook",
+        ] {
+            assert!(check_url(url, &[]).is_err(), "{url}");
+        }
+
+        let allowed_hosts = parse_allowed_hosts(" localhost, [::1],,127.0.0.1");
+        assert_eq!(allowed_hosts, ["localhost", "::1", "127.0.0.1"]);
+        assert_eq!(check_url("http://localhost/hook", &allowed_hosts), Ok(()));
+        assert_eq!(check_url("http://[::1]:3000/hook", &allowed_hosts), Ok(()));
+        assert_eq!(check_url("http://127.0.0.1/hook", &allowed_hosts), Ok(()));
+        assert!(check_url("http://10.0.0.1/hook", &allowed_hosts).is_err());
+        assert!(check_url("ftp://localhost/hook", &allowed_hosts).is_err());
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let base = Duration::from_secs(10);
+        assert_eq!(retry_delay(base, 1), Duration::from_secs(10));
+        assert_eq!(retry_delay(base, 2), Duration::from_secs(20));
+        assert_eq!(retry_delay(base, 4), Duration::from_secs(80));
+        assert_eq!(retry_delay(base, 40), RETRY_MAX_DELAY);
+        assert_eq!(retry_delay(Duration::ZERO, 3), Duration::ZERO);
+    }
+}
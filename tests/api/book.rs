@@ -1,9 +1,21 @@
-use super::helpers::book::{TestBook, create, delete, fetch_all, fetch_one, update};
+use super::helpers::book::{
+    TestBook, batch, create, delete, export, fetch_all, fetch_all_with_headers, fetch_history,
+    fetch_one, fetch_one_with_headers, fetch_trash, import, patch, restore, revert, update,
+    update_if_match,
+};
+use crate::api::helpers::TestResponse;
 use crate::{
     api::helpers::TestPaginateResponse,
     helper::{TestApp, TestAppBuilder},
 };
 use axum::http::StatusCode;
+use book_api::{
+    config::Config,
+    repositories::{book::BookRepository, idempotency::IdempotencyKeyRepository},
+};
+use chrono::{TimeDelta, Utc};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
 use uuid::Uuid;
 
 #[tokio::test]
@@ -19,7 +31,12 @@ async fn test_api_create_book() {
         .to_string(),
     )
     .await;
-    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.status_code, StatusCode::CREATED);
+    let book = TestBook::from_body(&response.body.to_string());
+    assert_eq!(
+        response.headers.get("location"),
+        Some(&format!("/api/v1/book/{}", book.id))
+    );
 }
 
 #[tokio::test]
@@ -30,6 +47,78 @@ async fn test_api_create_book_invalid_json() {
     assert_eq!(response.status_code, StatusCode::BAD_REQUEST);
 }
 
+#[tokio::test]
+async fn test_api_create_book_invalid_fields() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = create(&app, serde_json::json!({"title": 1}).to_string()).await;
+    assert_eq!(response.status_code, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response.body["type"], "/problems/unprocessable-entity");
+    assert_eq!(
+        response.body["errors"],
+        serde_json::json!([{
+            "field": "title",
+            "code": "invalid_type",
+            "message": "invalid type: integer `1`, expected a string",
+            "params": {},
+        }])
+    );
+
+    let response = create(&app, serde_json::json!({"title": "foo"}).to_string()).await;
+    assert_eq!(response.status_code, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        response.body["detail"],
+        "invalid request data: author: is required"
+    );
+    assert_eq!(response.body["errors"][0]["field"], "author");
+    assert_eq!(response.body["errors"][0]["code"], "required");
+}
+
+#[tokio::test]
+async fn test_api_create_book_duplicate() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let body = serde_json::json!({"title": "foo", "author": "bar"}).to_string();
+    let existing = TestBook::from_body(&create(&app, body.clone()).await.body.to_string());
+
+    let response = create(&app, body).await;
+    assert_eq!(response.status_code, StatusCode::CONFLICT);
+    assert_eq!(response.body["type"], "/problems/conflict");
+    assert_eq!(response.body["constraint"], "book_title_author_key");
+    assert_eq!(response.body["existing_id"], existing.id);
+
+    let other = create(
+        &app,
+        serde_json::json!({"title": "other", "author": "bar"}).to_string(),
+    )
+    .await;
+    let other = TestBook::from_body(&other.body.to_string());
+    let response = update(
+        &app,
+        serde_json::json!({"title": "foo", "author": "bar"}).to_string(),
+        &other.id,
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::CONFLICT);
+    assert_eq!(response.body["existing_id"], existing.id);
+}
+
+#[tokio::test]
+async fn test_api_create_book_too_long() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = create(
+        &app,
+        serde_json::json!({"title": "a".repeat(43), "author": "bar"}).to_string(),
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        response.body["detail"],
+        "value too long for type character varying(42)"
+    );
+}
+
 #[tokio::test]
 async fn test_api_fetch_all_books() {
     let app: TestApp = TestAppBuilder::new().await.build();
@@ -65,10 +154,10 @@ async fn test_api_fetch_all_books_invalid_filter() {
 }
 
 #[tokio::test]
-async fn test_api_fetch_one_book() {
+async fn test_api_fetch_all_books_sparse_fields() {
     let app: TestApp = TestAppBuilder::new().await.build();
 
-    let response = create(
+    create(
         &app,
         serde_json::json!({
             "title": "foo",
@@ -78,33 +167,242 @@ async fn test_api_fetch_one_book() {
     )
     .await;
 
+    let response = fetch_all(&app, Some("fields=title,id,updated_at")).await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body["total"], 1);
+
+    let book = response.body["data"][0]
+        .as_object()
+        .expect("book must be an object");
+    let mut keys = book.keys().map(String::as_str).collect::<Vec<_>>();
+    keys.sort();
+    assert_eq!(keys, ["id", "title", "updated_at"]);
+    assert_eq!(book["title"], "foo");
+    assert!(book["updated_at"].is_null());
+}
+
+#[tokio::test]
+async fn test_api_fetch_all_books_unknown_field() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = fetch_all(&app, Some("fields=id,isbn")).await;
+    assert_eq!(response.status_code, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.body["detail"],
+        "unknown field `isbn` in `fields`, valid fields are: id, title, author, created_at, updated_at, version, deleted_at"
+    );
+}
+
+#[tokio::test]
+async fn test_api_fetch_all_books_csv() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    create(
+        &app,
+        serde_json::json!({
+            "title": "foo, the book",
+            "author": "bar",
+        })
+        .to_string(),
+    )
+    .await;
+
+    let response =
+        fetch_all_with_headers(&app, Some("fields=title,author"), &[("Accept", "text/csv")]).await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(
+        response.headers.get("content-type"),
+        Some(&String::from("text/csv; charset=utf-8"))
+    );
+    assert_eq!(
+        String::from_utf8(response.raw_body).unwrap(),
+        "title,author\n\"foo, the book\",bar\n"
+    );
+}
+
+#[tokio::test]
+async fn test_api_fetch_one_book_msgpack_and_xml() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = create(
+        &app,
+        serde_json::json!({
+            "title": "foo",
+            "author": "bar",
+        })
+        .to_string(),
+    )
+    .await;
     let book_id = TestBook::from_body(&response.body.to_string()).id;
 
-    let response = fetch_one(&app, &book_id).await;
-    let body = TestBook::from_body(&response.body.to_string());
+    let response =
+        fetch_one_with_headers(&app, &book_id, &[("Accept", "application/msgpack")]).await;
     assert_eq!(response.status_code, StatusCode::OK);
-    assert_eq!(body.id, book_id);
-    assert_eq!(body.updated_at, None);
+    assert_eq!(
+        response.headers.get("content-type"),
+        Some(&String::from("application/msgpack"))
+    );
+    let book: serde_json::Value = rmp_serde::from_slice(&response.raw_body).unwrap();
+    assert_eq!(book["id"], book_id);
+    assert_eq!(book["title"], "foo");
+
+    let response = fetch_one_with_headers(
+        &app,
+        &book_id,
+        &[("Accept", "text/html, application/xml;q=0.9")],
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert!(
+        String::from_utf8(response.raw_body)
+            .unwrap()
+            .contains(&format!("<response><id>{book_id}</id><title>foo</title>"))
+    );
 }
 
 #[tokio::test]
-async fn test_api_fetch_one_book_invalid_id() {
+async fn test_api_fetch_one_book_not_acceptable() {
     let app: TestApp = TestAppBuilder::new().await.build();
 
-    let response = fetch_one(&app, "not_an_uuid").await;
-    assert_eq!(response.status_code, StatusCode::BAD_REQUEST);
+    let response = fetch_one_with_headers(
+        &app,
+        &Uuid::new_v4().to_string(),
+        &[("Accept", "text/html")],
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::NOT_ACCEPTABLE);
+    assert_eq!(response.body["status"], 406);
 }
 
 #[tokio::test]
-async fn test_api_fetch_one_book_unknown_id() {
+async fn test_api_errors_negotiated_format() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = fetch_one_with_headers(
+        &app,
+        &Uuid::new_v4().to_string(),
+        &[("Accept", "application/yaml")],
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::NOT_FOUND);
+    assert_eq!(
+        response.headers.get("content-type"),
+        Some(&String::from("application/yaml; charset=utf-8"))
+    );
+    assert_eq!(
+        String::from_utf8(response.raw_body).unwrap(),
+        "type: /problems/not-found\ntitle: Not Found\nstatus: 404\ndetail: book could not be found\n"
+    );
+
+    let response = TestResponse::with_headers(
+        &app,
+        "/api/v1/book/batch",
+        "GET",
+        None,
+        &[("Accept", "text/csv")],
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        String::from_utf8(response.raw_body).unwrap(),
+        "type,title,status,detail\n/problems/method-not-allowed,Method Not Allowed,405,Method Not Allowed\n"
+    );
+}
+
+#[tokio::test]
+async fn test_api_errors_problem_details() {
     let app: TestApp = TestAppBuilder::new().await.build();
 
+    let response = fetch_one_with_headers(
+        &app,
+        &Uuid::new_v4().to_string(),
+        &[("x-request-id", "request-1")],
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::NOT_FOUND);
+    assert_eq!(
+        response.headers.get("content-type"),
+        Some(&String::from("application/problem+json"))
+    );
+    assert_eq!(
+        response.body,
+        serde_json::json!({
+            "type": "/problems/not-found",
+            "title": "Not Found",
+            "status": 404,
+            "detail": "book could not be found",
+            "instance": "request-1",
+        })
+    );
+
+    // Path parameters
+    let response = TestResponse::new(
+        &app,
+        &format!("/api/v1/webhook/{}/deliveries/abc/retry", Uuid::new_v4()),
+        "POST",
+        None,
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["type"], "/problems/bad-request");
+    assert_eq!(response.body["index"], 1);
+    assert_eq!(response.body["value"], "abc");
+    assert_eq!(response.body["expected_type"], "i64");
+
+    // Query parameters
+    let response = fetch_all(&app, Some("p=abc")).await;
+    assert_eq!(response.status_code, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["type"], "/problems/bad-request");
+    assert!(response.body["instance"].is_null());
+
+    // Plain text rejections
+    let response =
+        TestResponse::with_headers(&app, "/api/v1/book", "POST", Some("{}".to_owned()), &[]).await;
+    assert_eq!(response.status_code, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(
+        response.headers.get("content-type"),
+        Some(&String::from("application/problem+json"))
+    );
+    assert_eq!(response.body["type"], "/problems/unsupported-media-type");
+    assert_eq!(response.body["title"], "Unsupported Media Type");
+    assert_eq!(
+        response.body["detail"],
+        "Expected request with `Content-Type: application/json`"
+    );
+}
+
+#[tokio::test]
+async fn test_api_errors_legacy_format() {
+    let app: TestApp = TestAppBuilder::new()
+        .await
+        .with_config(Config {
+            legacy_error_format: true,
+            ..Default::default()
+        })
+        .build();
+
     let response = fetch_one(&app, &Uuid::new_v4().to_string()).await;
     assert_eq!(response.status_code, StatusCode::NOT_FOUND);
+    assert_eq!(
+        response.headers.get("content-type"),
+        Some(&String::from("application/json"))
+    );
+    assert_eq!(
+        response.body,
+        serde_json::json!({"code": 404, "message": "book could not be found"})
+    );
+
+    let response =
+        TestResponse::with_headers(&app, "/api/v1/book", "POST", Some("{}".to_owned()), &[]).await;
+    assert_eq!(response.status_code, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(
+        response.headers.get("content-type"),
+        Some(&String::from("text/plain; charset=utf-8"))
+    );
 }
 
 #[tokio::test]
-async fn test_api_update_book() {
+async fn test_api_fetch_one_book() {
     let app: TestApp = TestAppBuilder::new().await.build();
 
     let response = create(
@@ -119,46 +417,179 @@ async fn test_api_update_book() {
 
     let book_id = TestBook::from_body(&response.body.to_string()).id;
 
-    let response = update(
+    let response = fetch_one(&app, &book_id).await;
+    let body = TestBook::from_body(&response.body.to_string());
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(body.id, book_id);
+    assert_eq!(body.updated_at, None);
+}
+
+#[tokio::test]
+async fn test_api_fetch_one_book_include_revisions() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = create(
         &app,
         serde_json::json!({
-            "title": "bar",
-            "author": "foo",
+            "title": "foo",
+            "author": "bar",
+        })
+        .to_string(),
+    )
+    .await;
+    let book_id = TestBook::from_body(&response.body.to_string()).id;
+
+    update(
+        &app,
+        serde_json::json!({
+            "title": "baz",
+            "author": "bar",
         })
         .to_string(),
         &book_id,
     )
     .await;
+
+    let response = TestResponse::new(
+        &app,
+        &format!("/api/v1/book/{book_id}?include=revisions"),
+        "GET",
+        None,
+    )
+    .await;
     assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body["title"], "baz");
+    assert_eq!(response.body["revisions"][0]["operation"], "create");
+    assert_eq!(response.body["revisions"][1]["operation"], "update");
 
-    let book = TestBook::from_body(&response.body.to_string());
-    assert_eq!(book.title, String::from("bar"));
-    assert_eq!(book.author, String::from("foo"));
-    assert_ne!(book.updated_at, None)
+    let response = fetch_one(&app, &book_id).await;
+    assert_eq!(response.body.get("revisions"), None);
 }
 
 #[tokio::test]
-async fn test_api_update_book_unknown_id() {
+async fn test_api_fetch_all_books_include_revisions() {
     let app: TestApp = TestAppBuilder::new().await.build();
 
-    let response = update(
+    for i in 0..2 {
+        create(
+            &app,
+            serde_json::json!({
+                "title": format!("foo-{i}"),
+                "author": "bar",
+            })
+            .to_string(),
+        )
+        .await;
+    }
+
+    let response = fetch_all(&app, Some("fields=title&include=revisions&s=+title")).await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    for (i, book) in response.body["data"].as_array().unwrap().iter().enumerate() {
+        assert_eq!(book["title"], format!("foo-{i}"));
+        assert!(book["id"].is_string());
+        assert_eq!(book["revisions"].as_array().unwrap().len(), 1);
+        assert_eq!(book["revisions"][0]["book_id"], book["id"]);
+    }
+}
+
+#[tokio::test]
+async fn test_api_fetch_books_unknown_relation() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = fetch_all(&app, Some("include=reviews")).await;
+    assert_eq!(response.status_code, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["status"], 400);
+    assert_eq!(
+        response.body["detail"],
+        "unknown relation `reviews` in `include`, valid relations are: revisions"
+    );
+
+    let response = TestResponse::new(
+        &app,
+        &format!("/api/v1/book/{}?include=reviews", Uuid::new_v4()),
+        "GET",
+        None,
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_api_fetch_one_book_not_modified() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = create(
+        &app,
+        serde_json::json!({
+            "title": "foo",
+            "author": "bar",
+        })
+        .to_string(),
+    )
+    .await;
+
+    let book_id = TestBook::from_body(&response.body.to_string()).id;
+
+    let response = fetch_one(&app, &book_id).await;
+    let etag = response.headers.get("etag").unwrap().clone();
+    let last_modified = response.headers.get("last-modified").unwrap().clone();
+
+    let response = fetch_one_with_headers(&app, &book_id, &[("If-None-Match", &etag)]).await;
+    assert_eq!(response.status_code, StatusCode::NOT_MODIFIED);
+    assert_eq!(response.body, serde_json::Value::Null);
+    assert_eq!(response.headers.get("etag"), Some(&etag));
+    assert_eq!(response.headers.get("vary"), Some(&String::from("accept")));
+
+    // The other representations of the same version are not validated by the JSON entity tag
+    let response = fetch_one_with_headers(
+        &app,
+        &book_id,
+        &[("If-None-Match", &etag), ("Accept", "text/csv")],
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.headers.get("etag").unwrap(), "\"1-csv\"");
+
+    let response = TestResponse::with_headers(
+        &app,
+        &format!("/api/v1/book/{book_id}?include=revisions"),
+        "GET",
+        None,
+        &[("If-None-Match", &etag)],
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    let include_etag = response.headers.get("etag").unwrap().clone();
+    assert_eq!(include_etag, "\"1-json+revisions\"");
+
+    let response =
+        fetch_one_with_headers(&app, &book_id, &[("If-Modified-Since", &last_modified)]).await;
+    assert_eq!(response.status_code, StatusCode::NOT_MODIFIED);
+
+    // The entity tags of all the representations match their version in `If-Match`
+    let response = update_if_match(
         &app,
         serde_json::json!({
             "title": "bar",
             "author": "foo",
         })
         .to_string(),
-        &Uuid::new_v4().to_string(),
+        &book_id,
+        &include_etag,
     )
     .await;
-    assert_eq!(response.status_code, StatusCode::NOT_FOUND);
+    assert_eq!(response.status_code, StatusCode::OK);
+
+    let response = fetch_one_with_headers(&app, &book_id, &[("If-None-Match", &etag)]).await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_ne!(response.headers.get("etag"), Some(&etag));
 }
 
 #[tokio::test]
-async fn test_api_delete_book() {
+async fn test_api_fetch_all_books_not_modified() {
     let app: TestApp = TestAppBuilder::new().await.build();
 
-    let response = create(
+    create(
         &app,
         serde_json::json!({
             "title": "foo",
@@ -168,22 +599,1330 @@ async fn test_api_delete_book() {
     )
     .await;
 
-    let book_id = TestBook::from_body(&response.body.to_string()).id;
+    let response = fetch_all(&app, None).await;
+    let etag = response.headers.get("etag").unwrap().clone();
+    assert_eq!(response.headers.get("last-modified"), None);
 
-    let response = delete(&app, &book_id).await;
-    assert_eq!(response.status_code, StatusCode::NO_CONTENT);
+    let response = fetch_all_with_headers(&app, None, &[("If-None-Match", &etag)]).await;
+    assert_eq!(response.status_code, StatusCode::NOT_MODIFIED);
+
+    let response = fetch_all_with_headers(
+        &app,
+        None,
+        &[
+            ("If-None-Match", &etag),
+            ("Accept", "application/vnd.api+json"),
+        ],
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_ne!(response.headers.get("etag"), Some(&etag));
+
+    create(
+        &app,
+        serde_json::json!({
+            "title": "baz",
+            "author": "bar",
+        })
+        .to_string(),
+    )
+    .await;
+
+    let response = fetch_all_with_headers(&app, None, &[("If-None-Match", &etag)]).await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body["total"], 2);
 }
 
 #[tokio::test]
-async fn test_api_delete_book_invalid_id() {
+async fn test_api_fetch_one_book_invalid_id() {
     let app: TestApp = TestAppBuilder::new().await.build();
-    let response = delete(&app, "not_an_uuid").await;
+
+    let response = fetch_one(&app, "not_an_uuid").await;
     assert_eq!(response.status_code, StatusCode::BAD_REQUEST);
 }
 
 #[tokio::test]
-async fn test_api_delete_book_unknown_id() {
+async fn test_api_fetch_one_book_unknown_id() {
     let app: TestApp = TestAppBuilder::new().await.build();
-    let response = delete(&app, &Uuid::new_v4().to_string()).await;
-    assert_eq!(response.status_code, StatusCode::INTERNAL_SERVER_ERROR);
+
+    let response = fetch_one(&app, &Uuid::new_v4().to_string()).await;
+    assert_eq!(response.status_code, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_api_update_book() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = create(
+        &app,
+        serde_json::json!({
+            "title": "foo",
+            "author": "bar",
+        })
+        .to_string(),
+    )
+    .await;
+
+    let book_id = TestBook::from_body(&response.body.to_string()).id;
+
+    let response = update(
+        &app,
+        serde_json::json!({
+            "title": "bar",
+            "author": "foo",
+        })
+        .to_string(),
+        &book_id,
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::OK);
+
+    let book = TestBook::from_body(&response.body.to_string());
+    assert_eq!(book.title, String::from("bar"));
+    assert_eq!(book.author, String::from("foo"));
+    assert_ne!(book.updated_at, None)
+}
+
+#[tokio::test]
+async fn test_api_update_book_unknown_id() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let id = Uuid::new_v4().to_string();
+    let response = update(
+        &app,
+        serde_json::json!({
+            "title": "bar",
+            "author": "foo",
+        })
+        .to_string(),
+        &id,
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::CREATED);
+    assert_eq!(
+        response.headers.get("location"),
+        Some(&format!("/api/v1/book/{id}"))
+    );
+    assert_eq!(response.body["id"], id);
+    assert_eq!(response.body["version"], 1);
+
+    let response = fetch_one(&app, &id).await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body["title"], "bar");
+
+    // A book in the trash is not created again
+    delete(&app, &id).await;
+    let response = update(
+        &app,
+        serde_json::json!({"title": "bar", "author": "foo"}).to_string(),
+        &id,
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn test_api_update_book_unknown_id_upsert_disabled() {
+    let app: TestApp = TestAppBuilder::new()
+        .await
+        .with_config(Config {
+            put_upsert_disabled: true,
+            ..Default::default()
+        })
+        .build();
+
+    let id = Uuid::new_v4().to_string();
+    let response = update(
+        &app,
+        serde_json::json!({"title": "bar", "author": "foo"}).to_string(),
+        &id,
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::NOT_FOUND);
+
+    let response = fetch_one(&app, &id).await;
+    assert_eq!(response.status_code, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_api_update_book_if_match() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = create(
+        &app,
+        serde_json::json!({
+            "title": "foo",
+            "author": "bar",
+        })
+        .to_string(),
+    )
+    .await;
+
+    let book_id = TestBook::from_body(&response.body.to_string()).id;
+
+    let response = fetch_one(&app, &book_id).await;
+    let etag = response.headers.get("etag").unwrap().clone();
+    assert_eq!(etag, "\"1\"");
+
+    let response = update_if_match(
+        &app,
+        serde_json::json!({
+            "title": "bar",
+            "author": "foo",
+        })
+        .to_string(),
+        &book_id,
+        &etag,
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.headers.get("etag").unwrap(), "\"2\"");
+
+    let response = update_if_match(
+        &app,
+        serde_json::json!({
+            "title": "baz",
+            "author": "foo",
+        })
+        .to_string(),
+        &book_id,
+        &etag,
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::PRECONDITION_FAILED);
+
+    let response = fetch_one(&app, &book_id).await;
+    let book = TestBook::from_body(&response.body.to_string());
+    assert_eq!(book.title, String::from("bar"));
+}
+
+#[tokio::test]
+async fn test_api_update_book_precondition_required() {
+    let app: TestApp = TestAppBuilder::new()
+        .await
+        .with_config(Config {
+            precondition_required: true,
+            ..Default::default()
+        })
+        .build();
+
+    let response = create(
+        &app,
+        serde_json::json!({
+            "title": "foo",
+            "author": "bar",
+        })
+        .to_string(),
+    )
+    .await;
+
+    let book_id = TestBook::from_body(&response.body.to_string()).id;
+
+    let response = update(
+        &app,
+        serde_json::json!({
+            "title": "bar",
+            "author": "foo",
+        })
+        .to_string(),
+        &book_id,
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::PRECONDITION_REQUIRED);
+
+    let response = delete(&app, &book_id).await;
+    assert_eq!(response.status_code, StatusCode::PRECONDITION_REQUIRED);
+
+    let response = update_if_match(
+        &app,
+        serde_json::json!({
+            "title": "bar",
+            "author": "foo",
+        })
+        .to_string(),
+        &book_id,
+        "*",
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_api_patch_book_merge_patch() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = create(
+        &app,
+        serde_json::json!({
+            "title": "foo",
+            "author": "bar",
+        })
+        .to_string(),
+    )
+    .await;
+
+    let book_id = TestBook::from_body(&response.body.to_string()).id;
+
+    let response = patch(
+        &app,
+        serde_json::json!({
+            "title": "baz",
+        })
+        .to_string(),
+        &book_id,
+        "application/merge-patch+json",
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::OK);
+
+    let book = TestBook::from_body(&response.body.to_string());
+    assert_eq!(book.title, String::from("baz"));
+    assert_eq!(book.author, String::from("bar"));
+    assert_ne!(book.updated_at, None)
+}
+
+#[tokio::test]
+async fn test_api_patch_book_json_patch() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = create(
+        &app,
+        serde_json::json!({
+            "title": "foo",
+            "author": "bar",
+        })
+        .to_string(),
+    )
+    .await;
+
+    let book_id = TestBook::from_body(&response.body.to_string()).id;
+
+    let response = patch(
+        &app,
+        serde_json::json!([
+            { "op": "test", "path": "/title", "value": "foo" },
+            { "op": "replace", "path": "/author", "value": "baz" },
+        ])
+        .to_string(),
+        &book_id,
+        "application/json-patch+json",
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::OK);
+
+    let book = TestBook::from_body(&response.body.to_string());
+    assert_eq!(book.title, String::from("foo"));
+    assert_eq!(book.author, String::from("baz"));
+
+    let response = patch(
+        &app,
+        serde_json::json!([
+            { "op": "test", "path": "/title", "value": "bar" },
+        ])
+        .to_string(),
+        &book_id,
+        "application/json-patch+json",
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn test_api_patch_book_unknown_path() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = create(
+        &app,
+        serde_json::json!({
+            "title": "foo",
+            "author": "bar",
+        })
+        .to_string(),
+    )
+    .await;
+
+    let book_id = TestBook::from_body(&response.body.to_string()).id;
+
+    let response = patch(
+        &app,
+        serde_json::json!([
+            { "op": "replace", "path": "/isbn", "value": "123" },
+        ])
+        .to_string(),
+        &book_id,
+        "application/json-patch+json",
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let response = patch(
+        &app,
+        serde_json::json!({
+            "isbn": "123",
+        })
+        .to_string(),
+        &book_id,
+        "application/merge-patch+json",
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn test_api_patch_book_unsupported_content_type() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = create(
+        &app,
+        serde_json::json!({
+            "title": "foo",
+            "author": "bar",
+        })
+        .to_string(),
+    )
+    .await;
+
+    let book_id = TestBook::from_body(&response.body.to_string()).id;
+
+    let response = patch(
+        &app,
+        serde_json::json!({
+            "title": "baz",
+        })
+        .to_string(),
+        &book_id,
+        "application/json",
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+#[tokio::test]
+async fn test_api_patch_book_unknown_id() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = patch(
+        &app,
+        serde_json::json!({
+            "title": "baz",
+        })
+        .to_string(),
+        &Uuid::new_v4().to_string(),
+        "application/merge-patch+json",
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_api_delete_book() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = create(
+        &app,
+        serde_json::json!({
+            "title": "foo",
+            "author": "bar",
+        })
+        .to_string(),
+    )
+    .await;
+
+    let book_id = TestBook::from_body(&response.body.to_string()).id;
+
+    let response = delete(&app, &book_id).await;
+    assert_eq!(response.status_code, StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn test_api_delete_book_moves_it_to_trash() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = create(
+        &app,
+        serde_json::json!({
+            "title": "foo",
+            "author": "bar",
+        })
+        .to_string(),
+    )
+    .await;
+
+    let book_id = TestBook::from_body(&response.body.to_string()).id;
+
+    let response = delete(&app, &book_id).await;
+    assert_eq!(response.status_code, StatusCode::NO_CONTENT);
+
+    let response = fetch_one(&app, &book_id).await;
+    assert_eq!(response.status_code, StatusCode::NOT_FOUND);
+
+    let response = fetch_all(&app, None).await;
+    assert_eq!(response.body["total"], 0);
+
+    let response = fetch_trash(&app).await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body["total"], 1);
+    assert_eq!(response.body["data"][0]["id"], book_id);
+    assert_ne!(
+        response.body["data"][0]["deleted_at"],
+        serde_json::Value::Null
+    );
+
+    let response = delete(&app, &book_id).await;
+    assert_eq!(response.status_code, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_api_restore_book() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = create(
+        &app,
+        serde_json::json!({
+            "title": "foo",
+            "author": "bar",
+        })
+        .to_string(),
+    )
+    .await;
+
+    let book_id = TestBook::from_body(&response.body.to_string()).id;
+
+    let response = restore(&app, &book_id).await;
+    assert_eq!(response.status_code, StatusCode::NOT_FOUND);
+
+    delete(&app, &book_id).await;
+
+    let response = restore(&app, &book_id).await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body["deleted_at"], serde_json::Value::Null);
+
+    let response = fetch_one(&app, &book_id).await;
+    assert_eq!(response.status_code, StatusCode::OK);
+
+    let response = fetch_trash(&app).await;
+    assert_eq!(response.body["total"], 0);
+}
+
+#[tokio::test]
+async fn test_api_create_book_after_delete() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = create(
+        &app,
+        serde_json::json!({
+            "title": "foo",
+            "author": "bar",
+        })
+        .to_string(),
+    )
+    .await;
+
+    let book_id = TestBook::from_body(&response.body.to_string()).id;
+    delete(&app, &book_id).await;
+
+    let response = create(
+        &app,
+        serde_json::json!({
+            "title": "foo",
+            "author": "bar",
+        })
+        .to_string(),
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn test_purge_trash() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = create(
+        &app,
+        serde_json::json!({
+            "title": "foo",
+            "author": "bar",
+        })
+        .to_string(),
+    )
+    .await;
+
+    let book_id = TestBook::from_body(&response.body.to_string()).id;
+    delete(&app, &book_id).await;
+
+    let pool = app._database.database().await;
+    let deleted_before = Utc::now() - chrono::TimeDelta::hours(1);
+    assert_eq!(BookRepository::purge(&pool, deleted_before).await, Ok(0));
+    assert_eq!(BookRepository::purge(&pool, Utc::now()).await, Ok(1));
+
+    let response = fetch_trash(&app).await;
+    assert_eq!(response.body["total"], 0);
+
+    let response = restore(&app, &book_id).await;
+    assert_eq!(response.status_code, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_purge_trash_id_not_reused() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = create(
+        &app,
+        serde_json::json!({"title": "foo", "author": "bar"}).to_string(),
+    )
+    .await;
+    let book_id = TestBook::from_body(&response.body.to_string()).id;
+    delete(&app, &book_id).await;
+
+    let pool = app._database.database().await;
+    assert_eq!(BookRepository::purge(&pool, Utc::now()).await, Ok(1));
+
+    // The history of the purged book is kept, its ID cannot be given to a new book
+    let response = update(
+        &app,
+        serde_json::json!({"title": "other", "author": "bar"}).to_string(),
+        &book_id,
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::CONFLICT);
+    assert_eq!(response.body["code"], "purged_id");
+
+    let response = fetch_history(&app, &book_id).await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body.as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_api_delete_book_invalid_id() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+    let response = delete(&app, "not_an_uuid").await;
+    assert_eq!(response.status_code, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_api_delete_book_unknown_id() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+    let response = delete(&app, &Uuid::new_v4().to_string()).await;
+    assert_eq!(response.status_code, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_api_delete_book_idempotent() {
+    let app: TestApp = TestAppBuilder::new()
+        .await
+        .with_config(Config {
+            idempotent_delete: true,
+            ..Default::default()
+        })
+        .build();
+
+    let response = delete(&app, &Uuid::new_v4().to_string()).await;
+    assert_eq!(response.status_code, StatusCode::NO_CONTENT);
+
+    let response = create(
+        &app,
+        serde_json::json!({"title": "foo", "author": "bar"}).to_string(),
+    )
+    .await;
+    let book_id = TestBook::from_body(&response.body.to_string()).id;
+    for _ in 0..2 {
+        let response = delete(&app, &book_id).await;
+        assert_eq!(response.status_code, StatusCode::NO_CONTENT);
+    }
+}
+
+#[tokio::test]
+async fn test_api_batch_books_atomic() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = create(
+        &app,
+        serde_json::json!({
+            "title": "foo",
+            "author": "bar",
+        })
+        .to_string(),
+    )
+    .await;
+
+    let book_id = TestBook::from_body(&response.body.to_string()).id;
+
+    let response = batch(
+        &app,
+        serde_json::json!({
+            "mode": "atomic",
+            "operations": [
+                { "op": "create", "data": { "title": "baz", "author": "bar" } },
+                { "op": "update", "id": book_id, "data": { "title": "foo", "author": "baz" } },
+            ],
+        })
+        .to_string(),
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body[0]["status"], 201);
+    assert_eq!(response.body[1]["status"], 200);
+    assert_eq!(response.body[1]["data"]["author"], "baz");
+
+    let response = fetch_all(&app, None).await;
+    assert_eq!(response.body["total"], 2);
+}
+
+#[tokio::test]
+async fn test_api_batch_books_atomic_rollback() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = batch(
+        &app,
+        serde_json::json!({
+            "mode": "atomic",
+            "operations": [
+                { "op": "create", "data": { "title": "foo", "author": "bar" } },
+                { "op": "delete", "id": Uuid::new_v4() },
+            ],
+        })
+        .to_string(),
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::NOT_FOUND);
+    assert_eq!(response.body["type"], "/problems/not-found");
+    assert_eq!(
+        response.body["detail"],
+        "operation 1 failed: book could not be found"
+    );
+    assert_eq!(response.body["index"], 1);
+
+    let response = fetch_all(&app, None).await;
+    assert_eq!(response.body["total"], 0);
+}
+
+#[tokio::test]
+async fn test_api_batch_books_atomic_rollback_legacy_format() {
+    let app: TestApp = TestAppBuilder::new()
+        .await
+        .with_config(Config {
+            legacy_error_format: true,
+            ..Default::default()
+        })
+        .build();
+
+    let response = batch(
+        &app,
+        serde_json::json!({
+            "mode": "atomic",
+            "operations": [{ "op": "delete", "id": Uuid::new_v4() }],
+        })
+        .to_string(),
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::NOT_FOUND);
+    assert_eq!(
+        response.body,
+        serde_json::json!({"code": 404, "message": "operation 0 failed: book could not be found"})
+    );
+}
+
+#[tokio::test]
+async fn test_api_batch_books_best_effort() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = batch(
+        &app,
+        serde_json::json!({
+            "mode": "best_effort",
+            "operations": [
+                { "op": "create", "data": { "title": "foo", "author": "bar" } },
+                { "op": "delete", "id": Uuid::new_v4() },
+                { "op": "create", "data": { "title": "baz", "author": "bar" } },
+            ],
+        })
+        .to_string(),
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body[0]["status"], 201);
+    assert_eq!(response.body[1]["status"], 404);
+    assert_eq!(response.body[1]["error"]["code"], 404);
+    assert_eq!(response.body[2]["status"], 201);
+
+    let response = fetch_all(&app, None).await;
+    assert_eq!(response.body["total"], 2);
+}
+
+#[tokio::test]
+async fn test_api_batch_books_empty() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = batch(
+        &app,
+        serde_json::json!({
+            "operations": [],
+        })
+        .to_string(),
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_api_create_book_invalid_actor() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+    let body = serde_json::json!({"title": "foo", "author": "bar"}).to_string();
+
+    let too_long = "a".repeat(256);
+    for actor in [too_long.as_str(), "tab\tseparated"] {
+        let response = TestResponse::with_headers(
+            &app,
+            "/api/v1/book",
+            "POST",
+            Some(body.clone()),
+            &[("Content-Type", "application/json"), ("X-Actor", actor)],
+        )
+        .await;
+        assert_eq!(response.status_code, StatusCode::BAD_REQUEST);
+        assert_eq!(response.body["header"], "X-Actor");
+    }
+
+    let response = fetch_all(&app, None).await;
+    assert_eq!(response.body["total"], 0);
+}
+
+#[tokio::test]
+async fn test_api_book_history() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = TestResponse::with_headers(
+        &app,
+        "/api/v1/book",
+        "POST",
+        Some(
+            serde_json::json!({
+                "title": "foo",
+                "author": "bar",
+            })
+            .to_string(),
+        ),
+        &[
+            ("Content-Type", "application/json"),
+            ("X-Actor", "librarian"),
+            ("X-Request-Id", "request-1"),
+        ],
+    )
+    .await;
+
+    let book_id = TestBook::from_body(&response.body.to_string()).id;
+
+    update(
+        &app,
+        serde_json::json!({
+            "title": "baz",
+            "author": "bar",
+        })
+        .to_string(),
+        &book_id,
+    )
+    .await;
+    delete(&app, &book_id).await;
+
+    let response = fetch_history(&app, &book_id).await;
+    assert_eq!(response.status_code, StatusCode::OK);
+
+    let history = response.body.as_array().unwrap();
+    assert_eq!(history.len(), 3);
+
+    assert_eq!(history[0]["revision"], 1);
+    assert_eq!(history[0]["operation"], "create");
+    assert_eq!(history[0]["actor"], "librarian");
+    assert_eq!(history[0]["request_id"], "request-1");
+    assert_eq!(
+        history[0]["changes"],
+        serde_json::json!([
+            { "field": "title", "old": null, "new": "foo" },
+            { "field": "author", "old": null, "new": "bar" },
+        ])
+    );
+
+    assert_eq!(history[1]["operation"], "update");
+    assert_eq!(history[1]["actor"], "anonymous");
+    assert_eq!(
+        history[1]["changes"],
+        serde_json::json!([{ "field": "title", "old": "foo", "new": "baz" }])
+    );
+
+    assert_eq!(history[2]["operation"], "delete");
+    assert_eq!(history[2]["changes"], serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn test_api_book_history_unknown_id() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = fetch_history(&app, &Uuid::new_v4().to_string()).await;
+    assert_eq!(response.status_code, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_api_revert_book() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = create(
+        &app,
+        serde_json::json!({
+            "title": "foo",
+            "author": "bar",
+        })
+        .to_string(),
+    )
+    .await;
+
+    let book_id = TestBook::from_body(&response.body.to_string()).id;
+
+    update(
+        &app,
+        serde_json::json!({
+            "title": "baz",
+            "author": "qux",
+        })
+        .to_string(),
+        &book_id,
+    )
+    .await;
+
+    let response = revert(&app, &book_id, 1).await;
+    assert_eq!(response.status_code, StatusCode::OK);
+
+    let book = TestBook::from_body(&response.body.to_string());
+    assert_eq!(book.title, String::from("foo"));
+    assert_eq!(book.author, String::from("bar"));
+    assert_eq!(response.headers.get("etag").unwrap(), "\"3\"");
+
+    let response = fetch_history(&app, &book_id).await;
+    assert_eq!(response.body[2]["operation"], "revert");
+
+    let response = revert(&app, &book_id, 42).await;
+    assert_eq!(response.status_code, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_api_create_book_idempotency_key() {
+    let app: TestApp = TestAppBuilder::new()
+        .await
+        .with_idempotency(Duration::from_secs(60), Duration::from_secs(60))
+        .build();
+
+    let body = serde_json::json!({
+        "title": "foo",
+        "author": "bar",
+    })
+    .to_string();
+    let headers = [
+        ("Content-Type", "application/json"),
+        ("Idempotency-Key", "key-1"),
+    ];
+
+    let first =
+        TestResponse::with_headers(&app, "/api/v1/book", "POST", Some(body.clone()), &headers)
+            .await;
+    assert_eq!(first.status_code, StatusCode::CREATED);
+    assert_eq!(first.headers.get("idempotent-replayed"), None);
+
+    let second =
+        TestResponse::with_headers(&app, "/api/v1/book", "POST", Some(body), &headers).await;
+    assert_eq!(second.status_code, StatusCode::CREATED);
+    assert_eq!(
+        second.headers.get("idempotent-replayed"),
+        Some(&String::from("true"))
+    );
+    assert_eq!(second.body, first.body);
+    assert_eq!(second.headers.get("etag"), first.headers.get("etag"));
+    assert_eq!(
+        second.headers.get("location"),
+        first.headers.get("location")
+    );
+
+    let response = fetch_all(&app, None).await;
+    assert_eq!(response.body["total"], 1);
+}
+
+#[tokio::test]
+async fn test_api_create_book_idempotency_key_different_body() {
+    let app: TestApp = TestAppBuilder::new()
+        .await
+        .with_idempotency(Duration::from_secs(60), Duration::from_secs(60))
+        .build();
+
+    let headers = [
+        ("Content-Type", "application/json"),
+        ("Idempotency-Key", "key-1"),
+    ];
+
+    let response = TestResponse::with_headers(
+        &app,
+        "/api/v1/book",
+        "POST",
+        Some(
+            serde_json::json!({
+                "title": "foo",
+                "author": "bar",
+            })
+            .to_string(),
+        ),
+        &headers,
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::CREATED);
+
+    let response = TestResponse::with_headers(
+        &app,
+        "/api/v1/book",
+        "POST",
+        Some(
+            serde_json::json!({
+                "title": "baz",
+                "author": "bar",
+            })
+            .to_string(),
+        ),
+        &headers,
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let response = fetch_all(&app, None).await;
+    assert_eq!(response.body["total"], 1);
+}
+
+#[tokio::test]
+async fn test_api_import_books_idempotency_key() {
+    let app: TestApp = TestAppBuilder::new()
+        .await
+        .with_idempotency(Duration::from_secs(60), Duration::from_secs(60))
+        .build();
+
+    // The streamed imports are not buffered to be fingerprinted
+    let response = TestResponse::with_headers(
+        &app,
+        "/api/v1/book/import",
+        "POST",
+        Some(String::from("title,author\nfoo,bar\n")),
+        &[("Content-Type", "text/csv"), ("Idempotency-Key", "key-1")],
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["header"], "Idempotency-Key");
+
+    let response = fetch_all(&app, None).await;
+    assert_eq!(response.body["total"], 0);
+}
+
+#[tokio::test]
+async fn test_api_create_book_idempotency_key_interrupted() {
+    let app: TestApp = TestAppBuilder::new()
+        .await
+        .with_idempotency(Duration::from_secs(60), Duration::from_secs(60))
+        .build();
+    let pool = app.pool().await;
+
+    let body = serde_json::json!({
+        "title": "foo",
+        "author": "bar",
+    })
+    .to_string();
+    let fingerprint = Sha256::digest(format!("POST /api/v1/book\n{body}"))
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+
+    // Requests interrupted before storing their response, e.g. by a crash
+    let now = Utc::now();
+    for (key, locked_until) in [
+        ("locked", now + TimeDelta::seconds(60)),
+        ("expired", now - TimeDelta::seconds(1)),
+    ] {
+        let reserved = IdempotencyKeyRepository::reserve(
+            &pool,
+            key,
+            &fingerprint,
+            &Uuid::new_v4().to_string(),
+            locked_until,
+            now + TimeDelta::seconds(60),
+        )
+        .await
+        .unwrap();
+        assert!(reserved);
+    }
+
+    let send = async |key: &str| {
+        TestResponse::with_headers(
+            &app,
+            "/api/v1/book",
+            "POST",
+            Some(body.clone()),
+            &[
+                ("Content-Type", "application/json"),
+                ("Idempotency-Key", key),
+            ],
+        )
+        .await
+    };
+
+    let response = send("locked").await;
+    assert_eq!(response.status_code, StatusCode::CONFLICT);
+
+    // The expired lock is taken over by the retry
+    let response = send("expired").await;
+    assert_eq!(response.status_code, StatusCode::CREATED);
+    let response = send("expired").await;
+    assert_eq!(response.status_code, StatusCode::CREATED);
+    assert_eq!(
+        response.headers.get("idempotent-replayed"),
+        Some(&String::from("true"))
+    );
+}
+
+#[tokio::test]
+async fn test_api_import_books_csv() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = create(
+        &app,
+        serde_json::json!({
+            "title": "foo",
+            "author": "bar",
+        })
+        .to_string(),
+    )
+    .await;
+    let book_id = TestBook::from_body(&response.body.to_string()).id;
+
+    let body = format!(
+        "id,title,author\n,baz,bar\n,foo,bar\n{book_id},\"foo, updated\",bar\n,missing author\n"
+    );
+    let response = import(&app, body, "text/csv", None).await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body["dry_run"], false);
+    assert_eq!(response.body["created"], 1);
+    assert_eq!(response.body["updated"], 1);
+    assert_eq!(response.body["skipped"], 1);
+    assert_eq!(response.body["failed"], 1);
+    assert_eq!(response.body["errors"][0]["row"], 4);
+    assert_eq!(response.body["errors"][0]["code"], 422);
+
+    let response = fetch_one(&app, &book_id).await;
+    assert_eq!(response.body["title"], "foo, updated");
+
+    let response = fetch_all(&app, None).await;
+    assert_eq!(response.body["total"], 2);
+}
+
+#[tokio::test]
+async fn test_api_import_books_ndjson_dry_run() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let body = [
+        serde_json::json!({ "title": "foo", "author": "bar" }).to_string(),
+        serde_json::json!({ "title": "baz", "author": "bar" }).to_string(),
+        serde_json::json!({ "title": "foo", "author": "bar" }).to_string(),
+    ]
+    .join("\n");
+    let response = import(&app, body, "application/x-ndjson", Some("dry_run=true")).await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body["dry_run"], true);
+    assert_eq!(response.body["created"], 2);
+    assert_eq!(response.body["skipped"], 1);
+    assert_eq!(response.body["failed"], 0);
+
+    let response = fetch_all(&app, None).await;
+    assert_eq!(response.body["total"], 0);
+}
+
+#[tokio::test]
+async fn test_api_import_books_chunks() {
+    let app: TestApp = TestAppBuilder::new()
+        .await
+        .with_config(Config {
+            import_chunk_size: 2,
+            ..Default::default()
+        })
+        .build();
+
+    let body = [
+        serde_json::json!({ "title": "foo", "author": "bar" }).to_string(),
+        serde_json::json!({ "title": "a".repeat(64 * 1024), "author": "bar" }).to_string(),
+        serde_json::json!({ "title": "baz", "author": "bar" }).to_string(),
+        serde_json::json!({ "title": "qux", "author": "bar" }).to_string(),
+        serde_json::json!({ "title": "quux", "author": "bar" }).to_string(),
+    ]
+    .join("\n");
+    let response = import(&app, body, "application/x-ndjson", None).await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body["created"], 4);
+    assert_eq!(response.body["failed"], 1);
+    assert_eq!(response.body["errors"][0]["row"], 2);
+    assert_eq!(response.body["errors"][0]["code"], 413);
+    assert_eq!(
+        response.body["chunks"],
+        serde_json::json!([
+            { "first_row": 1, "last_row": 2 },
+            { "first_row": 3, "last_row": 4 },
+            { "first_row": 5, "last_row": 5 },
+        ])
+    );
+
+    let response = fetch_all(&app, None).await;
+    assert_eq!(response.body["total"], 4);
+}
+
+#[tokio::test]
+async fn test_api_import_books_dry_run_across_chunks() {
+    let app: TestApp = TestAppBuilder::new()
+        .await
+        .with_config(Config {
+            import_chunk_size: 1,
+            ..Default::default()
+        })
+        .build();
+
+    let id = Uuid::new_v4().to_string();
+    let body = [
+        serde_json::json!({ "id": id, "title": "foo", "author": "bar" }).to_string(),
+        // Duplicates of the book created by the previous chunk
+        serde_json::json!({ "id": Uuid::new_v4(), "title": "foo", "author": "bar" }).to_string(),
+        serde_json::json!({ "title": "foo", "author": "bar" }).to_string(),
+        serde_json::json!({ "id": id, "title": "baz", "author": "bar" }).to_string(),
+    ]
+    .join("\n");
+
+    // The dry run reports what the import does
+    let mut reports = vec![];
+    for params in ["dry_run=true", "dry_run=false"] {
+        let response = import(&app, body.clone(), "application/x-ndjson", Some(params)).await;
+        assert_eq!(response.status_code, StatusCode::OK);
+        assert_eq!(response.body["created"], 1);
+        assert_eq!(response.body["updated"], 1);
+        assert_eq!(response.body["skipped"], 1);
+        assert_eq!(response.body["failed"], 1);
+        assert_eq!(response.body["errors"][0]["row"], 2);
+        assert_eq!(response.body["chunks"].as_array().unwrap().len(), 4);
+        reports.push(response.body);
+
+        let response = fetch_all(&app, None).await;
+        assert_eq!(response.body["total"], reports.len() - 1);
+    }
+    assert_eq!(reports[0]["errors"], reports[1]["errors"]);
+}
+
+#[tokio::test]
+async fn test_api_import_books_unsupported_content_type() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = import(&app, "[]".to_owned(), "application/json", None).await;
+    assert_eq!(response.status_code, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+#[tokio::test]
+async fn test_api_export_books_ndjson() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    for i in 0..3 {
+        create(
+            &app,
+            serde_json::json!({
+                "title": format!("foo-{i}"),
+                "author": "bar",
+            })
+            .to_string(),
+        )
+        .await;
+    }
+
+    let response = export(&app, None, &[]).await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(
+        response.headers.get("content-type"),
+        Some(&String::from("application/x-ndjson"))
+    );
+    assert!(
+        response
+            .headers
+            .get("content-disposition")
+            .unwrap()
+            .starts_with("attachment; filename=\"books-")
+    );
+
+    let body = String::from_utf8(response.raw_body).unwrap();
+    let lines = body.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3);
+    for (i, line) in lines.iter().enumerate() {
+        let book: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert_eq!(book["title"], format!("foo-{i}"));
+    }
+}
+
+#[tokio::test]
+async fn test_api_export_books_csv() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    create(
+        &app,
+        serde_json::json!({
+            "title": "foo, the book",
+            "author": "=bar",
+        })
+        .to_string(),
+    )
+    .await;
+
+    let response = export(
+        &app,
+        None,
+        &[("Accept", "application/x-ndjson;q=0.5, text/csv")],
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    let body = String::from_utf8(response.raw_body).unwrap();
+    let lines = body.lines().collect::<Vec<_>>();
+    assert_eq!(
+        lines[0],
+        "id,title,author,created_at,updated_at,version,deleted_at"
+    );
+    // Cells which would be evaluated as formulas are escaped
+    assert!(lines[1].contains(",\"foo, the book\",'=bar,"));
+
+    let response = export(&app, Some("format=ndjson"), &[("Accept", "text/csv")]).await;
+    assert_eq!(
+        response.headers.get("content-type"),
+        Some(&String::from("application/x-ndjson"))
+    );
+}
+
+#[tokio::test]
+async fn test_api_export_books_gzip() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    create(
+        &app,
+        serde_json::json!({
+            "title": "foo",
+            "author": "bar",
+        })
+        .to_string(),
+    )
+    .await;
+
+    let response = export(&app, None, &[("Accept-Encoding", "gzip")]).await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(
+        response.headers.get("content-encoding"),
+        Some(&String::from("gzip"))
+    );
+    assert_eq!(response.raw_body[..2], [0x1f, 0x8b]);
+}
+
+#[tokio::test]
+async fn test_api_export_books_not_acceptable() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = export(&app, None, &[("Accept", "text/html")]).await;
+    assert_eq!(response.status_code, StatusCode::NOT_ACCEPTABLE);
 }
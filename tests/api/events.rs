@@ -0,0 +1,163 @@
+use super::helpers::{
+    book::{TestBook, create, delete, update},
+    events::TestEventStream,
+};
+use crate::helper::{TestApp, TestAppBuilder};
+use axum::http::StatusCode;
+use book_api::config::Config;
+use serde_json::{Value, json};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn app_config() -> Config {
+    Config {
+        event_buffer_size: 10,
+        ..Default::default()
+    }
+}
+
+/// Split an event ID into the epoch of the event bus and the event number
+fn split_id(id: Option<&str>) -> (&str, &str) {
+    id.and_then(|id| id.rsplit_once('-')).unwrap()
+}
+
+async fn create_book(app: &TestApp, title: &str) -> TestBook {
+    let response = create(app, json!({"title": title, "author": "bar"}).to_string()).await;
+    assert_eq!(response.status_code, StatusCode::CREATED);
+
+    TestBook::from_body(&String::from_utf8(response.raw_body).unwrap())
+}
+
+#[tokio::test]
+async fn test_api_events_live() {
+    let app: TestApp = TestAppBuilder::new()
+        .await
+        .with_config(app_config())
+        .build();
+
+    let mut events = TestEventStream::open(&app, None).await;
+    assert_eq!(events.status_code, StatusCode::OK);
+    assert!(events.content_type.starts_with("text/event-stream"));
+
+    let book = create_book(&app, "foo").await;
+    let response = update(
+        &app,
+        json!({"title": "foo 2", "author": "bar"}).to_string(),
+        &book.id,
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    let response = delete(&app, &book.id).await;
+    assert_eq!(response.status_code, StatusCode::NO_CONTENT);
+
+    let mut epochs = vec![];
+    for (id, operation, title) in [
+        ("1", "create", "foo"),
+        ("2", "update", "foo 2"),
+        ("3", "delete", "foo 2"),
+    ] {
+        let event = events.next(TIMEOUT).await;
+        let (epoch, number) = split_id(event.id.as_deref());
+        assert_eq!(number, id);
+        epochs.push(epoch.to_owned());
+        assert_eq!(event.event.as_deref(), Some(operation));
+
+        let data: Value = serde_json::from_str(&event.data).unwrap();
+        assert_eq!(data["book_id"], book.id.as_str());
+        assert_eq!(data["operation"], operation);
+        assert_eq!(data["title"], title);
+    }
+    assert!(epochs.iter().all(|epoch| *epoch == epochs[0]));
+}
+
+#[tokio::test]
+async fn test_api_events_resume_from_last_event_id() {
+    let app: TestApp = TestAppBuilder::new()
+        .await
+        .with_config(app_config())
+        .build();
+
+    let mut events = TestEventStream::open(&app, None).await;
+    for title in ["foo-1", "foo-2", "foo-3"] {
+        create_book(&app, title).await;
+    }
+    let first_id = events.next(TIMEOUT).await.id.unwrap();
+    let (epoch, _) = split_id(Some(&first_id));
+
+    let mut events = TestEventStream::open(&app, Some(&first_id)).await;
+    create_book(&app, "foo-4").await;
+
+    for (id, title) in [("2", "foo-2"), ("3", "foo-3"), ("4", "foo-4")] {
+        let event = events.next(TIMEOUT).await;
+        assert_eq!(event.id, Some(format!("{epoch}-{id}")));
+
+        let data: Value = serde_json::from_str(&event.data).unwrap();
+        assert_eq!(data["title"], title);
+    }
+}
+
+#[tokio::test]
+async fn test_api_events_resync_when_not_buffered() {
+    let app: TestApp = TestAppBuilder::new()
+        .await
+        .with_config(Config {
+            event_buffer_size: 1,
+            ..Default::default()
+        })
+        .build();
+
+    for title in ["foo-1", "foo-2", "foo-3"] {
+        create_book(&app, title).await;
+    }
+
+    // The IDs of another epoch (e.g. before a restart), or without epoch, are resynced
+    for last_event_id in ["1", "00000000-3"] {
+        let mut events = TestEventStream::open(&app, Some(last_event_id)).await;
+        let event = events.next(TIMEOUT).await;
+        assert_eq!(event.event.as_deref(), Some("resync"));
+        assert_eq!(split_id(event.id.as_deref()).1, "3");
+    }
+
+    let mut events = TestEventStream::open(&app, Some("1")).await;
+    let event = events.next(TIMEOUT).await;
+    let (epoch, _) = split_id(event.id.as_deref());
+    let epoch = epoch.to_owned();
+
+    create_book(&app, "foo-4").await;
+    let event = events.next(TIMEOUT).await;
+    assert_eq!(event.id, Some(format!("{epoch}-4")));
+
+    // Events which are no longer buffered are resynced
+    let mut events = TestEventStream::open(&app, Some(&format!("{epoch}-1"))).await;
+    let event = events.next(TIMEOUT).await;
+    assert_eq!(event.event.as_deref(), Some("resync"));
+    assert_eq!(event.id, Some(format!("{epoch}-4")));
+}
+
+#[tokio::test]
+async fn test_api_events_invalid_last_event_id() {
+    let app: TestApp = TestAppBuilder::new()
+        .await
+        .with_config(app_config())
+        .build();
+
+    let events = TestEventStream::open(&app, Some("foo")).await;
+    assert_eq!(events.status_code, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_api_events_keep_alive() {
+    let app: TestApp = TestAppBuilder::new()
+        .await
+        .with_config(Config {
+            event_keep_alive_interval: 1,
+            ..Default::default()
+        })
+        .build();
+
+    let mut events = TestEventStream::open(&app, None).await;
+    let event = events.next(TIMEOUT).await;
+    assert_eq!(event.comment.as_deref(), Some("keep-alive"));
+    assert_eq!(event.id, None);
+}
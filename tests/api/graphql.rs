@@ -0,0 +1,201 @@
+use super::helpers::{book::create, graphql::execute};
+use crate::helper::{TestApp, TestAppBuilder};
+use axum::http::StatusCode;
+use book_api::config::Config;
+use serde_json::{Value, json};
+
+/// Create a book with the REST API and return its ID
+async fn create_book(app: &TestApp, title: &str, author: &str) -> String {
+    let response = create(
+        app,
+        json!({
+            "title": title,
+            "author": author,
+        })
+        .to_string(),
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::CREATED);
+
+    response.body["id"].as_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn test_graphql_book_by_id() {
+    let app: TestApp = TestAppBuilder::new().await.with_graphql().build();
+    let id = create_book(&app, "foo", "bar").await;
+
+    let query = "query ($id: ID!) { book(id: $id) { id title author version updatedAt } }";
+    let response = execute(&app, query, json!({ "id": id })).await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(
+        response.body["data"]["book"],
+        json!({
+            "id": id,
+            "title": "foo",
+            "author": "bar",
+            "version": 1,
+            "updatedAt": null,
+        })
+    );
+
+    let response = execute(
+        &app,
+        query,
+        json!({ "id": "9b6b9c41-1ad3-4e0e-9b8b-4a58e7b0e2c3" }),
+    )
+    .await;
+    assert_eq!(response.body["data"]["book"], Value::Null);
+
+    let response = execute(&app, query, json!({ "id": "not_an_uuid" })).await;
+    assert_eq!(response.body["errors"][0]["extensions"]["code"], 400);
+}
+
+#[tokio::test]
+async fn test_graphql_books_with_revisions() {
+    let app: TestApp = TestAppBuilder::new().await.with_graphql().build();
+    for i in 0..3 {
+        create_book(&app, &format!("Foo {i}"), "bar").await;
+    }
+    create_book(&app, "baz", "qux").await;
+
+    let query = r#"
+        {
+            books(page: 1, limit: 2, sort: "-title", filter: { title: "foo" }) {
+                total
+                data { title revisions { revision operation actor } }
+            }
+        }
+    "#;
+    let response = execute(&app, query, Value::Null).await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(
+        response.body["data"]["books"],
+        json!({
+            "total": 3,
+            "data": [
+                {
+                    "title": "Foo 2",
+                    "revisions": [{ "revision": 1, "operation": "CREATE", "actor": "anonymous" }],
+                },
+                {
+                    "title": "Foo 1",
+                    "revisions": [{ "revision": 1, "operation": "CREATE", "actor": "anonymous" }],
+                },
+            ],
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_graphql_mutations() {
+    let app: TestApp = TestAppBuilder::new().await.with_graphql().build();
+
+    let response = execute(
+        &app,
+        "mutation { createBook(input: { title: \"foo\", author: \"bar\" }) { id version } }",
+        Value::Null,
+    )
+    .await;
+    let id = response.body["data"]["createBook"]["id"]
+        .as_str()
+        .unwrap()
+        .to_owned();
+    assert_eq!(response.body["data"]["createBook"]["version"], 1);
+
+    let update = r#"
+        mutation ($id: ID!, $version: Int) {
+            updateBook(id: $id, input: { title: "foo 2", author: "bar" }, version: $version) {
+                title version
+            }
+        }
+    "#;
+    let response = execute(&app, update, json!({ "id": id, "version": 1 })).await;
+    assert_eq!(
+        response.body["data"]["updateBook"],
+        json!({ "title": "foo 2", "version": 2 })
+    );
+
+    // Stale version
+    let response = execute(&app, update, json!({ "id": id, "version": 1 })).await;
+    assert_eq!(response.body["errors"][0]["extensions"]["code"], 412);
+
+    let response = execute(
+        &app,
+        "mutation ($id: ID!) { revertBook(id: $id, revision: 1) { title version } }",
+        json!({ "id": id }),
+    )
+    .await;
+    assert_eq!(
+        response.body["data"]["revertBook"],
+        json!({ "title": "foo", "version": 3 })
+    );
+
+    let response = execute(
+        &app,
+        "mutation ($id: ID!) { deleteBook(id: $id) }",
+        json!({ "id": id }),
+    )
+    .await;
+    assert_eq!(response.body["data"]["deleteBook"], true);
+
+    let response = execute(
+        &app,
+        "mutation ($id: ID!) { restoreBook(id: $id) { version deletedAt } }",
+        json!({ "id": id }),
+    )
+    .await;
+    assert_eq!(
+        response.body["data"]["restoreBook"],
+        json!({ "version": 5, "deletedAt": null })
+    );
+
+    let response = execute(
+        &app,
+        "mutation ($id: ID!) { restoreBook(id: $id) { version } }",
+        json!({ "id": id }),
+    )
+    .await;
+    assert_eq!(response.body["errors"][0]["extensions"]["code"], 404);
+}
+
+#[tokio::test]
+async fn test_graphql_limits() {
+    let app: TestApp = TestAppBuilder::new()
+        .await
+        .with_config(Config {
+            graphql_max_depth: 3,
+            graphql_max_complexity: 100,
+            ..Default::default()
+        })
+        .with_graphql()
+        .build();
+
+    let response = execute(&app, "{ books(limit: 10) { total } }", Value::Null).await;
+    assert_eq!(response.body["data"]["books"]["total"], 0);
+
+    // Too deep
+    let response = execute(
+        &app,
+        "{ books(limit: 10) { data { revisions { revision } } } }",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(response.body["data"], Value::Null);
+    assert!(
+        response.body["errors"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("nested too deep")
+    );
+
+    // Too complex: every book of the default page counts
+    let response = execute(&app, "{ books { data { id title } } }", Value::Null).await;
+    assert_eq!(response.body["data"], Value::Null);
+    assert!(
+        response.body["errors"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("too complex")
+    );
+}
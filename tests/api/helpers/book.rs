@@ -25,6 +25,11 @@ pub async fn create(app: &TestApp, body: String) -> TestResponse {
     TestResponse::new(app, "/api/v1/book", "POST", Some(body)).await
 }
 
+/// Batch operations request helper
+pub async fn batch(app: &TestApp, body: String) -> TestResponse {
+    TestResponse::new(app, "/api/v1/book/batch", "POST", Some(body)).await
+}
+
 /// Return all processing activities
 pub async fn fetch_all(app: &TestApp, params: Option<&str>) -> TestResponse {
     TestResponse::new(
@@ -36,17 +41,126 @@ pub async fn fetch_all(app: &TestApp, params: Option<&str>) -> TestResponse {
     .await
 }
 
+/// Return all processing activities with custom request headers
+pub async fn fetch_all_with_headers(
+    app: &TestApp,
+    params: Option<&str>,
+    headers: &[(&str, &str)],
+) -> TestResponse {
+    TestResponse::with_headers(
+        app,
+        &format!("/api/v1/book?{}", params.unwrap_or_default(),),
+        "GET",
+        None,
+        headers,
+    )
+    .await
+}
+
 /// Return a processing book
 pub async fn fetch_one(app: &TestApp, id: &str) -> TestResponse {
     TestResponse::new(app, &format!("/api/v1/book/{id}"), "GET", None).await
 }
 
+/// Return a processing book with custom request headers
+pub async fn fetch_one_with_headers(
+    app: &TestApp,
+    id: &str,
+    headers: &[(&str, &str)],
+) -> TestResponse {
+    TestResponse::with_headers(app, &format!("/api/v1/book/{id}"), "GET", None, headers).await
+}
+
 /// Update a processing book
 pub async fn update(app: &TestApp, body: String, id: &str) -> TestResponse {
     TestResponse::new(app, &format!("/api/v1/book/{id}"), "PUT", Some(body)).await
 }
 
+/// Update a processing book with an `If-Match` header
+pub async fn update_if_match(
+    app: &TestApp,
+    body: String,
+    id: &str,
+    if_match: &str,
+) -> TestResponse {
+    TestResponse::with_headers(
+        app,
+        &format!("/api/v1/book/{id}"),
+        "PUT",
+        Some(body),
+        &[("Content-Type", "application/json"), ("If-Match", if_match)],
+    )
+    .await
+}
+
+/// Partially update a processing book
+pub async fn patch(app: &TestApp, body: String, id: &str, content_type: &str) -> TestResponse {
+    TestResponse::with_headers(
+        app,
+        &format!("/api/v1/book/{id}"),
+        "PATCH",
+        Some(body),
+        &[("Content-Type", content_type)],
+    )
+    .await
+}
+
 /// Delete a processing book
 pub async fn delete(app: &TestApp, id: &str) -> TestResponse {
     TestResponse::new(app, &format!("/api/v1/book/{id}"), "DELETE", None).await
 }
+
+/// Return deleted books
+pub async fn fetch_trash(app: &TestApp) -> TestResponse {
+    TestResponse::new(app, "/api/v1/book/trash", "GET", None).await
+}
+
+/// Restore a deleted book
+pub async fn restore(app: &TestApp, id: &str) -> TestResponse {
+    TestResponse::new(app, &format!("/api/v1/book/{id}/restore"), "POST", None).await
+}
+
+/// Return the revision history of a book
+pub async fn fetch_history(app: &TestApp, id: &str) -> TestResponse {
+    TestResponse::new(app, &format!("/api/v1/book/{id}/history"), "GET", None).await
+}
+
+/// Revert a book to a previous revision
+pub async fn revert(app: &TestApp, id: &str, revision: i64) -> TestResponse {
+    TestResponse::new(
+        app,
+        &format!("/api/v1/book/{id}/revert/{revision}"),
+        "POST",
+        None,
+    )
+    .await
+}
+
+/// Import books from a CSV or NDJSON body
+pub async fn import(
+    app: &TestApp,
+    body: String,
+    content_type: &str,
+    params: Option<&str>,
+) -> TestResponse {
+    TestResponse::with_headers(
+        app,
+        &format!("/api/v1/book/import?{}", params.unwrap_or_default()),
+        "POST",
+        Some(body),
+        &[("Content-Type", content_type)],
+    )
+    .await
+}
+
+/// Export all books
+pub async fn export(app: &TestApp, params: Option<&str>, headers: &[(&str, &str)]) -> TestResponse {
+    TestResponse::with_headers(
+        app,
+        &format!("/api/v1/book/export?{}", params.unwrap_or_default()),
+        "GET",
+        None,
+        headers,
+    )
+    .await
+}
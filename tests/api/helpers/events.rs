@@ -0,0 +1,88 @@
+//! Helpers for the book event stream tests
+
+use crate::helper::TestApp;
+use axum::{
+    body::{Body, BodyDataStream},
+    http::{Request, StatusCode},
+};
+use futures::StreamExt;
+use std::time::Duration;
+use tower::ServiceExt;
+
+/// Open event stream, read frame by frame
+pub struct TestEventStream {
+    pub status_code: StatusCode,
+    pub content_type: String,
+    body: BodyDataStream,
+    buffer: String,
+}
+
+/// Frame of an event stream
+#[derive(Debug, Default, PartialEq)]
+pub struct TestEvent {
+    pub id: Option<String>,
+    pub event: Option<String>,
+    pub data: String,
+    pub comment: Option<String>,
+}
+
+impl TestEventStream {
+    /// Open the event stream, with an optional `Last-Event-ID` header
+    pub async fn open(app: &TestApp, last_event_id: Option<&str>) -> Self {
+        let mut request = Request::builder().uri("/api/v1/book/events");
+        if let Some(id) = last_event_id {
+            request = request.header("Last-Event-ID", id);
+        }
+
+        let response = app
+            .router
+            .clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        Self {
+            status_code: response.status(),
+            content_type: response
+                .headers()
+                .get("content-type")
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_owned(),
+            body: response.into_body().into_data_stream(),
+            buffer: String::new(),
+        }
+    }
+
+    /// Wait for the next frame, which must come within `timeout`
+    pub async fn next(&mut self, timeout: Duration) -> TestEvent {
+        loop {
+            if let Some(end) = self.buffer.find("\n\n") {
+                let frame: String = self.buffer.drain(..end + 2).collect();
+                return parse_frame(&frame);
+            }
+
+            let chunk = tokio::time::timeout(timeout, self.body.next())
+                .await
+                .expect("no event received in time")
+                .expect("event stream closed")
+                .unwrap();
+            self.buffer.push_str(std::str::from_utf8(&chunk).unwrap());
+        }
+    }
+}
+
+fn parse_frame(frame: &str) -> TestEvent {
+    let mut event = TestEvent::default();
+    for line in frame.lines() {
+        match line.split_once(':') {
+            Some(("", value)) => event.comment = Some(value.trim().to_owned()),
+            Some(("id", value)) => event.id = Some(value.trim().to_owned()),
+            Some(("event", value)) => event.event = Some(value.trim().to_owned()),
+            Some(("data", value)) => event.data.push_str(value.trim()),
+            _ => {}
+        }
+    }
+
+    event
+}
@@ -0,0 +1,22 @@
+//! Helpers for GraphQL API tests
+
+use super::TestResponse;
+use crate::helper::TestApp;
+use serde_json::Value;
+
+/// GraphQL request helper
+pub async fn execute(app: &TestApp, query: &str, variables: Value) -> TestResponse {
+    TestResponse::new(
+        app,
+        "/graphql",
+        "POST",
+        Some(
+            serde_json::json!({
+                "query": query,
+                "variables": variables,
+            })
+            .to_string(),
+        ),
+    )
+    .await
+}
@@ -1,4 +1,7 @@
 pub mod book;
+pub mod events;
+pub mod graphql;
+pub mod webhook;
 
 use crate::helper::TestApp;
 use axum::{
@@ -15,16 +18,35 @@ use tower::ServiceExt;
 pub struct TestResponse {
     pub status_code: StatusCode,
     pub body: Value,
-    pub _headers: HashMap<String, String>,
+    pub raw_body: Vec<u8>,
+    pub headers: HashMap<String, String>,
 }
 
 impl TestResponse {
     /// Create a new `TestResponse`
     pub async fn new(app: &TestApp, url: &str, method: &str, body: Option<String>) -> Self {
-        let request = Request::builder()
-            .uri(url)
-            .method(method)
-            .header("Content-Type", "application/json");
+        Self::with_headers(
+            app,
+            url,
+            method,
+            body,
+            &[("Content-Type", "application/json")],
+        )
+        .await
+    }
+
+    /// Create a new `TestResponse` with custom request headers
+    pub async fn with_headers(
+        app: &TestApp,
+        url: &str,
+        method: &str,
+        body: Option<String>,
+        headers: &[(&str, &str)],
+    ) -> Self {
+        let mut request = Request::builder().uri(url).method(method);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
 
         let request = request.body(match body {
             None => Body::empty(),
@@ -34,15 +56,27 @@ impl TestResponse {
         let response = app.router.clone().oneshot(request.unwrap()).await.unwrap();
 
         let status_code = response.status();
+        let headers = response
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                (
+                    name.to_string(),
+                    value.to_str().unwrap_or_default().to_string(),
+                )
+            })
+            .collect();
         let body = to_bytes(response.into_body(), usize::MAX)
             .await
             .expect("failed to convert body into bytes");
+        let raw_body = body.to_vec();
         let body: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
 
         TestResponse {
             status_code,
             body,
-            _headers: HashMap::new(),
+            raw_body,
+            headers,
         }
     }
 }
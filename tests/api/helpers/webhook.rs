@@ -0,0 +1,122 @@
+//! Helpers for webhook tests
+
+use super::TestResponse;
+use crate::helper::TestApp;
+use axum::{
+    Router,
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    routing::post,
+};
+use serde_json::Value;
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+/// Webhook creation request helper
+pub async fn create(app: &TestApp, body: String) -> TestResponse {
+    TestResponse::new(app, "/api/v1/webhook", "POST", Some(body)).await
+}
+
+/// Webhook deliveries request helper
+pub async fn deliveries(app: &TestApp, id: &str, params: Option<&str>) -> TestResponse {
+    TestResponse::new(
+        app,
+        &format!(
+            "/api/v1/webhook/{id}/deliveries?{}",
+            params.unwrap_or_default()
+        ),
+        "GET",
+        None,
+    )
+    .await
+}
+
+/// Poll the deliveries of a webhook until `done` returns `true` for the first page
+pub async fn wait_for_deliveries(app: &TestApp, id: &str, done: impl Fn(&Value) -> bool) -> Value {
+    for _ in 0..100 {
+        let response = deliveries(app, id, None).await;
+        assert_eq!(response.status_code, StatusCode::OK);
+        if done(&response.body) {
+            return response.body;
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    panic!("deliveries not done in time");
+}
+
+/// Request received by the stand-in receiver
+#[derive(Debug, Clone)]
+pub struct TestReceivedRequest {
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+#[derive(Clone, Default)]
+struct TestReceiverState {
+    requests: Arc<Mutex<Vec<TestReceivedRequest>>>,
+    failures: Arc<AtomicUsize>,
+}
+
+/// Local HTTP receiver standing in for a partner endpoint
+pub struct TestReceiver {
+    pub url: String,
+    state: TestReceiverState,
+}
+
+impl TestReceiver {
+    /// Start a receiver which answers `500` to the first `failures` requests, then `204`
+    pub async fn start(failures: usize) -> Self {
+        let state = TestReceiverState::default();
+        state.failures.store(failures, Ordering::SeqCst);
+
+        let router = Router::new()
+            .route("/hook", post(receive))
+            .with_state(state.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, router).into_future());
+
+        Self {
+            url: format!("http://{addr}/hook"),
+            state,
+        }
+    }
+
+    pub fn requests(&self) -> Vec<TestReceivedRequest> {
+        self.state.requests.lock().unwrap().clone()
+    }
+
+    /// Change the number of requests to fail from now on
+    pub fn set_failures(&self, failures: usize) {
+        self.state.failures.store(failures, Ordering::SeqCst);
+    }
+}
+
+async fn receive(
+    State(state): State<TestReceiverState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    state
+        .requests
+        .lock()
+        .unwrap()
+        .push(TestReceivedRequest { headers, body });
+
+    match state
+        .failures
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |failures| {
+            failures.checked_sub(1)
+        }) {
+        Ok(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        Err(_) => StatusCode::NO_CONTENT,
+    }
+}
@@ -0,0 +1,210 @@
+use super::helpers::{
+    TestResponse,
+    book::{create, fetch_all_with_headers, fetch_one_with_headers},
+};
+use crate::helper::{TestApp, TestAppBuilder};
+use axum::http::StatusCode;
+use serde_json::{Value, json};
+
+const JSON_API: &str = "application/vnd.api+json";
+
+async fn add_book(app: &TestApp, title: &str) -> String {
+    let response = create(app, json!({"title": title, "author": "bar"}).to_string()).await;
+    assert_eq!(response.status_code, StatusCode::CREATED);
+    response.body["id"].as_str().unwrap().to_owned()
+}
+
+async fn send(app: &TestApp, url: &str, method: &str, body: Option<Value>) -> TestResponse {
+    TestResponse::with_headers(
+        app,
+        url,
+        method,
+        body.map(|body| body.to_string()),
+        &[("Accept", JSON_API), ("Content-Type", JSON_API)],
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_api_json_api_list() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+    for title in ["a", "b", "c"] {
+        add_book(&app, title).await;
+    }
+
+    let response = fetch_all_with_headers(
+        &app,
+        Some("page[number]=2&page[size]=1&s=-title"),
+        &[("Accept", JSON_API)],
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(
+        response.headers.get("content-type"),
+        Some(&String::from(JSON_API))
+    );
+    assert_eq!(response.body["meta"], json!({"total": 3}));
+
+    let data = response.body["data"].as_array().unwrap();
+    assert_eq!(data.len(), 1);
+    assert_eq!(data[0]["type"], "books");
+    assert_eq!(data[0]["attributes"]["title"], "b");
+    assert_eq!(data[0]["attributes"].get("id"), None);
+    assert_eq!(
+        data[0]["links"]["self"],
+        format!("/api/v1/book/{}", data[0]["id"].as_str().unwrap())
+    );
+
+    let links = &response.body["links"];
+    assert_eq!(
+        links["next"],
+        "/api/v1/book?s=-title&page%5Bnumber%5D=3&page%5Bsize%5D=1"
+    );
+    assert_eq!(
+        links["prev"],
+        "/api/v1/book?s=-title&page%5Bnumber%5D=1&page%5Bsize%5D=1"
+    );
+    assert_eq!(links["last"], links["next"]);
+
+    // The ID identifies the resources even when it is not selected
+    let response = TestResponse::with_headers(
+        &app,
+        "/api/v2/book?fields=title",
+        "GET",
+        None,
+        &[("Accept", JSON_API)],
+    )
+    .await;
+    assert_eq!(response.body["meta"]["total"], 3);
+    assert!(response.body["data"][0]["id"].is_string());
+    assert_eq!(response.body["links"]["next"], Value::Null);
+}
+
+#[tokio::test]
+async fn test_api_json_api_resource() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = send(
+        &app,
+        "/api/v2/book",
+        "POST",
+        Some(json!({"data": {"type": "books", "attributes": {"title": "foo", "author": "bar"}}})),
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::CREATED);
+    let id = response.body["data"]["id"].as_str().unwrap().to_owned();
+    assert_eq!(response.body["data"]["attributes"]["title"], "foo");
+    assert_eq!(response.body["links"]["self"], format!("/api/v2/book/{id}"));
+
+    let response = send(
+        &app,
+        &format!("/api/v2/book/{id}"),
+        "PATCH",
+        Some(json!({"data": {"type": "books", "id": id, "attributes": {"title": "baz"}}})),
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body["data"]["attributes"]["title"], "baz");
+
+    let response = send(
+        &app,
+        &format!("/api/book/{id}?include=revisions"),
+        "GET",
+        None,
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(
+        response.body["data"]["links"]["self"],
+        format!("/api/book/{id}")
+    );
+    assert_eq!(
+        response.body["data"]["relationships"]["revisions"]["data"][1],
+        json!({"type": "revisions", "id": format!("{id}-2")})
+    );
+    assert_eq!(response.body["included"][1]["attributes"]["title"], "baz");
+}
+
+#[tokio::test]
+async fn test_api_json_api_errors() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+
+    let response = fetch_one_with_headers(
+        &app,
+        "00000000-0000-0000-0000-000000000000",
+        &[("Accept", JSON_API)],
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::NOT_FOUND);
+    assert_eq!(
+        response.headers.get("content-type"),
+        Some(&String::from(JSON_API))
+    );
+    assert_eq!(response.body["errors"][0]["status"], "404");
+    assert_eq!(response.body["errors"][0]["code"], "not-found");
+    assert_eq!(
+        response.body["errors"][0]["detail"],
+        "book could not be found"
+    );
+
+    let response = send(
+        &app,
+        "/api/v2/book",
+        "POST",
+        Some(json!({"data": {"type": "books", "attributes": {"title": "foo"}}})),
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response.body["errors"][0]["code"], "required");
+    assert_eq!(
+        response.body["errors"][0]["source"]["pointer"],
+        "/data/attributes/author"
+    );
+
+    let response = send(
+        &app,
+        "/api/v2/book",
+        "POST",
+        Some(json!({"title": "foo", "author": "bar"})),
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["errors"][0]["code"], "bad-request");
+}
+
+#[tokio::test]
+async fn test_api_json_api_not_offered() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+    let id = add_book(&app, "foo").await;
+
+    let response = send(&app, "/api/v2/webhook", "GET", None).await;
+    assert_eq!(response.status_code, StatusCode::NOT_ACCEPTABLE);
+    assert_eq!(response.body["errors"][0]["status"], "406");
+
+    let response = send(&app, &format!("/api/v2/book/{id}/history"), "GET", None).await;
+    assert_eq!(response.status_code, StatusCode::NOT_ACCEPTABLE);
+
+    // The other acceptable formats are negotiated instead
+    let response = TestResponse::with_headers(
+        &app,
+        &format!("/api/v2/book/{id}/history"),
+        "GET",
+        None,
+        &[("Accept", "application/vnd.api+json, application/json;q=0.5")],
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body[0]["revision"], 1);
+}
+
+#[tokio::test]
+async fn test_api_json_api_plain_json_unchanged() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+    add_book(&app, "foo").await;
+
+    let response = fetch_all_with_headers(&app, Some("page[size]=1"), &[]).await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body["total"], 1);
+    assert_eq!(response.body["data"][0]["title"], "foo");
+    assert_eq!(response.body.get("links"), None);
+}
@@ -1,2 +1,7 @@
 mod book;
-mod helpers;
+mod events;
+mod graphql;
+pub mod helpers;
+mod jsonapi;
+mod versioning;
+mod webhook;
@@ -0,0 +1,134 @@
+use super::helpers::{TestResponse, book::create};
+use crate::helper::{TestApp, TestAppBuilder};
+use axum::http::StatusCode;
+use book_api::config::Config;
+use serde_json::json;
+
+async fn add_book(app: &TestApp) {
+    let response = create(app, json!({"title": "foo", "author": "bar"}).to_string()).await;
+    assert_eq!(response.status_code, StatusCode::CREATED);
+}
+
+async fn fetch_all(app: &TestApp, url: &str, accept: &str) -> TestResponse {
+    TestResponse::with_headers(app, url, "GET", None, &[("Accept", accept)]).await
+}
+
+#[tokio::test]
+async fn test_api_versions_by_path() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+    add_book(&app).await;
+
+    let response = fetch_all(&app, "/api/v1/book", "application/json").await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body["total"], 1);
+    assert_eq!(response.body.get("meta"), None);
+    assert_eq!(response.headers.get("deprecation"), None);
+
+    let response = fetch_all(&app, "/api/v2/book", "application/json").await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body["meta"]["total"], 1);
+    assert_eq!(response.body.get("total"), None);
+    assert_eq!(response.body["data"][0]["title"], "foo");
+
+    let response = TestResponse::new(
+        &app,
+        "/api/v2/book",
+        "POST",
+        Some(json!({"title": "other", "author": "bar"}).to_string()),
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::CREATED);
+    assert_eq!(
+        response.headers.get("location"),
+        Some(&format!(
+            "/api/v2/book/{}",
+            response.body["id"].as_str().unwrap()
+        ))
+    );
+}
+
+#[tokio::test]
+async fn test_api_versions_by_accept() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+    add_book(&app).await;
+
+    let response = fetch_all(&app, "/api/book", "application/json; version=1").await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body["total"], 1);
+    assert_eq!(response.headers.get("vary"), Some(&String::from("accept")));
+
+    // The latest version is served without version parameter
+    let response = fetch_all(&app, "/api/book", "application/json").await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body["meta"]["total"], 1);
+
+    let response = fetch_all(&app, "/api/book", "application/json; version=3").await;
+    assert_eq!(response.status_code, StatusCode::NOT_ACCEPTABLE);
+    assert_eq!(
+        response.body["detail"],
+        "API version 3 is not supported, supported versions are: 1, 2"
+    );
+    assert_eq!(response.headers.get("vary"), Some(&String::from("accept")));
+}
+
+#[tokio::test]
+async fn test_api_versions_deprecation() {
+    let app: TestApp = TestAppBuilder::new()
+        .await
+        .with_config(Config {
+            api_v1_deprecation: String::from("2026-01-01T00:00:00Z"),
+            api_v1_sunset: String::from("2027-01-01T00:00:00Z"),
+            ..Default::default()
+        })
+        .build();
+
+    let response = fetch_all(&app, "/api/v1/book?l=5", "application/json").await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(
+        response.headers.get("deprecation"),
+        Some(&String::from("@1767225600"))
+    );
+    assert_eq!(
+        response.headers.get("sunset"),
+        Some(&String::from("Fri, 01 Jan 2027 00:00:00 GMT"))
+    );
+    assert_eq!(
+        response.headers.get("link"),
+        Some(&String::from("</api/v2/book>; rel=\"successor-version\""))
+    );
+
+    let response = fetch_all(&app, "/api/book", "application/json; version=1").await;
+    assert_eq!(
+        response.headers.get("deprecation"),
+        Some(&String::from("@1767225600"))
+    );
+    assert_eq!(response.headers.get("link"), None);
+
+    let response = fetch_all(&app, "/api/v2/book", "application/json").await;
+    assert_eq!(response.headers.get("deprecation"), None);
+    assert_eq!(response.headers.get("sunset"), None);
+}
+
+#[tokio::test]
+async fn test_api_versions_legacy_error_format() {
+    let app: TestApp = TestAppBuilder::new()
+        .await
+        .with_config(Config {
+            legacy_error_format: true,
+            ..Default::default()
+        })
+        .build();
+
+    let response = fetch_all(&app, "/api/v1/book/abc", "application/json").await;
+    assert_eq!(response.status_code, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["code"], 400);
+
+    // Errors are always problem details from v2
+    let response = fetch_all(&app, "/api/v2/book/abc", "application/json").await;
+    assert_eq!(response.status_code, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["type"], "/problems/bad-request");
+    assert_eq!(
+        response.headers.get("content-type"),
+        Some(&String::from("application/problem+json"))
+    );
+}
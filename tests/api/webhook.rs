@@ -0,0 +1,310 @@
+use super::helpers::{
+    TestResponse,
+    book::{TestBook, create as create_book, update as update_book},
+    webhook::{TestReceiver, create, deliveries, wait_for_deliveries},
+};
+use crate::helper::{TestApp, TestAppBuilder};
+use axum::http::StatusCode;
+use book_api::config::Config;
+use hmac::{Hmac, KeyInit, Mac};
+use serde_json::{Value, json};
+use sha2::Sha256;
+
+const SECRET: &str = "0123456789abcdef";
+
+async fn webhook_app(max_attempts: u32) -> TestApp {
+    TestAppBuilder::new()
+        .await
+        .with_config(Config {
+            webhook_max_attempts: max_attempts,
+            webhook_retry_base_delay: 0,
+            webhook_allowed_hosts: String::from("127.0.0.1"),
+            ..Default::default()
+        })
+        .with_webhooks()
+        .build()
+}
+
+/// Application accepting webhooks which target the local host
+async fn local_app() -> TestApp {
+    TestAppBuilder::new()
+        .await
+        .with_config(Config {
+            webhook_allowed_hosts: String::from("localhost"),
+            ..Default::default()
+        })
+        .build()
+}
+
+async fn create_webhook(app: &TestApp, url: &str, event_types: &[&str]) -> String {
+    let response = create(
+        app,
+        json!({"url": url, "secret": SECRET, "event_types": event_types}).to_string(),
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::CREATED);
+    let id = response.body["id"].as_str().unwrap().to_owned();
+    assert_eq!(
+        response.headers.get("location"),
+        Some(&format!("/api/v1/webhook/{id}"))
+    );
+
+    id
+}
+
+async fn add_book(app: &TestApp, title: &str) -> TestBook {
+    let response = create_book(app, json!({"title": title, "author": "bar"}).to_string()).await;
+
+    TestBook::from_body(&String::from_utf8(response.raw_body).unwrap())
+}
+
+#[tokio::test]
+async fn test_api_webhook_crud() {
+    let app = local_app().await;
+
+    let id = create_webhook(&app, "http://localhost/hook", &["create", "delete"]).await;
+
+    let response = TestResponse::new(&app, &format!("/api/v1/webhook/{id}"), "GET", None).await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body["url"], "http://localhost/hook");
+    assert_eq!(response.body["event_types"], json!(["create", "delete"]));
+    // The secret is never returned
+    assert_eq!(response.body.get("secret"), None);
+
+    let response = TestResponse::new(
+        &app,
+        &format!("/api/v1/webhook/{id}"),
+        "PUT",
+        Some(json!({"url": "http://localhost/other", "secret": SECRET}).to_string()),
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body["url"], "http://localhost/other");
+    assert_eq!(response.body["event_types"], json!([]));
+
+    let response = TestResponse::new(&app, "/api/v1/webhook", "GET", None).await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body.as_array().unwrap().len(), 1);
+
+    let response = TestResponse::new(&app, &format!("/api/v1/webhook/{id}"), "DELETE", None).await;
+    assert_eq!(response.status_code, StatusCode::NO_CONTENT);
+
+    let response = TestResponse::new(&app, &format!("/api/v1/webhook/{id}"), "GET", None).await;
+    assert_eq!(response.status_code, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_api_webhook_invalid() {
+    let app = local_app().await;
+
+    for (body, field, code) in [
+        (json!({"url": "not an url", "secret": SECRET}), "url", "url"),
+        (
+            json!({"url": "http://localhost/hook", "secret": "short"}),
+            "secret",
+            "length",
+        ),
+        (
+            json!({"url": "http://localhost/hook", "secret": SECRET, "event_types": ["purge"]}),
+            "event_types",
+            "event_types",
+        ),
+        (
+            json!({"url": "ftp://localhost/hook", "secret": SECRET}),
+            "url",
+            "target",
+        ),
+        (
+            json!({"url": "http://169.254.169.254/latest/meta-data", "secret": SECRET}),
+            "url",
+            "target",
+        ),
+        (
+            json!({"url": "http://[::1]/hook", "secret": SECRET}),
+            "url",
+            "target",
+        ),
+        (
+            json!({"url": "http://10.0.0.1/hook", "secret": SECRET}),
+            "url",
+            "target",
+        ),
+    ] {
+        let response = create(&app, body.to_string()).await;
+        assert_eq!(response.status_code, StatusCode::BAD_REQUEST);
+        assert_eq!(response.body["errors"].as_array().unwrap().len(), 1);
+        assert_eq!(response.body["errors"][0]["field"], field);
+        assert_eq!(response.body["errors"][0]["code"], code);
+    }
+
+    let response = create(
+        &app,
+        json!({"url": "not an url", "secret": "short"}).to_string(),
+    )
+    .await;
+    assert_eq!(
+        response.body["errors"],
+        json!([
+            {
+                "field": "secret",
+                "code": "length",
+                "message": "length must be between 16 and 255",
+                "params": {"min": 16, "max": 255},
+            },
+            {
+                "field": "url",
+                "code": "url",
+                "message": "must be a valid URL",
+                "params": {},
+            },
+        ])
+    );
+}
+
+#[tokio::test]
+async fn test_api_webhook_signed_delivery() {
+    let app = webhook_app(3).await;
+    let receiver = TestReceiver::start(0).await;
+    let id = create_webhook(&app, &receiver.url, &["create"]).await;
+
+    let book = add_book(&app, "foo").await;
+    // Updates are not subscribed to
+    update_book(
+        &app,
+        json!({"title": "foo 2", "author": "bar"}).to_string(),
+        &book.id,
+    )
+    .await;
+
+    let body =
+        wait_for_deliveries(&app, &id, |body| body["data"][0]["status"] == "succeeded").await;
+    assert_eq!(body["total"], 1);
+    let delivery = &body["data"][0];
+    assert_eq!(delivery["event_type"], "create");
+    assert_eq!(delivery["attempts"], 1);
+    assert_eq!(delivery["log"][0]["attempt"], 1);
+    assert_eq!(delivery["log"][0]["status_code"], 204);
+    assert_eq!(delivery["log"][0]["error"], Value::Null);
+
+    let requests = receiver.requests();
+    assert_eq!(requests.len(), 1);
+    let request = &requests[0];
+    let header = |name| request.headers.get(name).unwrap().to_str().unwrap();
+    assert_eq!(header("content-type"), "application/json");
+    assert_eq!(header("x-webhook-event"), "create");
+    assert_eq!(header("x-webhook-id"), delivery["id"].to_string());
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+    mac.update(format!("{}.", header("x-webhook-timestamp")).as_bytes());
+    mac.update(&request.body);
+    let expected = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    assert_eq!(header("x-webhook-signature"), format!("sha256={expected}"));
+
+    let payload: Value = serde_json::from_slice(&request.body).unwrap();
+    assert_eq!(payload["type"], "create");
+    assert_eq!(payload["event_id"], 1);
+    assert_eq!(payload["data"]["book_id"], book.id.as_str());
+    assert_eq!(payload["data"]["title"], "foo");
+}
+
+#[tokio::test]
+async fn test_api_webhook_retried_until_success() {
+    let app = webhook_app(5).await;
+    let receiver = TestReceiver::start(2).await;
+    let id = create_webhook(&app, &receiver.url, &[]).await;
+
+    add_book(&app, "foo").await;
+
+    let body =
+        wait_for_deliveries(&app, &id, |body| body["data"][0]["status"] == "succeeded").await;
+    let delivery = &body["data"][0];
+    assert_eq!(delivery["attempts"], 3);
+    let status_codes = delivery["log"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|attempt| attempt["status_code"].as_i64().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(status_codes, [500, 500, 204]);
+    assert_eq!(receiver.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn test_api_webhook_dead_letter_and_retry() {
+    let app = webhook_app(3).await;
+    let receiver = TestReceiver::start(usize::MAX).await;
+    let id = create_webhook(&app, &receiver.url, &[]).await;
+
+    add_book(&app, "foo").await;
+
+    let body = wait_for_deliveries(&app, &id, |body| body["data"][0]["status"] == "dead").await;
+    let delivery = &body["data"][0];
+    assert_eq!(delivery["attempts"], 3);
+    assert_eq!(delivery["log"].as_array().unwrap().len(), 3);
+    assert_eq!(
+        delivery["log"][2]["error"],
+        "unexpected status 500 Internal Server Error"
+    );
+    assert_eq!(receiver.requests().len(), 3);
+
+    let response = deliveries(&app, &id, Some("status=pending")).await;
+    assert_eq!(response.body["total"], 0);
+    let response = deliveries(&app, &id, Some("status=dead")).await;
+    assert_eq!(response.body["total"], 1);
+
+    // A dead delivery can be retried manually
+    receiver.set_failures(0);
+    let delivery_id = delivery["id"].as_i64().unwrap();
+    let response = TestResponse::new(
+        &app,
+        &format!("/api/v1/webhook/{id}/deliveries/{delivery_id}/retry"),
+        "POST",
+        None,
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::OK);
+    assert_eq!(response.body["status"], "pending");
+
+    let body =
+        wait_for_deliveries(&app, &id, |body| body["data"][0]["status"] == "succeeded").await;
+    assert_eq!(body["data"][0]["log"].as_array().unwrap().len(), 4);
+
+    // Only dead deliveries can be retried
+    let response = TestResponse::new(
+        &app,
+        &format!("/api/v1/webhook/{id}/deliveries/{delivery_id}/retry"),
+        "POST",
+        None,
+    )
+    .await;
+    assert_eq!(response.status_code, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_api_webhook_delete_referenced() {
+    let app = local_app().await;
+    let pool = app._database.database().await;
+
+    // Table which references the webhooks without cascading their deletion
+    sqlx::query(
+        "CREATE TABLE webhook_owner (webhook_id varchar(36) NOT NULL REFERENCES webhook (id))",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    let id = create_webhook(&app, "http://localhost/hook", &[]).await;
+    sqlx::query("INSERT INTO webhook_owner VALUES ($1)")
+        .bind(&id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let response = TestResponse::new(&app, &format!("/api/v1/webhook/{id}"), "DELETE", None).await;
+    assert_eq!(response.status_code, StatusCode::CONFLICT);
+    assert_eq!(response.body["constraint"], "webhook_owner_webhook_id_fkey");
+}
@@ -0,0 +1,186 @@
+use crate::helper::TestGrpcServer;
+use book_api::{
+    config::Config,
+    grpc::proto::{
+        CreateBookRequest, DeleteBookRequest, GetBookRequest, ListAllBooksRequest,
+        ListBooksRequest, UpdateBookRequest,
+    },
+    repositories::revision::BookRevisionRepository,
+};
+use futures::TryStreamExt;
+use tonic::{Code, Request};
+
+fn create_request(title: &str, author: &str) -> CreateBookRequest {
+    CreateBookRequest {
+        title: title.to_owned(),
+        author: author.to_owned(),
+    }
+}
+
+#[tokio::test]
+async fn test_grpc_create_get_update_delete() {
+    let mut server = TestGrpcServer::new(Config::default()).await;
+    let client = &mut server.client;
+
+    let book = client
+        .create(create_request("foo", "bar"))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(book.title, "foo");
+    assert_eq!(book.version, 1);
+    assert!(book.created_at.is_some());
+    assert!(book.updated_at.is_none());
+
+    let found = client
+        .get(GetBookRequest {
+            id: book.id.clone(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(found.id, book.id);
+    assert_eq!(found.title, "foo");
+    assert_eq!(found.version, 1);
+
+    let updated = client
+        .update(UpdateBookRequest {
+            id: book.id.clone(),
+            title: "foo 2".to_owned(),
+            author: "bar".to_owned(),
+            version: Some(1),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(updated.title, "foo 2");
+    assert_eq!(updated.version, 2);
+    assert!(updated.updated_at.is_some());
+
+    let status = client
+        .update(UpdateBookRequest {
+            id: book.id.clone(),
+            title: "foo 3".to_owned(),
+            author: "bar".to_owned(),
+            version: Some(1),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+
+    client
+        .delete(DeleteBookRequest {
+            id: book.id.clone(),
+            version: None,
+        })
+        .await
+        .unwrap();
+
+    let status = client
+        .get(GetBookRequest { id: book.id })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn test_grpc_invalid_id() {
+    let mut server = TestGrpcServer::new(Config::default()).await;
+
+    let status = server
+        .client
+        .get(GetBookRequest {
+            id: "not_an_uuid".to_owned(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn test_grpc_precondition_required() {
+    let mut server = TestGrpcServer::new(Config {
+        precondition_required: true,
+        ..Default::default()
+    })
+    .await;
+    let client = &mut server.client;
+
+    let book = client
+        .create(create_request("foo", "bar"))
+        .await
+        .unwrap()
+        .into_inner();
+
+    let status = client
+        .delete(DeleteBookRequest {
+            id: book.id,
+            version: None,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+}
+
+#[tokio::test]
+async fn test_grpc_invalid_actor() {
+    let mut server = TestGrpcServer::new(Config::default()).await;
+
+    let mut request = Request::new(create_request("foo", "bar"));
+    request
+        .metadata_mut()
+        .insert("x-actor", "a".repeat(256).parse().unwrap());
+    let status = server.client.create(request).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert!(status.message().contains("`X-Actor`"));
+}
+
+#[tokio::test]
+async fn test_grpc_list_and_list_all() {
+    let mut server = TestGrpcServer::new(Config::default()).await;
+    let client = &mut server.client;
+
+    for i in 0..3 {
+        let mut request = Request::new(create_request(&format!("foo-{i}"), "bar"));
+        request
+            .metadata_mut()
+            .insert("x-actor", "grpc-test".parse().unwrap());
+        client.create(request).await.unwrap();
+    }
+
+    let page = client
+        .list(ListBooksRequest {
+            page: Some(2),
+            limit: Some(2),
+            sort: Some("+title".to_owned()),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(page.total, 3);
+    assert_eq!(page.books.len(), 1);
+    assert_eq!(page.books[0].title, "foo-2");
+
+    let books: Vec<_> = client
+        .list_all(ListAllBooksRequest {})
+        .await
+        .unwrap()
+        .into_inner()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(
+        books
+            .iter()
+            .map(|book| book.title.as_str())
+            .collect::<Vec<_>>(),
+        ["foo-0", "foo-1", "foo-2"]
+    );
+
+    // The actor metadata is recorded with the revisions
+    let pool = server._database.database().await;
+    let revisions = BookRevisionRepository::get_all(&pool, books[0].id.clone())
+        .await
+        .unwrap();
+    assert_eq!(revisions[0].actor, "grpc-test");
+}
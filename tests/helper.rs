@@ -1,14 +1,19 @@
 //! Test helper for unit tests
 
-use axum::{Extension, Router};
+use axum::{Extension, Router, middleware};
 use book_api::{
-    config::logger,
-    layers::{self, MakeRequestUuid},
-    routes,
+    API_BASE_PATH, ApiVersion,
+    config::{Config, logger},
+    events::EventBus,
+    grpc::{self, proto::book_service_client::BookServiceClient},
+    layers::{self, MakeRequestUuid, idempotency::IdempotencyLayer, versioning::ApiVersioning},
+    outbox::OutboxRelay,
+    routes, tasks,
 };
 use rand::distr::{Alphanumeric, SampleString};
 use sqlx::{Connection, PgConnection, PgPool, Postgres, postgres::PgPoolOptions};
-use std::time::Duration;
+use std::{net::SocketAddr, time::Duration};
+use tonic::transport::{Channel, Server, server::TcpIncoming};
 use tower::ServiceBuilder;
 use tower_http::ServiceBuilderExt;
 
@@ -20,22 +25,40 @@ pub struct TestApp {
 pub struct TestAppBuilder {
     router: Router,
     database: TestDatabase,
+    config: Config,
+    webhooks: bool,
 }
 
 impl TestAppBuilder {
     pub async fn new() -> Self {
         let db = TestDatabase::new().await;
 
-        let mut router = Router::new().nest("/api/v1/book", routes::api());
+        let mut router = Router::new().nest(API_BASE_PATH, routes::api());
+        for version in ApiVersion::ALL {
+            router = router.nest(&version.prefix(), routes::api());
+        }
         router = router.merge(routes::web());
-        router = router.layer(Extension(db.database().await));
+        router = router.merge(routes::ws());
+        router = router
+            .layer(middleware::from_fn(layers::override_http_errors))
+            .layer(Extension(db.database().await))
+            .layer(middleware::from_fn(
+                layers::negotiation::content_negotiation,
+            ));
 
         Self {
             router,
             database: db,
+            config: Config::default(),
+            webhooks: false,
         }
     }
 
+    #[allow(unused)]
+    pub fn with_config(self, config: Config) -> Self {
+        Self { config, ..self }
+    }
+
     #[allow(unused)]
     pub fn with_logger(self) -> Self {
         logger::init("test").unwrap();
@@ -46,18 +69,124 @@ impl TestAppBuilder {
 
         Self {
             router: self.router.layer(layers),
-            database: self.database,
+            ..self
+        }
+    }
+
+    #[allow(unused)]
+    pub fn with_idempotency(self, ttl: Duration, lock_timeout: Duration) -> Self {
+        let layer = IdempotencyLayer::new(self.database.pool.clone(), ttl, lock_timeout);
+
+        Self {
+            router: self.router.layer(layer),
+            ..self
+        }
+    }
+
+    /// Serve the GraphQL API, with the limits of the configuration set before
+    #[allow(unused)]
+    pub fn with_graphql(self) -> Self {
+        let graphql = routes::graphql(&self.config).layer(Extension(self.database.pool.clone()));
+
+        Self {
+            router: self.router.merge(graphql),
+            ..self
+        }
+    }
+
+    /// Deliver the webhooks, with the attempts of the configuration set before
+    #[allow(unused)]
+    pub fn with_webhooks(self) -> Self {
+        Self {
+            webhooks: true,
+            ..self
         }
     }
 
     pub fn build(self) -> TestApp {
+        let events = EventBus::new(self.config.event_buffer_size);
+        if self.webhooks {
+            let (enqueuer, _) = tasks::webhook::spawn_delivery(
+                self.database.pool.clone(),
+                self.config.webhook_max_attempts,
+                Duration::from_secs(self.config.webhook_retry_base_delay),
+                &self.config.webhook_allowed_hosts,
+            );
+            tasks::outbox::spawn_relay(
+                OutboxRelay::new(self.database.pool.clone()).with_consumer(enqueuer),
+                events.clone(),
+                Duration::from_secs(1),
+            );
+        }
+
         TestApp {
-            router: self.router,
+            router: self
+                .router
+                .layer(Extension(events))
+                .layer(middleware::from_fn_with_state(
+                    ApiVersioning::new(&self.config).unwrap(),
+                    layers::versioning::api_version,
+                ))
+                .layer(middleware::from_fn_with_state(
+                    self.config.legacy_error_format,
+                    layers::problem::error_context,
+                ))
+                .layer(Extension(self.config)),
             _database: self.database,
         }
     }
 }
 
+impl TestApp {
+    /// Pool of the test database
+    #[allow(unused)]
+    pub async fn pool(&self) -> PgPool {
+        self._database.database().await
+    }
+
+    /// Serve the application on a random local port, for the clients which need a real connection
+    #[allow(unused)]
+    pub async fn serve(&self) -> SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, self.router.clone()).into_future());
+
+        addr
+    }
+}
+
+/// gRPC server running on a random local port
+pub struct TestGrpcServer {
+    pub client: BookServiceClient<Channel>,
+    pub _database: TestDatabase,
+}
+
+impl TestGrpcServer {
+    #[allow(unused)]
+    pub async fn new(config: Config) -> Self {
+        let database = TestDatabase::new().await;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let events = EventBus::new(config.event_buffer_size);
+        let service = grpc::service(database.database().await, &config, events);
+        tokio::spawn(
+            Server::builder()
+                .add_service(service)
+                .serve_with_incoming(TcpIncoming::from(listener)),
+        );
+
+        let client = BookServiceClient::connect(format!("http://{addr}"))
+            .await
+            .unwrap();
+
+        Self {
+            client,
+            _database: database,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct TestDatabase {
     url: String,
@@ -1,3 +1,7 @@
 mod api;
+mod grpc;
 mod helper;
+mod notifications;
+mod outbox;
 mod web;
+mod ws;
@@ -0,0 +1,179 @@
+use crate::helper::TestDatabase;
+use book_api::{
+    events::{BookEventMessage, EventBus},
+    models::{
+        book::{Book, BookCreation},
+        revision::{ChangeContext, RevisionOperation},
+    },
+    notifications::Notifications,
+    repositories::book::BookRepository,
+    tasks,
+};
+use futures::{Stream, StreamExt, pin_mut};
+use sqlx::PgPool;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Listener of an instance, with the bus it publishes to
+struct TestInstance {
+    notifications: Notifications,
+    events: EventBus,
+}
+
+impl TestInstance {
+    async fn start(pool: &PgPool) -> Self {
+        let notifications = Notifications::new();
+        let events = EventBus::new(10);
+        tasks::notifications::spawn_listener(pool.clone(), notifications.clone(), events.clone());
+
+        let instance = Self {
+            notifications,
+            events,
+        };
+        instance.wait_for_connections(1).await;
+
+        instance
+    }
+
+    async fn wait_for_connections(&self, count: u64) {
+        let mut connections = self.notifications.connections();
+        tokio::time::timeout(
+            TIMEOUT,
+            connections.wait_for(|connections| *connections >= count),
+        )
+        .await
+        .expect("listener not connected in time")
+        .unwrap();
+    }
+}
+
+async fn create_book(pool: &PgPool, title: &str) -> Book {
+    let mut book = Book::new(BookCreation {
+        title: title.to_owned(),
+        author: "Author".to_owned(),
+    });
+    BookRepository::create(pool, &mut book, &ChangeContext::default())
+        .await
+        .unwrap();
+
+    book
+}
+
+/// Wait for the next event, and return its book ID
+async fn next_book_id(stream: &mut (impl Stream<Item = BookEventMessage> + Unpin)) -> String {
+    match tokio::time::timeout(TIMEOUT, stream.next())
+        .await
+        .expect("no event received in time")
+    {
+        Some(BookEventMessage::Event(event)) => event.revision.book_id.clone(),
+        message => panic!("unexpected message {message:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_changes_published_to_all_instances() {
+    let database = TestDatabase::new().await;
+    let pool = database.database().await;
+
+    let first = TestInstance::start(&pool).await;
+    let second = TestInstance::start(&pool).await;
+    let first_stream = first.events.subscribe(None);
+    let second_stream = second.events.subscribe(None);
+    pin_mut!(first_stream, second_stream);
+    let mut notices = second.notifications.subscribe();
+
+    // The first instance publishes its change itself, before the notification
+    let book = create_book(&pool, "First").await;
+    let revision = BookRepository::delete(&pool, book.id.clone(), None, &ChangeContext::default())
+        .await
+        .unwrap()
+        .unwrap();
+    first.events.publish([revision]);
+
+    assert_eq!(next_book_id(&mut first_stream).await, book.id);
+    assert_eq!(next_book_id(&mut first_stream).await, book.id);
+    assert_eq!(next_book_id(&mut second_stream).await, book.id);
+    assert_eq!(next_book_id(&mut second_stream).await, book.id);
+
+    let notice = notices.recv().await.unwrap();
+    assert_eq!(notice.id, 1);
+    assert_eq!(notice.book_id, book.id);
+    assert_eq!(notice.operation, RevisionOperation::Create);
+    let notice = notices.recv().await.unwrap();
+    assert_eq!(notice.id, 2);
+    assert_eq!(notice.operation, RevisionOperation::Delete);
+
+    // The deletion is not published twice
+    let other = create_book(&pool, "Second").await;
+    assert_eq!(next_book_id(&mut first_stream).await, other.id);
+}
+
+/// Terminate the connection of the listener
+async fn disconnect(pool: &PgPool) {
+    let terminated: Vec<bool> = sqlx::query_scalar(
+        r#"
+            SELECT pg_terminate_backend(pid)
+            FROM pg_stat_activity
+            WHERE datname = current_database()
+                AND query LIKE 'LISTEN %'
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .unwrap();
+    assert_eq!(terminated, [true]);
+}
+
+#[tokio::test]
+async fn test_changes_replayed_after_reconnection() {
+    let database = TestDatabase::new().await;
+    let pool = database.database().await;
+
+    let instance = TestInstance::start(&pool).await;
+    let stream = instance.events.subscribe(None);
+    pin_mut!(stream);
+
+    disconnect(&pool).await;
+
+    let first = create_book(&pool, "First").await;
+    let second = create_book(&pool, "Second").await;
+
+    instance.wait_for_connections(2).await;
+    assert_eq!(next_book_id(&mut stream).await, first.id);
+    assert_eq!(next_book_id(&mut stream).await, second.id);
+}
+
+#[tokio::test]
+async fn test_changes_committed_out_of_order_replayed_after_reconnection() {
+    let database = TestDatabase::new().await;
+    let pool = database.database().await;
+
+    let instance = TestInstance::start(&pool).await;
+    let stream = instance.events.subscribe(None);
+    pin_mut!(stream);
+
+    // The first change takes the first outbox ID, but is committed after the second one
+    let mut transaction = pool.begin().await.unwrap();
+    let mut first = Book::new(BookCreation {
+        title: "First".to_owned(),
+        author: "Author".to_owned(),
+    });
+    BookRepository::create(&mut *transaction, &mut first, &ChangeContext::default())
+        .await
+        .unwrap();
+    let second = create_book(&pool, "Second").await;
+    assert_eq!(next_book_id(&mut stream).await, second.id);
+
+    disconnect(&pool).await;
+    transaction.commit().await.unwrap();
+
+    instance.wait_for_connections(2).await;
+    assert_eq!(next_book_id(&mut stream).await, first.id);
+
+    // The gap is closed, the change is not replayed again
+    disconnect(&pool).await;
+    instance.wait_for_connections(3).await;
+    let third = create_book(&pool, "Third").await;
+    assert_eq!(next_book_id(&mut stream).await, third.id);
+}
@@ -0,0 +1,160 @@
+use crate::helper::TestDatabase;
+use book_api::{
+    AppError, AppResult,
+    models::{
+        book::{Book, BookCreation},
+        outbox::OutboxEvent,
+        revision::{ChangeContext, RevisionOperation},
+    },
+    outbox::{OutboxConsumer, OutboxRelay},
+    repositories::{book::BookRepository, outbox::OutboxRepository},
+};
+use chrono::{TimeDelta, Utc};
+use futures::future::BoxFuture;
+use sqlx::{PgConnection, PgPool};
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, Ordering},
+};
+
+/// Consumer which records the events, or fails while `failing` is set
+#[derive(Clone, Default)]
+struct TestConsumer {
+    events: Arc<Mutex<Vec<(i64, RevisionOperation)>>>,
+    failing: Arc<AtomicBool>,
+}
+
+impl OutboxConsumer for TestConsumer {
+    fn consume<'a>(
+        &'a self,
+        _connection: &'a mut PgConnection,
+        events: &'a [OutboxEvent],
+    ) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            if self.failing.load(Ordering::SeqCst) {
+                return Err(AppError::InternalError {
+                    message: "consumer failure".to_owned(),
+                });
+            }
+
+            self.events.lock().unwrap().extend(
+                events
+                    .iter()
+                    .map(|event| (event.id, event.revision.operation)),
+            );
+
+            Ok(())
+        })
+    }
+}
+
+async fn create_book(pool: &PgPool) -> Book {
+    let mut book = Book::new(BookCreation {
+        title: "Title".to_owned(),
+        author: "Author".to_owned(),
+    });
+    BookRepository::create(pool, &mut book, &ChangeContext::default())
+        .await
+        .unwrap();
+
+    book
+}
+
+#[tokio::test]
+async fn test_outbox_written_in_book_transaction() {
+    let database = TestDatabase::new().await;
+    let pool = database.database().await;
+
+    let mut transaction = pool.begin().await.unwrap();
+    let mut book = Book::new(BookCreation {
+        title: "Title".to_owned(),
+        author: "Author".to_owned(),
+    });
+    BookRepository::create(&mut *transaction, &mut book, &ChangeContext::default())
+        .await
+        .unwrap();
+    transaction.rollback().await.unwrap();
+    assert_eq!(OutboxRepository::count_pending(&pool).await.unwrap(), 0);
+
+    create_book(&pool).await;
+    assert_eq!(OutboxRepository::count_pending(&pool).await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_outbox_relayed_in_order() {
+    let database = TestDatabase::new().await;
+    let pool = database.database().await;
+
+    let book = create_book(&pool).await;
+    BookRepository::delete(&pool, book.id.clone(), None, &ChangeContext::default())
+        .await
+        .unwrap();
+    create_book(&pool).await;
+
+    let consumer = TestConsumer::default();
+    let relay = OutboxRelay::new(pool.clone()).with_consumer(consumer.clone());
+
+    assert_eq!(relay.relay(2).await.unwrap(), 2);
+    assert_eq!(relay.relay(2).await.unwrap(), 1);
+    assert_eq!(relay.relay(2).await.unwrap(), 0);
+
+    assert_eq!(
+        *consumer.events.lock().unwrap(),
+        vec![
+            (1, RevisionOperation::Create),
+            (2, RevisionOperation::Delete),
+            (3, RevisionOperation::Create),
+        ]
+    );
+    assert_eq!(OutboxRepository::count_pending(&pool).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_outbox_redelivered_after_failure() {
+    let database = TestDatabase::new().await;
+    let pool = database.database().await;
+
+    create_book(&pool).await;
+
+    let recorder = TestConsumer::default();
+    let failing = TestConsumer::default();
+    failing.failing.store(true, Ordering::SeqCst);
+    let relay = OutboxRelay::new(pool.clone())
+        .with_consumer(recorder.clone())
+        .with_consumer(failing.clone());
+
+    assert!(relay.relay(10).await.is_err());
+    assert_eq!(OutboxRepository::count_pending(&pool).await.unwrap(), 1);
+
+    failing.failing.store(false, Ordering::SeqCst);
+    assert_eq!(relay.relay(10).await.unwrap(), 1);
+    assert_eq!(OutboxRepository::count_pending(&pool).await.unwrap(), 0);
+
+    // The consumer which succeeded is handed the event again
+    assert_eq!(
+        *recorder.events.lock().unwrap(),
+        vec![
+            (1, RevisionOperation::Create),
+            (1, RevisionOperation::Create)
+        ]
+    );
+    assert_eq!(
+        *failing.events.lock().unwrap(),
+        vec![(1, RevisionOperation::Create)]
+    );
+}
+
+#[tokio::test]
+async fn test_outbox_purge() {
+    let database = TestDatabase::new().await;
+    let pool = database.database().await;
+
+    create_book(&pool).await;
+    let later = Utc::now() + TimeDelta::seconds(1);
+
+    // The pending changes are only purged without relay
+    assert_eq!(OutboxRepository::purge(&pool, later, false).await, Ok(0));
+    assert_eq!(OutboxRepository::count_pending(&pool).await.unwrap(), 1);
+    assert_eq!(OutboxRepository::purge(&pool, later, true).await, Ok(1));
+    assert_eq!(OutboxRepository::count_pending(&pool).await.unwrap(), 0);
+}
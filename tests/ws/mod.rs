@@ -0,0 +1,216 @@
+use crate::{
+    api::helpers::book::{TestBook, create, update},
+    helper::{TestApp, TestAppBuilder},
+};
+use book_api::config::Config;
+use futures::{SinkExt, StreamExt};
+use serde_json::{Value, json};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async, tungstenite::Message};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+type TestSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn connect(app: &TestApp) -> TestSocket {
+    let addr = app.serve().await;
+    let (socket, _) = connect_async(format!("ws://{addr}/ws")).await.unwrap();
+
+    socket
+}
+
+async fn send(socket: &mut TestSocket, message: Value) {
+    socket
+        .send(Message::Text(message.to_string().into()))
+        .await
+        .unwrap();
+}
+
+/// Wait for the next message, which must be JSON text
+async fn receive(socket: &mut TestSocket) -> Value {
+    loop {
+        let message = tokio::time::timeout(TIMEOUT, socket.next())
+            .await
+            .expect("no message received in time")
+            .expect("connection closed")
+            .unwrap();
+        match message {
+            Message::Text(text) => return serde_json::from_str(&text).unwrap(),
+            Message::Ping(_) | Message::Pong(_) => continue,
+            message => panic!("unexpected message: {message:?}"),
+        }
+    }
+}
+
+async fn create_book(app: &TestApp, title: &str, author: &str) -> TestBook {
+    let response = create(app, json!({"title": title, "author": author}).to_string()).await;
+
+    TestBook::from_body(&String::from_utf8(response.raw_body).unwrap())
+}
+
+#[tokio::test]
+async fn test_ws_subscribe_to_book() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+    let book = create_book(&app, "foo", "bar").await;
+    let mut socket = connect(&app).await;
+
+    send(
+        &mut socket,
+        json!({"type": "subscribe", "id": "book", "book_id": book.id}),
+    )
+    .await;
+    assert_eq!(
+        receive(&mut socket).await,
+        json!({"type": "ack", "id": "book"})
+    );
+
+    // Changes of the other books are not sent
+    create_book(&app, "other", "bar").await;
+    update(
+        &app,
+        json!({"title": "foo 2", "author": "bar"}).to_string(),
+        &book.id,
+    )
+    .await;
+
+    let message = receive(&mut socket).await;
+    assert_eq!(message["type"], "event");
+    assert_eq!(message["event_id"], 3);
+    assert_eq!(message["subscriptions"], json!(["book"]));
+    assert_eq!(message["data"]["book_id"], book.id.as_str());
+    assert_eq!(message["data"]["operation"], "update");
+    assert_eq!(message["data"]["title"], "foo 2");
+}
+
+#[tokio::test]
+async fn test_ws_subscribe_to_author_and_unsubscribe() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+    let mut socket = connect(&app).await;
+
+    send(
+        &mut socket,
+        json!({"type": "subscribe", "id": "tolkien", "author": "TOLKIEN"}),
+    )
+    .await;
+    assert_eq!(receive(&mut socket).await["type"], "ack");
+
+    create_book(&app, "The Hobbit", "J. R. R. Tolkien").await;
+    let message = receive(&mut socket).await;
+    assert_eq!(message["subscriptions"], json!(["tolkien"]));
+    assert_eq!(message["data"]["title"], "The Hobbit");
+
+    send(&mut socket, json!({"type": "unsubscribe", "id": "tolkien"})).await;
+    assert_eq!(
+        receive(&mut socket).await,
+        json!({"type": "ack", "id": "tolkien"})
+    );
+    send(
+        &mut socket,
+        json!({"type": "subscribe", "id": "herbert", "author": "herbert"}),
+    )
+    .await;
+    assert_eq!(receive(&mut socket).await["type"], "ack");
+
+    // Only the remaining subscription receives the changes
+    create_book(&app, "The Silmarillion", "J. R. R. Tolkien").await;
+    create_book(&app, "Dune", "Frank Herbert").await;
+    let message = receive(&mut socket).await;
+    assert_eq!(message["subscriptions"], json!(["herbert"]));
+    assert_eq!(message["data"]["title"], "Dune");
+}
+
+#[tokio::test]
+async fn test_ws_invalid_messages() {
+    let app: TestApp = TestAppBuilder::new().await.build();
+    let mut socket = connect(&app).await;
+
+    socket
+        .send(Message::Text("invalid_json".into()))
+        .await
+        .unwrap();
+    let message = receive(&mut socket).await;
+    assert_eq!(message["type"], "error");
+    assert_eq!(message["id"], Value::Null);
+
+    send(
+        &mut socket,
+        json!({
+            "type": "subscribe",
+            "id": "both",
+            "book_id": "0b8a4c3e-7b8f-4a52-9d42-5c9a6f3c1e2d",
+            "author": "bar",
+        }),
+    )
+    .await;
+    assert_eq!(
+        receive(&mut socket).await,
+        json!({
+            "type": "error",
+            "id": "both",
+            "message": "exactly one of book_id and author must be set",
+        })
+    );
+
+    send(
+        &mut socket,
+        json!({"type": "subscribe", "id": "bar", "author": "bar"}),
+    )
+    .await;
+    assert_eq!(receive(&mut socket).await["type"], "ack");
+    send(
+        &mut socket,
+        json!({"type": "subscribe", "id": "bar", "author": "baz"}),
+    )
+    .await;
+    assert_eq!(
+        receive(&mut socket).await,
+        json!({"type": "error", "id": "bar", "message": "subscription `bar` already exists"})
+    );
+
+    send(&mut socket, json!({"type": "unsubscribe", "id": "unknown"})).await;
+    assert_eq!(
+        receive(&mut socket).await,
+        json!({
+            "type": "error",
+            "id": "unknown",
+            "message": "subscription `unknown` does not exist",
+        })
+    );
+
+    socket
+        .send(Message::Binary(vec![1, 2, 3].into()))
+        .await
+        .unwrap();
+    assert_eq!(receive(&mut socket).await["type"], "error");
+}
+
+#[tokio::test]
+async fn test_ws_heartbeat() {
+    let app: TestApp = TestAppBuilder::new()
+        .await
+        .with_config(Config {
+            ws_heartbeat_interval: 1,
+            ..Default::default()
+        })
+        .build();
+    let mut socket = connect(&app).await;
+
+    let message = tokio::time::timeout(TIMEOUT, socket.next())
+        .await
+        .expect("no ping received in time")
+        .unwrap()
+        .unwrap();
+    assert!(matches!(message, Message::Ping(_)));
+    // Send the pong queued by the client
+    socket.flush().await.unwrap();
+
+    // The connection stays open while the pings are answered
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    send(
+        &mut socket,
+        json!({"type": "subscribe", "id": "bar", "author": "bar"}),
+    )
+    .await;
+    assert_eq!(receive(&mut socket).await["type"], "ack");
+}